@@ -108,6 +108,7 @@ impl ServerState {
         match &payload {
             // Unused
             Payloads::InvalidName { .. }
+            | Payloads::NameAssigned { .. }
             | Payloads::RendezvousHandshake { .. }
             | Payloads::InvalidVersion { .. }
             | Payloads::PlayerJoined { .. }
@@ -118,11 +119,24 @@ impl ServerState {
             | Payloads::RequestHosting { .. }
             | Payloads::PeerEstablished { .. }
             | Payloads::ConnectionDenied { .. }
+            | Payloads::SessionFull
+            | Payloads::SessionLocked
+            | Payloads::Banned
+            | Payloads::InvalidPassword
             | Payloads::Heartbeat
+            | Payloads::ServerVersion { .. }
             | Payloads::SetSelfObserver { .. }
+            | Payloads::DefinitionFragment { .. }
             | Payloads::PlayerLeft { .. } => return,
             // Used
-            Payloads::AircraftDefinition { .. } | Payloads::Update { .. } => {}
+            Payloads::AircraftDefinition { .. }
+            | Payloads::Motd { .. }
+            | Payloads::FlightPlan { .. }
+            | Payloads::Update { .. }
+            | Payloads::FollowPosition { .. }
+            | Payloads::SetRole { .. }
+            | Payloads::SetTag { .. }
+            | Payloads::SetAfk { .. } => {}
             Payloads::InitHandshake { name, version } => {
                 let server_version = dotenv::var("APP_VERSION").unwrap();
 
@@ -182,9 +196,23 @@ impl ServerState {
                     client.is_observer = *is_observer;
                 }
             }
-            Payloads::Ready => {
+            Payloads::Ready { .. } => {
                 // Tell "host" to do a full sync
                 if let Some(client) = self.clients.get(&self.in_control) {
+                    net.send_message(payload.clone(), client.addr).ok();
+                }
+                // The host keeps track of who has loaded in
+                if self.hoster != self.in_control {
+                    if let Some(client) = self.clients.get(&self.hoster) {
+                        net.send_message(payload, client.addr).ok();
+                    }
+                }
+
+                return;
+            }
+            Payloads::RequestDefinitions { .. } => {
+                // Only the "host" has the definitions
+                if let Some(client) = self.clients.get(&self.hoster) {
                     net.send_message(payload, client.addr).ok();
                 }
 
@@ -1,29 +1,34 @@
 use crossbeam_channel::unbounded;
 use laminar::Socket;
-use log::info;
+use log::{info, warn};
 use mem::drop;
 use spin_sleep::sleep;
 use std::sync::{
     atomic::{AtomicBool, Ordering::SeqCst},
-    Arc,
+    Arc, PoisonError,
 };
 use std::thread;
 use std::{mem, net::IpAddr, net::SocketAddr, sync::Mutex, time::Duration, time::Instant};
 
 use crate::util::{
-    get_bind_address, get_rendezvous_server, get_socket_config, match_ip_address_to_socket_addr,
+    get_bind_address, get_idle_timeout, get_rendezvous_server, get_socket_config,
+    match_ip_address_to_socket_addr,
 };
 use crate::util::{
-    ClientReceiver, ClientSender, Event, ReceiveMessage, ServerReceiver, ServerSender,
-    TransferClient,
+    get_checksum, run_watched, DEFAULT_HEARTBEAT_INTERVAL, LOOP_SLEEP_TIME_MS,
+    MAX_DEFINITION_RETRIES, MAX_PUNCH_RETRIES, PROTOCOL_VERSION, RENDEZVOUS_IDLE_TIMEOUT,
+};
+use crate::util::{
+    ClientReceiver, ClientSender, ConnectionStage, Event, ReceiveMessage, ServerReceiver,
+    ServerSender, TransferClient,
 };
-use crate::util::{HEARTBEAT_INTERVAL_MANUAL_SECS, LOOP_SLEEP_TIME_MS, MAX_PUNCH_RETRIES};
 use crate::{
-    messages::{Message, Payloads, SenderReceiver},
+    messages::{Message, NetworkSimulation, Payloads, SenderReceiver, DEFAULT_MAX_PACKET_SIZE},
+    trace::ProtocolTrace,
     util::get_local_endpoints_with_port,
 };
 
-use yourcontrols_types::Error;
+use yourcontrols_types::{Clock, Error, SystemClock};
 
 struct TransferStruct {
     name: String,
@@ -39,10 +44,27 @@ struct TransferStruct {
     connected_address: Option<SocketAddr>,
     retry_timer: Option<Instant>,
     session_id: String,
+    // Nonce of the host the rendezvous server pointed us at, None when it didn't give one
+    expected_nonce: Option<u64>,
     retries: u8,
+    // Times in a row the definitions failed their checksum
+    definition_retries: u8,
+    // Message sent to the rendezvous server, kept to register again if it loses our session
+    rendezvous_registration: Option<(Payloads, SocketAddr)>,
+    // Whether the rendezvous server answered, and the protocol version it said it has
+    heard_from_rendezvous: bool,
+    // Last time we registered or heard from the rendezvous server, and whether it gave us what we registered for
+    rendezvous_instant: Instant,
+    rendezvous_answered: bool,
+    rendezvous_version: Option<u32>,
     // State
     should_stop: Arc<AtomicBool>,
     heartbeat_instant: Instant,
+    heartbeat_interval: Duration,
+    // Once connected, give up on a host we haven't heard from for this long
+    session_timeout: Option<Duration>,
+    last_heard: Instant,
+    clock: Arc<dyn Clock>,
 }
 
 impl TransferStruct {
@@ -52,33 +74,64 @@ impl TransferStruct {
 
     // Should stop client
     fn handle_message(&mut self, addr: SocketAddr, payload: Payloads) {
+        if self.is_rendezvous(addr) {
+            self.heard_from_rendezvous = true;
+            self.rendezvous_instant = self.clock.now();
+        }
+
+        if self.connected_address == Some(addr) {
+            self.last_heard = self.clock.now();
+        }
+
         match &payload {
             // Unused by client
             Payloads::InitHandshake { .. } |
             Payloads::RendezvousHandshake  { .. } |
             Payloads::PeerEstablished { .. } |
             Payloads::RequestHosting {..} |
-            Payloads::Ready |
+            Payloads::Ready { .. } |
             Payloads::SetSelfObserver { .. }|
+            Payloads::DefinitionFragment { .. } |
+            Payloads::RequestDefinitions { .. } |
             // No futher handling required
-            Payloads::AircraftDefinition { .. } |
             Payloads::TransferControl { ..} |
             Payloads::SetObserver { .. } |
+            Payloads::SetRole { .. } |
+            Payloads::SetTag { .. } |
+            Payloads::SetAfk { .. } |
             Payloads::PlayerJoined { .. } |
             Payloads::PlayerLeft { .. } |
             Payloads::Update { .. } |
+            Payloads::FollowPosition { .. } |
             Payloads::ConnectionDenied { .. } |
+            Payloads::SessionFull |
+            Payloads::SessionLocked |
+            Payloads::Banned |
+            Payloads::InvalidPassword |
             Payloads::SetHost |
+            Payloads::Motd { .. } |
+            Payloads::FlightPlan { .. } |
             Payloads::AttemptHosterConnection {..} |
             Payloads::Heartbeat => {}
             // Used
             Payloads::InvalidVersion { server_version } => {
                 self.stop(format!("Server has mismatching version {}", server_version));
             }
+            Payloads::ServerVersion { protocol_version } => {
+                self.rendezvous_version = Some(*protocol_version);
+
+                if self.is_rendezvous_outdated() {
+                    warn!("[NETWORK] Cloud server has protocol version {}, we have {}", protocol_version, PROTOCOL_VERSION);
+                    self.stop("server_out_of_date".to_string());
+                }
+            }
             Payloads::InvalidName { .. } => {
                 self.stop(format!("{} already in use!", self.name));
             }
-            Payloads::Handshake { session_id } => {
+            Payloads::NameAssigned { name } => {
+                self.name.clone_from(name);
+            }
+            Payloads::Handshake { session_id, nonce } => {
                 // Already established connection
                 if self.connected() {return}
                 // Why doesn't the other peer have the same session ID? 
@@ -86,8 +139,15 @@ impl TransferStruct {
                     self.stop(format!("Handshake verification failed! Expected {}, got {}", self.session_id, session_id));
                     return;
                 }
+                // Another host has the same session ID, joining it would put us in a stranger's aircraft
+                if self.expected_nonce.is_some() && *nonce != self.expected_nonce {
+                    warn!("[NETWORK] Port {} answered for {} but is not the host the rendezvous server gave us", addr.port(), session_id);
+                    self.stop("session_collision".to_string());
+                    return;
+                }
                 // Established connection with host
                 self.connected_address = Some(addr);
+                self.last_heard = self.clock.now();
                 self.received_address.drain(..);
 
                 // Send initial data
@@ -102,9 +162,26 @@ impl TransferStruct {
             }
             Payloads::HostingReceived { session_id } => {
                 self.session_id.clone_from(session_id);
+                self.rendezvous_answered = true;
             }
-            Payloads::AttemptConnection { peers } => {
+            Payloads::AttemptConnection { peers, nonce } => {
+                self.rendezvous_answered = true;
                 self.received_address.clone_from(peers) ;
+                self.expected_nonce = *nonce;
+                self.send_stage(ConnectionStage::PunchingThrough);
+            }
+            Payloads::AircraftDefinition { bytes, checksum } => {
+                if get_checksum(bytes) == *checksum {
+                    self.definition_retries = 0;
+                } else if self.definition_retries < MAX_DEFINITION_RETRIES {
+                    warn!("[NETWORK] Aircraft definitions failed their checksum, requesting them again.");
+                    self.definition_retries += 1;
+                    self.net.send_message(Payloads::RequestDefinitions { from: self.name.clone() }, addr).ok();
+                    return;
+                } else {
+                    self.stop("Received corrupted aircraft definitions too many times.".to_string());
+                    return;
+                }
             }
         }
 
@@ -129,30 +206,37 @@ impl TransferStruct {
 
         // Send a message every second
         if let Some(timer) = self.retry_timer.as_ref() {
-            if timer.elapsed().as_secs() < 1 {
+            if self.clock.elapsed(*timer).as_secs() < 1 {
                 return;
             }
         }
 
-        for addr in &self.received_address {
+        // Cloned so stop can borrow self mutably inside the loop
+        let addresses = self.received_address.clone();
+        for addr in &addresses {
             self.net
                 .send_message(
                     Payloads::Handshake {
                         session_id: self.session_id.clone(),
+                        nonce: None,
                     },
                     *addr,
                 )
                 .ok();
             // Reset second timer
-            self.retry_timer = Some(Instant::now());
+            self.retry_timer = Some(self.clock.now());
             self.retries += 1;
 
             // Over retry limit, stop connection
             if self.retries == MAX_PUNCH_RETRIES {
-                self.should_stop.store(true, SeqCst);
-                self.server_tx
-                    .try_send(ReceiveMessage::Event(Event::UnablePunchthrough))
-                    .ok();
+                if self.is_rendezvous_outdated() {
+                    self.stop("server_out_of_date".to_string());
+                } else {
+                    self.should_stop.store(true, SeqCst);
+                    self.server_tx
+                        .try_send(ReceiveMessage::Event(Event::UnablePunchthrough))
+                        .ok();
+                }
             }
 
             info!(
@@ -163,6 +247,42 @@ impl TransferStruct {
         }
     }
 
+    fn register_with_rendezvous(&mut self) {
+        if let Some((payload, addr)) = self.rendezvous_registration.clone() {
+            self.rendezvous_instant = self.clock.now();
+            self.net.send_message(payload, addr).ok();
+        }
+    }
+
+    // The socket only reports the rendezvous server gone after the whole connect timeout, so check sooner ourselves
+    fn handle_rendezvous_idle(&mut self) {
+        if self.connected()
+            || self.rendezvous_answered
+            || self.rendezvous_registration.is_none()
+            || self.clock.elapsed(self.rendezvous_instant) < RENDEZVOUS_IDLE_TIMEOUT
+        {
+            return;
+        }
+
+        info!("[NETWORK] No answer from rendezvous server, registering again");
+        self.register_with_rendezvous();
+    }
+
+    // Answered without saying its version, or with an older one than ours
+    fn is_rendezvous_outdated(&self) -> bool {
+        match self.rendezvous_version {
+            Some(version) => version < PROTOCOL_VERSION,
+            None => self.heard_from_rendezvous,
+        }
+    }
+
+    fn is_rendezvous(&self, addr: SocketAddr) -> bool {
+        self.rendezvous_registration
+            .as_ref()
+            .map(|(_, x)| *x == addr)
+            .unwrap_or(false)
+    }
+
     // Reliably compared to default heartbeat implementation
     fn handle_heartbeat(&mut self) {
         if !self.connected() {
@@ -170,15 +290,30 @@ impl TransferStruct {
         }
 
         if let Some(addr) = self.connected_address {
-            if self.heartbeat_instant.elapsed().as_secs_f32() < HEARTBEAT_INTERVAL_MANUAL_SECS {
+            if self.clock.elapsed(self.heartbeat_instant) < self.heartbeat_interval {
                 return;
             }
 
-            self.heartbeat_instant = Instant::now();
+            self.heartbeat_instant = self.clock.now();
             self.net.send_message(Payloads::Heartbeat, addr).ok();
         }
     }
 
+    fn handle_session_timeout(&mut self) {
+        let timeout = match self.session_timeout {
+            Some(timeout) if self.connected() => timeout,
+            _ => return,
+        };
+
+        if self.clock.elapsed(self.last_heard) >= timeout {
+            warn!(
+                "[NETWORK] Nothing heard from the host for {:?}",
+                self.clock.elapsed(self.last_heard)
+            );
+            self.stop("Connection timeout".to_string());
+        }
+    }
+
     fn stop(&mut self, reason: String) {
         self.server_tx
             .try_send(ReceiveMessage::Event(Event::ConnectionLost(reason)))
@@ -186,6 +321,12 @@ impl TransferStruct {
         self.should_stop.store(true, SeqCst);
     }
 
+    fn send_stage(&self, stage: ConnectionStage) {
+        self.server_tx
+            .try_send(ReceiveMessage::Event(Event::ConnectionStage(stage)))
+            .ok();
+    }
+
     fn connected(&self) -> bool {
         self.connected_address.is_some()
     }
@@ -206,7 +347,14 @@ pub struct Client {
     username: String,
     version: String,
     timeout: u64,
+    session_timeout: Option<Duration>,
     is_host: bool,
+    // Address the socket actually bound to
+    local_address: Option<SocketAddr>,
+    max_packet_size: usize,
+    heartbeat_interval: Duration,
+    protocol_trace: Option<ProtocolTrace>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Client {
@@ -217,6 +365,7 @@ impl Client {
         Self {
             should_stop: Arc::new(AtomicBool::new(false)),
             timeout,
+            session_timeout: None,
             transfer: None,
             client_rx,
             client_tx,
@@ -225,13 +374,47 @@ impl Client {
             username,
             version,
             is_host: false,
+            local_address: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            protocol_trace: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    // Must be called before starting the client
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    // Must be called before starting the client
+    pub fn set_heartbeat_interval(&mut self, heartbeat_interval: Duration) {
+        self.heartbeat_interval = heartbeat_interval;
+    }
+
+    // Must be called before starting the client. The timeout given to new() then only applies while connecting.
+    pub fn set_session_timeout(&mut self, session_timeout: Duration) {
+        self.session_timeout = Some(session_timeout);
+    }
+
+    // Must be called before starting the client
+    pub fn set_protocol_trace(&mut self, protocol_trace: Option<ProtocolTrace>) {
+        self.protocol_trace = protocol_trace;
+    }
+
+    // Must be called before starting the client
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn get_local_address(&self) -> Option<SocketAddr> {
+        self.local_address
+    }
+
     fn get_socket(&self, is_ipv6: bool) -> Result<Socket, laminar::ErrorKind> {
         Socket::bind_with_config(
             get_bind_address(is_ipv6, None),
-            get_socket_config(self.timeout),
+            get_socket_config(get_idle_timeout(self.timeout, self.session_timeout)),
         )
     }
 
@@ -239,14 +422,16 @@ impl Client {
         &mut self,
         ip: IpAddr,
         port: u16,
+        scope_id: u32,              // Zone of a link-local IPv6 address, 0 otherwise
         session_id: Option<String>, // Only used when connecting to the hoster as a secret password
     ) -> Result<(), Error> {
-        self.run(
-            ip.is_ipv6(),
-            session_id,
-            None,
-            Some(match_ip_address_to_socket_addr(ip, port)),
-        )
+        let mut target = match_ip_address_to_socket_addr(ip, port);
+
+        if let SocketAddr::V6(target) = &mut target {
+            target.set_scope_id(scope_id);
+        }
+
+        self.run(ip.is_ipv6(), session_id, None, Some(target))
     }
 
     pub fn start_with_hole_punch(
@@ -275,6 +460,7 @@ impl Client {
     ) -> Result<(), Error> {
         let socket = self.get_socket(is_ipv6)?;
         let port = socket.local_addr().unwrap().port();
+        self.local_address = socket.local_addr().ok();
 
         self.is_host = session_id.is_none() && target_address.is_none();
 
@@ -291,46 +477,57 @@ impl Client {
             connected_address: None,
             retry_timer: None,
             session_id: session_id.clone().unwrap_or_default(),
+            expected_nonce: None,
+            rendezvous_registration: None,
+            heard_from_rendezvous: false,
+            rendezvous_instant: self.clock.now(),
+            rendezvous_answered: false,
+            rendezvous_version: None,
+            definition_retries: 0,
             // State
             name: self.get_server_name().to_string(),
             version: self.version.clone(),
             should_stop: self.should_stop.clone(),
-            heartbeat_instant: Instant::now(),
+            heartbeat_instant: self.clock.now(),
+            heartbeat_interval: self.heartbeat_interval,
+            session_timeout: self.session_timeout,
+            last_heard: self.clock.now(),
+            clock: self.clock.clone(),
         };
 
+        transfer.net.set_max_packet_size(self.max_packet_size);
+        transfer.net.set_trace(self.protocol_trace.clone());
+
         if let Some(rendezvous) = rendezvous {
-            if let Some(session_id) = session_id {
+            let local_endpoint = get_local_endpoints_with_port(is_ipv6, port);
+
+            let payload = if let Some(session_id) = session_id {
                 // Send a handshake to rendezvous to resolve session id with an ip address
-                transfer
-                    .net
-                    .send_message(
-                        Payloads::RendezvousHandshake {
-                            session_id,
-                            local_endpoint: get_local_endpoints_with_port(is_ipv6, port),
-                        },
-                        rendezvous,
-                    )
-                    .ok();
+                Payloads::RendezvousHandshake {
+                    session_id,
+                    local_endpoint,
+                }
             } else {
-                transfer
-                    .net
-                    .send_message(
-                        Payloads::RequestHosting {
-                            self_hosted: false,
-                            local_endpoint: get_local_endpoints_with_port(is_ipv6, port),
-                        },
-                        rendezvous,
-                    )
-                    .ok();
-            }
+                Payloads::RequestHosting {
+                    self_hosted: false,
+                    local_endpoint,
+                    nonce: None,
+                }
+            };
+
+            transfer.rendezvous_registration = Some((payload, rendezvous));
+            transfer.send_stage(ConnectionStage::ContactingRendezvous);
+            transfer.register_with_rendezvous();
         } else if let Some(addr) = target_address {
             info!("Sending request to port {} to join session", addr.port());
+            transfer.send_stage(ConnectionStage::Handshaking);
             // Send a handshake to the target address to start establishing a connection
             transfer
                 .net
                 .send_message(
                     Payloads::Handshake {
                         session_id: String::new(),
+                        nonce: None,
                     },
                     addr,
                 )
@@ -342,16 +539,29 @@ impl Client {
 
         self.transfer = Some(transfer_send);
 
-        let rendezvous_timer = Instant::now();
+        let rendezvous_timer = self.clock.now();
+        let connect_timeout = self.timeout;
+        let server_tx = self.server_tx.clone();
+        let should_stop = self.should_stop.clone();
         // Run main loop
         thread::spawn(move || {
             let sleep_duration = Duration::from_millis(LOOP_SLEEP_TIME_MS);
 
-            loop {
-                let mut transfer = transfer_thread_clone.lock().unwrap();
+            // A panic leaves the lock poisoned, the state in it is still what the loop picks back up with
+            run_watched(&server_tx, &should_stop, || loop {
+                let mut transfer = transfer_thread_clone
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
 
                 transfer.net.poll();
 
+                if let Some(congested) = transfer.net.take_congestion_change() {
+                    transfer
+                        .server_tx
+                        .try_send(ReceiveMessage::Event(Event::SendCongested(congested)))
+                        .ok();
+                }
+
                 while let Ok(message) = transfer.net.get_next_message() {
                     match message {
                         Message::Payload(addr, payload) => {
@@ -364,6 +574,10 @@ impl Client {
                                 .unwrap_or(false);
                             if was_connected_addr {
                                 transfer.stop("Connection timeout".to_string())
+                            } else if !transfer.connected() && transfer.is_rendezvous(addr) {
+                                // The rendezvous server may have restarted and forgotten our session
+                                info!("[NETWORK] Lost rendezvous server, registering again");
+                                transfer.register_with_rendezvous();
                             }
                         }
                         Message::Metrics(addr, metrics) => {
@@ -380,17 +594,23 @@ impl Client {
                     }
                 }
 
-                // Check rendezvous timer
+                // Check rendezvous timer against the configured connect timeout
                 if !transfer.connected()
                     && rendezvous.is_some()
-                    && rendezvous_timer.elapsed().as_secs() >= 5
+                    && transfer.clock.elapsed(rendezvous_timer).as_secs() >= connect_timeout
                 {
-                    transfer.stop("Could not connect to session.".to_string())
+                    if transfer.is_rendezvous_outdated() {
+                        transfer.stop("server_out_of_date".to_string())
+                    } else {
+                        transfer.stop("Could not connect to session.".to_string())
+                    }
                 }
 
+                transfer.handle_rendezvous_idle();
                 transfer.handle_handshake();
                 transfer.handle_app_message();
                 transfer.handle_heartbeat();
+                transfer.handle_session_timeout();
 
                 if transfer.should_stop() {
                     break;
@@ -398,7 +618,7 @@ impl Client {
 
                 drop(transfer);
                 sleep(sleep_duration);
-            }
+            });
         });
 
         Ok(())
@@ -428,11 +648,38 @@ impl TransferClient for Client {
 
     fn get_session_id(&self) -> Option<String> {
         if let Some(transfer) = self.transfer.as_ref() {
-            return Some(transfer.lock().unwrap().session_id.clone());
+            return Some(
+                transfer
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .session_id
+                    .clone(),
+            );
         }
         None
     }
 
+    fn set_network_simulation(&self, simulation: NetworkSimulation) {
+        if let Some(transfer) = self.transfer.as_ref() {
+            transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .net
+                .set_simulation(simulation);
+        }
+    }
+
+    fn set_server_name(&mut self, name: String) {
+        if let Some(transfer) = self.transfer.as_ref() {
+            transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .name
+                .clone_from(&name);
+        }
+        self.username = name;
+    }
+
     fn stop(&mut self, reason: String) {
         self.should_stop.store(true, SeqCst);
         self.server_tx
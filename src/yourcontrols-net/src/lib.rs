@@ -1,12 +1,15 @@
 mod client;
 mod messages;
 mod server;
+mod trace;
 mod util;
 
 pub use client::Client;
-pub use messages::{Message, Payloads, SenderReceiver};
+pub use messages::{Message, NetworkSimulation, Payloads, SenderReceiver};
 pub use server::Server;
+pub use trace::ProtocolTrace;
 pub use util::{
     get_addr_from_hostname_and_port, get_rendezvous_server, get_socket_config, get_socket_duplex,
-    is_actually_ipv4, Event, ReceiveMessage, TransferClient,
+    get_unique_name, is_actually_ipv4, ConnectionStage, Event, ReceiveMessage, TransferClient,
+    PROTOCOL_VERSION,
 };
@@ -1,26 +1,117 @@
 use crossbeam_channel::{Receiver, Sender};
 use laminar::{Metrics, Packet, Socket, SocketEvent};
+use log::{debug, info, warn};
 use rmp_serde::{self};
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, time::Instant};
-use yourcontrols_types::AllNeedSync;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use yourcontrols_types::{AllNeedSync, ClientRole};
 use zstd::bulk::{Compressor, Decompressor};
 
 use yourcontrols_types::Error;
 
+use crate::trace::{ProtocolTrace, TraceDirection};
+
+// Byte buffers go out as MessagePack bin, as an array of numbers they take up to twice the space
+mod as_bin {
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+    use std::fmt;
+
+    pub fn serialize<S, T>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        serializer.serialize_bytes(bytes.as_ref())
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<Vec<u8>>,
+    {
+        deserializer.deserialize_byte_buf(BytesVisitor).map(T::from)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a byte buffer")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        // Buffers sent as an array of numbers by older versions
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Payloads {
     InvalidName,
+    // Reasons a session can turn a joiner away
+    SessionFull,
+    SessionLocked,
+    Banned,
+    InvalidPassword,
+    // The name the server gave a client whose requested name was taken
+    NameAssigned {
+        name: String,
+    },
     InvalidVersion {
         server_version: String,
     },
     AircraftDefinition {
+        #[serde(with = "as_bin")]
+        bytes: Box<[u8]>,
+        // Of the bytes, checked by the receiver before loading them
+        checksum: u32,
+    },
+    // A received AircraftDefinition failed its checksum, asks for it to be sent again
+    RequestDefinitions {
+        from: String,
+    },
+    // The host's active flight plan, the contents of its .PLN file
+    FlightPlan {
+        name: String,
+        #[serde(with = "as_bin")]
+        bytes: Box<[u8]>,
+    },
+    // Piece of an AircraftDefinition or FlightPlan that was too big for one packet, reassembled before being handed out
+    DefinitionFragment {
+        index: u16,
+        count: u16,
+        #[serde(with = "as_bin")]
         bytes: Box<[u8]>,
     },
     SetHost,
     RequestHosting {
         self_hosted: bool,
         local_endpoint: Option<SocketAddr>,
+        // Made up by a self hosting host, handed to joiners so they can tell it apart from another host with the same session id
+        #[serde(default)]
+        nonce: Option<u64>,
     },
     ConnectionDenied {
         reason: String,
@@ -56,8 +147,35 @@ pub enum Payloads {
     SetSelfObserver {
         name: String,
     },
+    // Only sent by the host
+    SetRole {
+        from: String,
+        to: String,
+        role: ClientRole,
+    },
+    // Only sent by the host, label shown next to the client in the roster
+    SetTag {
+        from: String,
+        to: String,
+        tag: Option<String>,
+    },
+    // No local input for a while
+    SetAfk {
+        name: String,
+        is_afk: bool,
+    },
     // Ready to receive data
-    Ready,
+    Ready {
+        from: String,
+    },
+    // Where the leader is in follow mode, followers place themselves at an offset from it
+    FollowPosition {
+        from: String,
+        latitude: f64,
+        longitude: f64,
+        altitude: f64,
+        heading: f64,
+    },
     // Hole punching payloads
     RendezvousHandshake {
         session_id: String,
@@ -65,12 +183,18 @@ pub enum Payloads {
     },
     Handshake {
         session_id: String,
+        // Only sent by a self hosting host
+        #[serde(default)]
+        nonce: Option<u64>,
     }, // With hoster
     HostingReceived {
         session_id: String,
     },
     AttemptConnection {
         peers: Vec<SocketAddr>,
+        // The nonce the host gave the rendezvous server, only sent to joiners
+        #[serde(default)]
+        nonce: Option<u64>,
     },
     AttemptHosterConnection {
         peer: SocketAddr,
@@ -79,6 +203,14 @@ pub enum Payloads {
         peer: SocketAddr,
     },
     Heartbeat,
+    // Sent by the rendezvous server before answering, older ones don't send it at all
+    ServerVersion {
+        protocol_version: u32,
+    },
+    // Host's welcome message, sent to each joiner
+    Motd {
+        message: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -87,6 +219,80 @@ pub struct PayloadWrapper {
     pub size: usize, // used for decompression buffer size
 }
 
+fn prepare_payload_bytes(
+    compressor: &mut Compressor<'static>,
+    message: &Payloads,
+) -> Result<Vec<u8>, Error> {
+    // Struct to MessagePack
+    let payload_bytes = rmp_serde::to_vec(&message)?;
+
+    // Compress
+    compressor.set_compression_level(get_compression_level_for_message(message))?;
+
+    let compressed = compressor.compress(&payload_bytes)?;
+
+    // Wrap
+    let wrapper = PayloadWrapper {
+        data: compressed,
+        size: payload_bytes.len(),
+    };
+
+    // Serialize
+    Ok(rmp_serde::to_vec(&wrapper)?)
+}
+
+// Splits oversized definitions into fragments, anything else is sent as a single packet
+fn prepare_packets(
+    compressor: &mut Compressor<'static>,
+    message: &Payloads,
+    max_packet_size: usize,
+) -> Result<Vec<(Payloads, Vec<u8>)>, Error> {
+    let payload_bytes = prepare_payload_bytes(compressor, message)?;
+
+    if !matches!(
+        message,
+        Payloads::AircraftDefinition { .. } | Payloads::FlightPlan { .. }
+    ) || payload_bytes.len() <= max_packet_size
+    {
+        return Ok(vec![(message.clone(), payload_bytes)]);
+    }
+
+    let mut chunk_size = max_packet_size - FRAGMENT_OVERHEAD;
+
+    loop {
+        let chunks: Vec<&[u8]> = payload_bytes.chunks(chunk_size).collect();
+        let count = chunks.len() as u16;
+
+        let mut packets = Vec::with_capacity(chunks.len());
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let fragment = Payloads::DefinitionFragment {
+                index: index as u16,
+                count,
+                bytes: chunk.into(),
+            };
+            let fragment_bytes = prepare_payload_bytes(compressor, &fragment)?;
+
+            packets.push((fragment, fragment_bytes));
+        }
+
+        let largest = packets
+            .iter()
+            .map(|(_, bytes)| bytes.len())
+            .max()
+            .unwrap_or_default();
+
+        if largest <= max_packet_size || chunk_size == 1 {
+            return Ok(packets);
+        }
+
+        // The wrapper stores its bytes as an array of numbers, which can take up to two bytes each
+        chunk_size = (chunk_size * max_packet_size / largest)
+            .min(chunk_size - 1)
+            .max(1);
+    }
+}
+
 pub enum Message {
     Payload(SocketAddr, Payloads),
     ConnectionClosed(SocketAddr),
@@ -103,41 +309,109 @@ fn get_packet_for_message(
         Payloads::AttemptConnection {..} |
         Payloads::AttemptHosterConnection {..} |
         Payloads::HostingReceived {..} |
-        Payloads::SetHost {..} |
         Payloads::ConnectionDenied {..} |
         // Used
         Payloads::InvalidVersion {..} |
+        Payloads::ServerVersion {..} |
         Payloads::Heartbeat {..} |
         Payloads::SetSelfObserver { .. } |
+        Payloads::SessionFull |
+        Payloads::SessionLocked |
+        Payloads::Banned |
+        Payloads::InvalidPassword |
         Payloads::InvalidName {..} => Packet::reliable_unordered(target, payload_bytes),
         Payloads::PeerEstablished {..} |
         Payloads::RendezvousHandshake  {..} |
         Payloads::Handshake {..} => Packet::unreliable(target, payload_bytes),
+        // Session and control state, always applied in the order it was sent
         Payloads::InitHandshake {..} |
+        Payloads::NameAssigned {..} |
         Payloads::PlayerJoined {..} |
         Payloads::PlayerLeft {..} |
+        Payloads::SetHost {..} |
         Payloads::SetObserver {..} |
-        Payloads::Ready |
+        Payloads::SetRole {..} |
+        Payloads::SetTag {..} |
+        Payloads::SetAfk {..} |
+        Payloads::Motd {..} |
+        Payloads::Ready {..} |
         Payloads::TransferControl {..} |
-        Payloads::AircraftDefinition {..}  |
         Payloads::RequestHosting {..} => Packet::reliable_ordered(target, payload_bytes, Some(1)),
+        // Kept apart so a big definition transfer can't hold up control changes
+        Payloads::AircraftDefinition {..}  |
+        Payloads::FlightPlan {..}  |
+        Payloads::DefinitionFragment {..}  |
+        Payloads::RequestDefinitions {..} => Packet::reliable_ordered(target, payload_bytes, Some(3)),
+        Payloads::FollowPosition {..} => Packet::unreliable_sequenced(target, payload_bytes, Some(2)),
         Payloads::Update {is_unreliable, ..} => if *is_unreliable {Packet::unreliable_sequenced(target, payload_bytes, Some(0))} else {Packet::reliable_ordered(target, payload_bytes, Some(0))}
     }
 }
 
 fn get_compression_level_for_message(msg: &Payloads) -> i32 {
     match msg {
-        Payloads::AircraftDefinition { .. } => 22,
+        Payloads::AircraftDefinition { .. } | Payloads::FlightPlan { .. } => 22,
         _ => 0,
     }
 }
 
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1200;
+// Room left in each fragment for the payload, its compression frame and the wrapper around it
+const FRAGMENT_OVERHEAD: usize = 96;
+// Consecutive packets from a peer that fail to decode before giving up on it
+const MAX_CORRUPT_PACKETS: u32 = 10;
+// Packets queued between two polls before we count as sending too fast
+const MAX_PACKETS_PER_POLL: usize = 64;
+// Polls in a row over (or back under) the limit before backing off (or recovering)
+const CONGESTION_POLLS: u32 = 10;
+
+// Updates that get superseded by the next one anyway, dropped first when sending too fast
+fn can_drop_when_congested(message: &Payloads) -> bool {
+    matches!(
+        message,
+        Payloads::Update {
+            is_unreliable: true,
+            ..
+        } | Payloads::FollowPosition { .. }
+    )
+}
+
+// Artificial conditions applied to what we receive, to test against a bad connection without having one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkSimulation {
+    pub latency: Duration,
+    // Chance from 0 to 1 of an unreliable update being dropped, reliable ones can't be lost
+    pub packet_loss: f64,
+}
+
+impl NetworkSimulation {
+    pub fn is_active(&self) -> bool {
+        self.latency > Duration::ZERO || self.packet_loss > 0.0
+    }
+}
+
 pub struct SenderReceiver {
     socket: Socket,
     sender: Sender<Packet>,
     receiver: Receiver<SocketEvent>,
     compressor: Compressor<'static>,
     decompressor: Decompressor<'static>,
+    // Definitions bigger than this get split into fragments
+    max_packet_size: usize,
+    // Fragments received so far from each address
+    fragments: HashMap<SocketAddr, Vec<u8>>,
+    // Malformed packets received in a row from each address
+    corrupt_packets: HashMap<SocketAddr, u32>,
+    // Packets that could not be handed to the socket since the last poll
+    send_failures: u32,
+    // Sending too fast, unreliable updates are dropped until the backlog clears
+    congested: bool,
+    congestion_streak: u32,
+    congestion_changed: bool,
+    simulation: NetworkSimulation,
+    // Received messages held back until the simulated latency passes
+    delayed: VecDeque<(Instant, Message)>,
+    random_state: u64,
+    trace: Option<ProtocolTrace>,
 }
 
 impl SenderReceiver {
@@ -151,59 +425,235 @@ impl SenderReceiver {
             receiver,
             compressor: Compressor::new(0).unwrap(),
             decompressor: Decompressor::new().unwrap(),
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            fragments: HashMap::new(),
+            corrupt_packets: HashMap::new(),
+            send_failures: 0,
+            congested: false,
+            congestion_streak: 0,
+            congestion_changed: false,
+            simulation: NetworkSimulation::default(),
+            delayed: VecDeque::new(),
+            random_state: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_nanos() as u64)
+                .unwrap_or_default()
+                | 1,
+            trace: None,
+        }
+    }
+
+    pub fn set_trace(&mut self, trace: Option<ProtocolTrace>) {
+        self.trace = trace;
+    }
+
+    fn trace(&self, direction: TraceDirection, addr: SocketAddr, payload: &Payloads) {
+        if let Some(trace) = self.trace.as_ref() {
+            trace.write(direction, addr, payload);
+        }
+    }
+
+    pub fn set_simulation(&mut self, simulation: NetworkSimulation) {
+        if simulation.is_active() {
+            warn!(
+                "[NETWORK] Simulating {}ms latency and {}% packet loss",
+                simulation.latency.as_millis(),
+                simulation.packet_loss * 100.0
+            );
         }
+        self.simulation = simulation;
+    }
+
+    // Xorshift, only used to pick which packets to drop
+    fn next_random(&mut self) -> f64 {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 7;
+        self.random_state ^= self.random_state << 17;
+        (self.random_state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Drops or holds back a received message, returning it if it should be handled now
+    fn simulate_receive(&mut self, message: Message) -> Option<Message> {
+        if let Message::Payload(_, payload) = &message {
+            if self.simulation.packet_loss > 0.0
+                && can_drop_when_congested(payload)
+                && self.next_random() < self.simulation.packet_loss
+            {
+                return None;
+            }
+        }
+
+        if self.simulation.latency > Duration::ZERO || !self.delayed.is_empty() {
+            self.delayed
+                .push_back((Instant::now() + self.simulation.latency, message));
+            return None;
+        }
+
+        Some(message)
+    }
+
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size.max(FRAGMENT_OVERHEAD * 2);
     }
 
     pub fn get_next_message(&mut self) -> Result<Message, Error> {
-        // Receive packet
-        let packet = match self.receiver.try_recv()? {
-            SocketEvent::Packet(packet) => packet,
-            SocketEvent::Timeout(addr) => return Ok(Message::ConnectionClosed(addr)),
-            SocketEvent::Metrics(addr, metrics) => return Ok(Message::Metrics(addr, metrics)),
-            _ => return Err(Error::NotProcessed),
-        };
+        if let Some((due, _)) = self.delayed.front() {
+            if *due <= Instant::now() {
+                return Ok(self.delayed.pop_front().unwrap().1);
+            }
+        }
+
+        loop {
+            // Receive packet
+            let packet = match self.receiver.try_recv()? {
+                SocketEvent::Packet(packet) => packet,
+                SocketEvent::Timeout(addr) => {
+                    self.fragments.remove(&addr);
+                    self.corrupt_packets.remove(&addr);
+                    return Ok(Message::ConnectionClosed(addr));
+                }
+                SocketEvent::Metrics(addr, metrics) => return Ok(Message::Metrics(addr, metrics)),
+                _ => return Err(Error::NotProcessed),
+            };
+
+            let addr = packet.addr();
+
+            let result = match self.decode_payload(packet.payload()) {
+                Ok(Payloads::DefinitionFragment {
+                    index,
+                    count,
+                    bytes,
+                }) => {
+                    // Fragments arrive in order, so a first one means a fresh definition
+                    let buffer = self.fragments.entry(addr).or_default();
+                    if index == 0 {
+                        buffer.clear();
+                    }
+                    buffer.extend_from_slice(&bytes);
+
+                    if index + 1 < count {
+                        self.corrupt_packets.remove(&addr);
+                        continue;
+                    }
+
+                    let bytes = self.fragments.remove(&addr).unwrap_or_default();
+                    self.decode_payload(&bytes)
+                }
+                result => result,
+            };
+
+            match result {
+                Ok(payload) => {
+                    self.corrupt_packets.remove(&addr);
+                    self.trace(TraceDirection::Receive, addr, &payload);
+
+                    if let Some(message) = self.simulate_receive(Message::Payload(addr, payload)) {
+                        return Ok(message);
+                    }
+                }
+                // Drop just this packet, unless the peer keeps sending garbage
+                Err(e) => {
+                    let count = self.corrupt_packets.entry(addr).or_default();
+                    *count += 1;
+
+                    debug!(
+                        "[NETWORK] Dropped malformed packet from {} ({} in a row): {}",
+                        addr, count, e
+                    );
+
+                    if *count >= MAX_CORRUPT_PACKETS {
+                        warn!(
+                            "[NETWORK] Closing connection to {} after {} malformed packets in a row",
+                            addr, count
+                        );
 
+                        self.corrupt_packets.remove(&addr);
+                        self.fragments.remove(&addr);
+                        return Ok(Message::ConnectionClosed(addr));
+                    }
+                }
+            }
+        }
+    }
+
+    fn decode_payload(&mut self, bytes: &[u8]) -> Result<Payloads, Error> {
         // Decode wrapper struct
-        let wrapper: PayloadWrapper = rmp_serde::from_slice(packet.payload())?;
+        let wrapper: PayloadWrapper = rmp_serde::from_slice(bytes)?;
 
         // Decompress
         let payload_bytes = self.decompressor.decompress(&wrapper.data, wrapper.size)?;
 
         // Decode to struct
-        let payload = rmp_serde::from_slice(&payload_bytes)?;
-        Ok(Message::Payload(packet.addr(), payload))
+        Ok(rmp_serde::from_slice(&payload_bytes)?)
     }
 
     pub fn poll(&mut self) {
+        // Everything sent since the last poll goes out now
+        let queued = self.sender.len();
+
         self.socket.manual_poll(Instant::now());
+
+        self.update_congestion(queued > MAX_PACKETS_PER_POLL || self.send_failures > 0);
+        self.send_failures = 0;
     }
 
-    fn prepare_payload_bytes(&mut self, message: &Payloads) -> Result<Vec<u8>, Error> {
-        // Struct to MessagePack
-        let payload_bytes = rmp_serde::to_vec(&message)?;
+    fn update_congestion(&mut self, overloaded: bool) {
+        if overloaded == self.congested {
+            self.congestion_streak = 0;
+            return;
+        }
 
-        // Compress
-        self.compressor
-            .set_compression_level(get_compression_level_for_message(message))?;
+        self.congestion_streak += 1;
 
-        let compressed = self.compressor.compress(&payload_bytes)?;
+        if self.congestion_streak < CONGESTION_POLLS {
+            return;
+        }
 
-        // Wrap
-        let wrapper = PayloadWrapper {
-            data: compressed,
-            size: payload_bytes.len(),
-        };
+        if overloaded {
+            warn!(
+                "[NETWORK] Sending faster than the socket can keep up, dropping unreliable updates"
+            );
+        } else {
+            info!("[NETWORK] Send backlog cleared");
+        }
 
-        // Serialize
-        Ok(rmp_serde::to_vec(&wrapper)?)
+        self.congested = overloaded;
+        self.congestion_streak = 0;
+        self.congestion_changed = true;
+    }
+
+    // Whether congestion started or stopped since the last call
+    pub fn take_congestion_change(&mut self) -> Option<bool> {
+        if !self.congestion_changed {
+            return None;
+        }
+
+        self.congestion_changed = false;
+        Some(self.congested)
+    }
+
+    fn prepare_packets(&mut self, message: &Payloads) -> Result<Vec<(Payloads, Vec<u8>)>, Error> {
+        prepare_packets(&mut self.compressor, message, self.max_packet_size)
     }
 
     pub fn send_message(&mut self, message: Payloads, target: SocketAddr) -> Result<(), Error> {
-        let payload_bytes = self.prepare_payload_bytes(&message)?;
+        if self.congested && can_drop_when_congested(&message) {
+            self.trace(TraceDirection::Dropped, target, &message);
+            return Ok(());
+        }
+
+        self.trace(TraceDirection::Send, target, &message);
+
         // Send payload
-        self.sender
-            .send(get_packet_for_message(&message, payload_bytes, target))
-            .ok();
+        for (message, payload_bytes) in self.prepare_packets(&message)? {
+            if self
+                .sender
+                .send(get_packet_for_message(&message, payload_bytes, target))
+                .is_err()
+            {
+                self.send_failures += 1;
+            }
+        }
 
         Ok(())
     }
@@ -213,18 +663,68 @@ impl SenderReceiver {
         message: Payloads,
         targets: Vec<SocketAddr>,
     ) -> Result<(), Error> {
-        let payload_bytes = self.prepare_payload_bytes(&message)?;
+        let dropped = self.congested && can_drop_when_congested(&message);
+
+        for addr in targets.iter() {
+            let direction = if dropped {
+                TraceDirection::Dropped
+            } else {
+                TraceDirection::Send
+            };
+            self.trace(direction, *addr, &message);
+        }
+
+        if dropped {
+            return Ok(());
+        }
+
+        let packets = self.prepare_packets(&message)?;
 
         for addr in targets {
-            self.sender
-                .send(get_packet_for_message(
-                    &message,
-                    payload_bytes.clone(),
-                    addr,
-                ))
-                .ok();
+            for (message, payload_bytes) in packets.iter() {
+                if self
+                    .sender
+                    .send(get_packet_for_message(message, payload_bytes.clone(), addr))
+                    .is_err()
+                {
+                    self.send_failures += 1;
+                }
+            }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fragments_fit_in_packet() {
+        let mut compressor = Compressor::new(0).unwrap();
+
+        // Xorshift output doesn't compress, so most bytes need two bytes in the wrapper
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let bytes: Vec<u8> = (0..20000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let message = Payloads::AircraftDefinition {
+            bytes: bytes.into_boxed_slice(),
+            checksum: 0,
+        };
+
+        let packets = prepare_packets(&mut compressor, &message, DEFAULT_MAX_PACKET_SIZE).unwrap();
+
+        assert!(packets.len() > 1);
+        for (_, fragment_bytes) in packets.iter() {
+            assert!(fragment_bytes.len() <= DEFAULT_MAX_PACKET_SIZE);
+        }
+    }
+}
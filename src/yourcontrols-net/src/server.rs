@@ -2,12 +2,12 @@ use crossbeam_channel::unbounded;
 use igd::{search_gateway, PortMappingProtocol, SearchOptions};
 use laminar::{Metrics, Socket};
 
-use log::info;
+use log::{info, warn};
 use mem::drop;
 use spin_sleep::sleep;
 use std::sync::{
     atomic::{AtomicBool, AtomicU16, Ordering::SeqCst},
-    Arc, Mutex,
+    Arc, Mutex, PoisonError,
 };
 use std::{
     collections::HashMap,
@@ -18,29 +18,40 @@ use std::{
     time::Instant,
 };
 
+use crate::util::{
+    run_watched, DEFAULT_HEARTBEAT_INTERVAL, LOOP_SLEEP_TIME_MS, MAX_PUNCH_RETRIES,
+    PROTOCOL_VERSION,
+};
 use crate::util::{
     ClientReceiver, ClientSender, Event, ReceiveMessage, ServerReceiver, ServerSender,
     TransferClient,
 };
-use crate::util::{HEARTBEAT_INTERVAL_MANUAL_SECS, LOOP_SLEEP_TIME_MS, MAX_PUNCH_RETRIES};
 use crate::{
     get_socket_duplex,
-    util::{get_bind_address, get_local_ip_address, get_rendezvous_server, get_socket_config},
+    util::{
+        get_bind_address, get_idle_timeout, get_local_ip_address, get_random_nonce,
+        get_rendezvous_server, get_socket_config, get_unique_name,
+    },
 };
 use crate::{
-    messages::{Message, Payloads, SenderReceiver},
+    messages::{Message, NetworkSimulation, Payloads, SenderReceiver, DEFAULT_MAX_PACKET_SIZE},
+    trace::ProtocolTrace,
     util::get_local_endpoints_with_port,
 };
 
-use yourcontrols_types::Error;
+use yourcontrols_types::{Clock, Error, SystemClock};
 
 struct Client {
     addr: SocketAddr,
     is_observer: bool,
+    joined_at: Instant,
+    last_heard: Instant,
 }
 
 struct TransferStruct {
     session_id: String,
+    // Given to the rendezvous server and sent in every handshake, so joiners know they reached us and not another host with our session id
+    nonce: u64,
     clients: HashMap<String, Client>,
     // Reading/writing to UDP stream
     net: SenderReceiver,
@@ -56,10 +67,16 @@ struct TransferStruct {
     number_connections: Arc<AtomicU16>,
     username: String,
     version: String,
+    // Give joining clients with a taken name a numbered one instead of rejecting them
+    rename_duplicate_names: bool,
     heartbeat_instant: Instant,
+    heartbeat_interval: Duration,
+    // Drop clients we haven't heard from for this long once they've joined
+    session_timeout: Option<Duration>,
     // Metrics
     metrics: HashMap<SocketAddr, Metrics>,
     metrics_instant: Instant,
+    clock: Arc<dyn Clock>,
 }
 
 impl TransferStruct {
@@ -85,12 +102,14 @@ impl TransferStruct {
         }
 
         let session_id = self.session_id.clone();
+        let nonce = self.nonce;
+        let now = self.clock.now();
         let mut to_send = Vec::new();
 
         self.clients_to_holepunch.retain_mut(|session| {
             // Send a message every second
             if let Some(timer) = session.timer.as_ref() {
-                if timer.elapsed().as_secs() < 1 {
+                if now.saturating_duration_since(*timer).as_secs() < 1 {
                     return true;
                 }
             }
@@ -99,6 +118,7 @@ impl TransferStruct {
                 to_send.push((
                     Payloads::Handshake {
                         session_id: session_id.clone(),
+                        nonce: Some(nonce),
                     },
                     *addr,
                 ));
@@ -111,7 +131,7 @@ impl TransferStruct {
 
             // Reset second timer
             session.retries += 1;
-            session.timer = Some(Instant::now());
+            session.timer = Some(now);
 
             // Over retry limit, stop connection
             if session.retries == MAX_PUNCH_RETRIES {
@@ -129,24 +149,61 @@ impl TransferStruct {
     fn handle_message(&mut self, addr: SocketAddr, payload: Payloads) {
         let mut should_relay = true;
 
+        let now = self.clock.now();
+        if let Some(client) = self.clients.values_mut().find(|x| x.addr == addr) {
+            client.last_heard = now;
+        }
+
         match &payload {
             // Unused for server
             Payloads::InvalidName { .. }
+            | Payloads::NameAssigned { .. }
             | Payloads::AttemptHosterConnection { .. }
             | Payloads::InvalidVersion { .. }
             | Payloads::PlayerJoined { .. }
             | Payloads::PlayerLeft { .. }
             | Payloads::SetObserver { .. }
+            | Payloads::SetRole { .. }
+            | Payloads::SetTag { .. }
             | Payloads::RequestHosting { .. }
             | Payloads::AircraftDefinition { .. }
+            | Payloads::FlightPlan { .. }
+            | Payloads::DefinitionFragment { .. }
             | Payloads::ConnectionDenied { .. }
+            | Payloads::SessionFull
+            | Payloads::SessionLocked
+            | Payloads::Banned
+            | Payloads::InvalidPassword
             | Payloads::Heartbeat
             | Payloads::SetHost
+            | Payloads::Motd { .. }
             | Payloads::RendezvousHandshake { .. }
             | Payloads::PeerEstablished { .. } => return, // No client should be able to send this
+            // From the rendezvous server when hosting through it
+            Payloads::ServerVersion { protocol_version } => {
+                if *protocol_version < PROTOCOL_VERSION {
+                    warn!(
+                        "[NETWORK] Cloud server has protocol version {}, we have {}",
+                        protocol_version, PROTOCOL_VERSION
+                    );
+                    self.should_stop.store(true, SeqCst);
+                    self.server_tx
+                        .try_send(ReceiveMessage::Event(Event::ConnectionLost(
+                            "server_out_of_date".to_string(),
+                        )))
+                        .ok();
+                }
+                return;
+            }
             // No processing needed
             Payloads::Update { .. } => {}
-            Payloads::Ready => {}
+            Payloads::FollowPosition { .. } => {}
+            Payloads::SetAfk { .. } => {}
+            Payloads::Ready { .. } => {}
+            // Only the app has the definitions to send again
+            Payloads::RequestDefinitions { .. } => {
+                should_relay = false;
+            }
             Payloads::SetSelfObserver { .. } => {
                 should_relay = false;
             }
@@ -166,24 +223,37 @@ impl TransferStruct {
                 }
 
                 info!("[NETWORK] Client requests name {}", name);
-                // Name already in use by another client
-                let mut invalid_name = *name == self.username;
-                // Lookup name if it exists already
-                if let Some(client) = self.clients.get(name) {
-                    // Same client might've send packet twice
-                    if client.addr == addr {
-                        return;
-                    }
-                    invalid_name = true;
+                // Same client might've send packet twice, possibly already under an assigned name
+                if self.clients.values().any(|client| client.addr == addr) {
+                    return;
                 }
+                // Name already in use by another client
+                let invalid_name = *name == self.username || self.clients.contains_key(name);
+
+                let name = if !invalid_name {
+                    name.clone()
+                } else if self.rename_duplicate_names {
+                    let name = get_unique_name(name, |x| {
+                        x == self.username || self.clients.contains_key(x)
+                    });
+
+                    info!("[NETWORK] Name taken, assigning {}", name);
+
+                    self.net
+                        .send_message(Payloads::NameAssigned { name: name.clone() }, addr)
+                        .ok();
 
-                if invalid_name {
+                    name
+                } else {
                     self.net.send_message(Payloads::InvalidName {}, addr).ok();
                     return;
-                }
+                };
 
-                // Send all connected clients to new player
-                for (name, client) in self.clients.iter() {
+                // Send all connected clients to new player, in the order they joined
+                let mut connected: Vec<(&String, &Client)> = self.clients.iter().collect();
+                connected.sort_by_key(|(_, client)| client.joined_at);
+
+                for (name, client) in connected {
                     self.net
                         .send_message(
                             Payloads::PlayerJoined {
@@ -214,6 +284,8 @@ impl TransferStruct {
                     Client {
                         addr,
                         is_observer: false,
+                        joined_at: self.clock.now(),
+                        last_heard: self.clock.now(),
                     },
                 );
 
@@ -250,6 +322,7 @@ impl TransferStruct {
                         .send_message(
                             Payloads::Handshake {
                                 session_id: session_id.clone(),
+                                nonce: Some(self.nonce),
                             },
                             addr,
                         )
@@ -285,7 +358,7 @@ impl TransferStruct {
                     .try_send(ReceiveMessage::Event(Event::ConnectionEstablished))
                     .ok();
             }
-            Payloads::AttemptConnection { peers } => {
+            Payloads::AttemptConnection { peers, .. } => {
                 info!(
                     "[NETWORK] Peers attempted connection {}",
                     peers
@@ -327,16 +400,39 @@ impl TransferStruct {
 
     // Reliably compared to default heartbeat implementation
     fn handle_heartbeat(&mut self) {
-        if self.heartbeat_instant.elapsed().as_secs_f32() < HEARTBEAT_INTERVAL_MANUAL_SECS {
+        if self.clock.elapsed(self.heartbeat_instant) < self.heartbeat_interval {
             return;
         }
 
-        self.heartbeat_instant = Instant::now();
+        self.heartbeat_instant = self.clock.now();
         self.send_to_all(None, Payloads::Heartbeat);
     }
 
+    fn handle_session_timeout(&mut self) {
+        let timeout = match self.session_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        let timed_out: Vec<SocketAddr> = self
+            .clients
+            .values()
+            .filter(|x| self.clock.elapsed(x.last_heard) >= timeout)
+            .map(|x| x.addr)
+            .collect();
+
+        for addr in timed_out {
+            warn!(
+                "[NETWORK] Nothing heard from port {} for {:?}",
+                addr.port(),
+                timeout
+            );
+            self.remove_client(addr);
+        }
+    }
+
     fn handle_metrics(&mut self) {
-        if self.metrics_instant.elapsed().as_secs_f32() < 1.0 {
+        if self.clock.elapsed(self.metrics_instant).as_secs_f32() < 1.0 {
             return;
         }
 
@@ -346,11 +442,22 @@ impl TransferStruct {
             all_metrics += metric;
         }
 
-        self.metrics_instant = Instant::now();
+        self.metrics_instant = self.clock.now();
 
         self.server_tx
             .send(ReceiveMessage::Event(Event::Metrics(all_metrics)))
             .ok();
+
+        for (name, client) in self.clients.iter() {
+            if let Some(metrics) = self.metrics.get(&client.addr) {
+                self.server_tx
+                    .send(ReceiveMessage::Event(Event::ClientMetrics(
+                        name.clone(),
+                        metrics.clone(),
+                    )))
+                    .ok();
+            }
+        }
     }
 
     fn remove_client(&mut self, addr: SocketAddr) {
@@ -411,6 +518,8 @@ pub struct Server {
     transfer: Option<Arc<Mutex<TransferStruct>>>,
 
     last_port_forward_result: Option<Result<(), Error>>,
+    // Address the socket actually bound to
+    local_address: Option<SocketAddr>,
     // Send data to peers
     client_tx: ClientSender,
     // Internally receive data to send to clients
@@ -424,6 +533,12 @@ pub struct Server {
     username: String,
     version: String,
     timeout: u64,
+    session_timeout: Option<Duration>,
+    rename_duplicate_names: bool,
+    max_packet_size: usize,
+    heartbeat_interval: Duration,
+    protocol_trace: Option<ProtocolTrace>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Server {
@@ -435,6 +550,7 @@ impl Server {
             number_connections: Arc::new(AtomicU16::new(0)),
 
             last_port_forward_result: None,
+            local_address: None,
             should_stop: Arc::new(AtomicBool::new(false)),
             client_rx,
             client_tx,
@@ -444,9 +560,44 @@ impl Server {
             username,
             version,
             timeout,
+            session_timeout: None,
+            rename_duplicate_names: false,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            protocol_trace: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    // Must be called before starting the server
+    pub fn set_rename_duplicate_names(&mut self, rename_duplicate_names: bool) {
+        self.rename_duplicate_names = rename_duplicate_names;
+    }
+
+    // Must be called before starting the server
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    // Must be called before starting the server
+    pub fn set_heartbeat_interval(&mut self, heartbeat_interval: Duration) {
+        self.heartbeat_interval = heartbeat_interval;
+    }
+
+    // Must be called before starting the server. The timeout given to new() then only applies while connecting.
+    pub fn set_session_timeout(&mut self, session_timeout: Duration) {
+        self.session_timeout = Some(session_timeout);
+    }
+
+    // Must be called before starting the server
+    pub fn set_protocol_trace(&mut self, protocol_trace: Option<ProtocolTrace>) {
+        self.protocol_trace = protocol_trace;
+    }
+
+    fn get_idle_timeout(&self) -> u64 {
+        get_idle_timeout(self.timeout, self.session_timeout)
+    }
+
     fn port_forward(&self, port: u16) -> Result<(), Error> {
         let local_addr: Ipv4Addr = match get_local_ip_address(false) {
             Some(IpAddr::V4(ip)) => ip,
@@ -483,11 +634,22 @@ impl Server {
     }
 
     pub fn start(&mut self, is_ipv6: bool, port: u16, upnp: bool) -> Result<(), Error> {
-        let socket =
-            Socket::from_udp_socket(get_socket_duplex(port), get_socket_config(self.timeout))?;
-        // Attempt to port forward
+        let socket = Socket::from_udp_socket(
+            get_socket_duplex(port),
+            get_socket_config(self.get_idle_timeout()),
+        )?;
+        // Attempt to port forward, falling back to plain direct hosting if the router refuses
         if upnp && !is_ipv6 {
-            self.last_port_forward_result = Some(self.port_forward(port));
+            let result = self.port_forward(port);
+
+            if let Err(e) = result.as_ref() {
+                warn!(
+                    "[NETWORK] UPnP port mapping failed, continuing without it. Reason: {}",
+                    e
+                );
+            }
+
+            self.last_port_forward_result = Some(result);
         }
 
         self.run(socket, None)
@@ -496,22 +658,38 @@ impl Server {
     pub fn start_with_hole_punching(&mut self, is_ipv6: bool) -> Result<(), Error> {
         let socket = Socket::bind_with_config(
             get_bind_address(is_ipv6, None),
-            get_socket_config(self.timeout),
+            get_socket_config(self.get_idle_timeout()),
         )?;
         let addr: SocketAddr = get_rendezvous_server(is_ipv6)?;
 
         self.run(socket, Some(addr))
     }
 
+    // None if UPnP was not attempted
+    pub fn get_last_port_forward_result(&self) -> Option<&Result<(), Error>> {
+        self.last_port_forward_result.as_ref()
+    }
+
+    // Must be called before starting the server
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn get_local_address(&self) -> Option<SocketAddr> {
+        self.local_address
+    }
+
     fn run(&mut self, socket: Socket, rendezvous: Option<SocketAddr>) -> Result<(), Error> {
         let local_endpoint = socket.local_addr().unwrap();
         let port = local_endpoint.port();
+        self.local_address = Some(local_endpoint);
 
         info!("[NETWORK] Listening on {:?}", local_endpoint);
 
         let mut transfer = TransferStruct {
             // Holepunching
             session_id: String::new(),
+            nonce: get_random_nonce(),
             rendezvous_server: rendezvous,
             clients_to_holepunch: Vec::new(),
             // Transfer
@@ -525,11 +703,18 @@ impl Server {
             number_connections: self.number_connections.clone(),
             username: self.username.clone(),
             version: self.version.clone(),
-            heartbeat_instant: Instant::now(),
-            metrics_instant: Instant::now(),
+            rename_duplicate_names: self.rename_duplicate_names,
+            heartbeat_instant: self.clock.now(),
+            heartbeat_interval: self.heartbeat_interval,
+            session_timeout: self.session_timeout,
+            metrics_instant: self.clock.now(),
             metrics: HashMap::new(),
+            clock: self.clock.clone(),
         };
 
+        transfer.net.set_max_packet_size(self.max_packet_size);
+        transfer.net.set_trace(self.protocol_trace.clone());
+
         if let Some(addr) = rendezvous {
             // Send handshake payload to rendezvous server to get session ID
             transfer
@@ -541,6 +726,7 @@ impl Server {
                             local_endpoint.is_ipv6(),
                             port,
                         ),
+                        nonce: Some(transfer.nonce),
                     },
                     addr,
                 )
@@ -556,14 +742,27 @@ impl Server {
         let transfer_thread_clone = transfer_send.clone();
         self.transfer = Some(transfer_send);
 
+        let server_tx = self.server_tx.clone();
+        let should_stop = self.should_stop.clone();
         // Run main loop
         thread::spawn(move || {
             let sleep_duration = Duration::from_millis(LOOP_SLEEP_TIME_MS);
-            loop {
-                let mut transfer = transfer_thread_clone.lock().unwrap();
+
+            // A panic leaves the lock poisoned, the state in it is still what the loop picks back up with
+            run_watched(&server_tx, &should_stop, || loop {
+                let mut transfer = transfer_thread_clone
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
 
                 transfer.net.poll();
 
+                if let Some(congested) = transfer.net.take_congestion_change() {
+                    transfer
+                        .server_tx
+                        .try_send(ReceiveMessage::Event(Event::SendCongested(congested)))
+                        .ok();
+                }
+
                 while let Ok(message) = transfer.net.get_next_message() {
                     match message {
                         Message::Payload(addr, payload) => {
@@ -594,6 +793,7 @@ impl Server {
                 transfer.handle_handshake();
                 transfer.handle_app_message();
                 transfer.handle_heartbeat();
+                transfer.handle_session_timeout();
                 transfer.handle_metrics();
 
                 if transfer.should_stop() {
@@ -602,7 +802,7 @@ impl Server {
 
                 drop(transfer);
                 sleep(sleep_duration);
-            }
+            });
         });
 
         Ok(())
@@ -633,7 +833,11 @@ impl TransferClient for Server {
     fn transfer_control(&self, target: String) {
         // Read for initial contact with other clients
         if let Some(transfer) = self.transfer.as_ref() {
-            transfer.lock().unwrap().in_control.clone_from(&target);
+            transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .in_control
+                .clone_from(&target);
         }
 
         let message = Payloads::TransferControl {
@@ -651,7 +855,12 @@ impl TransferClient for Server {
     fn set_observer(&self, target: String, is_observer: bool) {
         // Read for initial contact with other clients
         if let Some(transfer) = self.transfer.as_ref() {
-            if let Some(client) = transfer.lock().unwrap().clients.get_mut(&target) {
+            if let Some(client) = transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clients
+                .get_mut(&target)
+            {
                 client.is_observer = is_observer;
             }
         }
@@ -670,11 +879,38 @@ impl TransferClient for Server {
 
     fn get_session_id(&self) -> Option<String> {
         if let Some(transfer) = self.transfer.as_ref() {
-            return Some(transfer.lock().unwrap().session_id.clone());
+            return Some(
+                transfer
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .session_id
+                    .clone(),
+            );
         }
         None
     }
 
+    fn set_network_simulation(&self, simulation: NetworkSimulation) {
+        if let Some(transfer) = self.transfer.as_ref() {
+            transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .net
+                .set_simulation(simulation);
+        }
+    }
+
+    fn set_server_name(&mut self, name: String) {
+        if let Some(transfer) = self.transfer.as_ref() {
+            transfer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .username
+                .clone_from(&name);
+        }
+        self.username = name;
+    }
+
     fn stop(&mut self, reason: String) {
         self.should_stop.store(true, SeqCst);
         self.server_tx
@@ -0,0 +1,89 @@
+use std::{
+    fs::File,
+    io::{self, LineWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::messages::Payloads;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TraceDirection {
+    Send,
+    Receive,
+    // Not sent because the socket couldn't keep up
+    Dropped,
+}
+
+impl TraceDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TraceDirection::Send => "SEND",
+            TraceDirection::Receive => "RECV",
+            TraceDirection::Dropped => "DROP",
+        }
+    }
+}
+
+// Writes every payload sent and received to its own file, for debugging a user's connection
+#[derive(Clone)]
+pub struct ProtocolTrace {
+    writer: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl ProtocolTrace {
+    // Starts a fresh file, shared by every connection given a clone
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: Arc::new(Mutex::new(LineWriter::new(File::create(path)?))),
+        })
+    }
+
+    pub fn write(&self, direction: TraceDirection, addr: SocketAddr, payload: &Payloads) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        if let Ok(mut writer) = self.writer.lock() {
+            writeln!(
+                writer,
+                "{:.3} {} {} {}",
+                timestamp,
+                direction.as_str(),
+                addr,
+                describe_payload(payload)
+            )
+            .ok();
+        }
+    }
+}
+
+// Byte buffers are only listed by size, the rest of the payload is written out in full
+fn describe_payload(payload: &Payloads) -> String {
+    match payload {
+        Payloads::AircraftDefinition { bytes, checksum } => format!(
+            "AircraftDefinition {{ bytes: {} bytes, checksum: {} }}",
+            bytes.len(),
+            checksum
+        ),
+        Payloads::FlightPlan { name, bytes } => format!(
+            "FlightPlan {{ name: {:?}, bytes: {} bytes }}",
+            name,
+            bytes.len()
+        ),
+        Payloads::DefinitionFragment {
+            index,
+            count,
+            bytes,
+        } => format!(
+            "DefinitionFragment {{ index: {}, count: {}, bytes: {} bytes }}",
+            index,
+            count,
+            bytes.len()
+        ),
+        payload => format!("{:?}", payload),
+    }
+}
@@ -2,8 +2,14 @@ use crossbeam_channel::{Receiver, Sender};
 use dns_lookup::lookup_host;
 use dotenv_codegen::dotenv;
 use laminar::Metrics;
+use log::{error, warn};
 use socket2::{Domain, Socket, Type};
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::net::UdpSocket;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
 use std::time::SystemTime;
 use std::{
     net::SocketAddr,
@@ -11,15 +17,25 @@ use std::{
     net::{IpAddr, SocketAddrV6},
     time::Duration,
 };
-use yourcontrols_types::{AllNeedSync, Error};
+use yourcontrols_types::{AllNeedSync, ClientRole, Error};
 
-use crate::messages::Payloads;
+use crate::messages::{NetworkSimulation, Payloads};
 
 pub const MAX_PUNCH_RETRIES: u8 = 5;
+pub const MAX_DEFINITION_RETRIES: u8 = 3;
 pub const LOOP_SLEEP_TIME_MS: u64 = 5;
-pub const HEARTBEAT_INTERVAL_MANUAL_SECS: f32 = 0.5;
+// Times the network loop is started again after panicking before the session is given up on
+pub const MAX_LOOP_RESTARTS: u8 = 3;
+// Also keeps NAT mappings open while little else is being sent
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+// Register with the rendezvous server again if it hasn't answered for this long while connecting
+pub const RENDEZVOUS_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+// Bumped whenever payloads change in a way an older cloud server can't understand
+pub const PROTOCOL_VERSION: u32 = 3;
 
 const HEARTBEAT_INTERVAL_MS: u64 = 1000;
+// Largest prime below 2^16, for the Adler-32 checksum
+const ADLER_MOD: u32 = 65521;
 const RENDEZVOUS_SERVER_HOSTNAME: &str = dotenv!("SERVER_HOSTNAME");
 const RENDEZVOUS_PORT: &str = dotenv!("SERVER_PORT");
 
@@ -29,6 +45,18 @@ pub type ClientReceiver = Receiver<(Payloads, Option<String>)>;
 pub type ServerSender = Sender<ReceiveMessage>;
 pub type ServerReceiver = Receiver<ReceiveMessage>;
 
+// Adler-32, enough to catch a definition that got mangled on the way
+pub fn get_checksum(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in bytes {
+        a = (a + *byte as u32) % ADLER_MOD;
+        b = (b + a) % ADLER_MOD;
+    }
+
+    (b << 16) | a
+}
+
 pub fn get_bind_address(is_ipv6: bool, port: Option<u16>) -> SocketAddr {
     let bind_string = format!(
         "{}:{}",
@@ -78,6 +106,14 @@ pub fn get_socket_config(timeout: u64) -> laminar::Config {
     }
 }
 
+// The socket drops peers that are quiet for this long, so it has to wait out both timeouts
+pub fn get_idle_timeout(timeout: u64, session_timeout: Option<Duration>) -> u64 {
+    session_timeout
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+        .max(timeout)
+}
+
 pub fn get_socket_duplex(port: u16) -> UdpSocket {
     let socket = Socket::new(Domain::IPV6, Type::DGRAM, None).unwrap();
     socket.set_only_v6(false).ok();
@@ -99,6 +135,59 @@ pub fn get_seconds() -> f64 {
         .as_secs_f64()
 }
 
+// Appends the lowest free number to a name, e.g. "Bob" becomes "Bob (2)"
+pub fn get_unique_name(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    (2..)
+        .map(|number| format!("{} ({})", name, number))
+        .find(|candidate| !is_taken(candidate))
+        .unwrap()
+}
+
+// Runs a network thread's loop, starting it again if it panics. Once it has panicked too often
+// the session is ended with a ConnectionLost, rather than the app thinking it's still connected.
+pub fn run_watched(server_tx: &ServerSender, should_stop: &AtomicBool, mut run: impl FnMut()) {
+    let mut restarts = 0;
+
+    while let Err(panic) = panic::catch_unwind(AssertUnwindSafe(&mut run)) {
+        let message = get_panic_message(&*panic);
+
+        if restarts == MAX_LOOP_RESTARTS || should_stop.load(SeqCst) {
+            error!(
+                "[NETWORK] Network thread panicked, disconnecting: {}",
+                message
+            );
+            should_stop.store(true, SeqCst);
+            server_tx
+                .try_send(ReceiveMessage::Event(Event::ConnectionLost(
+                    "network_thread_crashed".to_string(),
+                )))
+                .ok();
+            return;
+        }
+
+        restarts += 1;
+        warn!(
+            "[NETWORK] Network thread panicked, restarting it ({}/{}): {}",
+            restarts, MAX_LOOP_RESTARTS, message
+        );
+    }
+}
+
+fn get_panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown reason"
+    }
+}
+
+// Different every call, std's hasher keys are randomly seeded
+pub fn get_random_nonce() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
 pub fn get_local_endpoints_with_port(is_ipv6: bool, port: u16) -> Option<SocketAddr> {
     get_local_ip_address(is_ipv6).map(|x| SocketAddr::new(x, port))
 }
@@ -130,13 +219,43 @@ pub fn is_actually_ipv4(addr: SocketAddr) -> bool {
         SocketAddr::V6(v6) => matches!(v6.ip().segments(), [0, 0, 0, 0, 0, 0xFFFF, ..]),
     }
 }
+// Steps of joining a session, in the order they're reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStage {
+    ResolvingHost,
+    ContactingRendezvous,
+    PunchingThrough,
+    Handshaking,
+    LoadingDefinitions,
+    SyncingInitialState,
+}
+
+impl ConnectionStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionStage::ResolvingHost => "resolving_host",
+            ConnectionStage::ContactingRendezvous => "contacting_rendezvous",
+            ConnectionStage::PunchingThrough => "punching_through",
+            ConnectionStage::Handshaking => "handshaking",
+            ConnectionStage::LoadingDefinitions => "loading_definitions",
+            ConnectionStage::SyncingInitialState => "syncing_initial_state",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
     ConnectionEstablished,
+    // Only the network stages are sent by the client, the app reports the rest itself
+    ConnectionStage(ConnectionStage),
     UnablePunchthrough,
     SessionIdFetchFailed,
     ConnectionLost(String),
     Metrics(Metrics),
+    // Per joiner metrics, only sent by a directly hosting server
+    ClientMetrics(String, Metrics),
+    // Started or stopped sending faster than the socket keeps up with
+    SendCongested(bool),
 }
 
 #[derive(Debug)]
@@ -152,6 +271,8 @@ pub trait TransferClient {
     fn get_receiver(&self) -> &ServerReceiver;
     fn get_server_name(&self) -> &str;
     fn get_session_id(&self) -> Option<String>;
+    fn set_server_name(&mut self, name: String);
+    fn set_network_simulation(&self, simulation: NetworkSimulation);
     // Application specific functions
     fn stop(&mut self, reason: String);
 
@@ -224,15 +345,116 @@ pub trait TransferClient {
             .ok();
     }
 
+    fn set_role(&self, target: String, role: ClientRole) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::SetRole {
+                    from: self.get_server_name().to_string(),
+                    to: target,
+                    role,
+                },
+                None,
+            ))
+            .ok();
+    }
+
+    fn send_role(&self, name: String, role: ClientRole, target: String) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::SetRole {
+                    from: self.get_server_name().to_string(),
+                    to: name,
+                    role,
+                },
+                Some(target),
+            ))
+            .ok();
+    }
+
+    fn set_tag(&self, target: String, tag: Option<String>) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::SetTag {
+                    from: self.get_server_name().to_string(),
+                    to: target,
+                    tag,
+                },
+                None,
+            ))
+            .ok();
+    }
+
+    fn send_tag(&self, name: String, tag: String, target: String) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::SetTag {
+                    from: self.get_server_name().to_string(),
+                    to: name,
+                    tag: Some(tag),
+                },
+                Some(target),
+            ))
+            .ok();
+    }
+
+    fn set_afk(&self, is_afk: bool) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::SetAfk {
+                    name: self.get_server_name().to_string(),
+                    is_afk,
+                },
+                None,
+            ))
+            .ok();
+    }
+
+    fn send_follow_position(&self, latitude: f64, longitude: f64, altitude: f64, heading: f64) {
+        self.get_transmitter()
+            .try_send((
+                Payloads::FollowPosition {
+                    from: self.get_server_name().to_string(),
+                    latitude,
+                    longitude,
+                    altitude,
+                    heading,
+                },
+                None,
+            ))
+            .ok();
+    }
+
     fn send_ready(&self) {
         self.get_transmitter()
-            .try_send((Payloads::Ready, None))
+            .try_send((
+                Payloads::Ready {
+                    from: self.get_server_name().to_string(),
+                },
+                None,
+            ))
+            .ok();
+    }
+
+    fn send_motd(&self, message: String, target: String) {
+        self.get_transmitter()
+            .try_send((Payloads::Motd { message }, Some(target)))
+            .ok();
+    }
+
+    fn send_flight_plan(&self, name: String, bytes: Box<[u8]>, target: Option<String>) {
+        self.get_transmitter()
+            .try_send((Payloads::FlightPlan { name, bytes }, target))
             .ok();
     }
 
     fn send_definitions(&self, bytes: Box<[u8]>, target: String) {
+        let checksum = get_checksum(&bytes);
+
         self.get_transmitter()
-            .try_send((Payloads::AircraftDefinition { bytes }, Some(target)))
+            .try_send((
+                Payloads::AircraftDefinition { bytes, checksum },
+                Some(target),
+            ))
             .ok();
     }
 }
@@ -1,5 +1,5 @@
 use crate::servers::{Client, ServerState, Servers};
-use laminar::Socket;
+use laminar::{Metrics, Socket};
 use log::info;
 use semver::Version;
 use std::collections::HashMap;
@@ -15,6 +15,7 @@ pub const SERVER_NAME: &str = "SERVER";
 
 const CLEANUP_INTERVAL: u64 = 30;
 const INACTIVE_SESSION_TIMEOUT: u64 = 30;
+const SUMMARY_INTERVAL: u64 = 60;
 
 fn send_to_all(
     payload: Payloads,
@@ -46,6 +47,7 @@ fn process_payload(
     match &payload {
         // Unused
         Payloads::InvalidName { .. }
+        | Payloads::NameAssigned { .. }
         | Payloads::RendezvousHandshake { .. }
         | Payloads::InvalidVersion { .. }
         | Payloads::PlayerJoined { .. }
@@ -56,14 +58,34 @@ fn process_payload(
         | Payloads::RequestHosting { .. }
         | Payloads::PeerEstablished { .. }
         | Payloads::ConnectionDenied { .. }
+        | Payloads::SessionFull
+        | Payloads::SessionLocked
+        | Payloads::Banned
+        | Payloads::InvalidPassword
         | Payloads::Heartbeat
+        | Payloads::ServerVersion { .. }
+        | Payloads::DefinitionFragment { .. }
         | Payloads::PlayerLeft { .. } => return,
         // Used
-        Payloads::AircraftDefinition { bytes } => {
-            state.aircraft_definition = Some(bytes.clone());
+        Payloads::AircraftDefinition { bytes, checksum } => {
+            state.aircraft_definition = Some((bytes.clone(), *checksum));
             return;
         }
-        Payloads::Update { .. } => {}
+        // Passed on as well, the host only sends it once someone has already joined
+        Payloads::Motd { message } => {
+            state.motd = Some(message.clone());
+        }
+        Payloads::RequestDefinitions { from } => {
+            info!("{} asked for the definitions again.", from);
+            send_definitions(addr, state, net);
+            return;
+        }
+        Payloads::Update { .. }
+        | Payloads::FollowPosition { .. }
+        | Payloads::SetRole { .. }
+        | Payloads::SetTag { .. }
+        | Payloads::FlightPlan { .. }
+        | Payloads::SetAfk { .. } => {}
         Payloads::InitHandshake { name, version } => {
             if let Ok(version) = Version::from_str(version) {
                 let server_version =
@@ -121,14 +143,10 @@ fn process_payload(
             );
 
             // Send definitions to new client
-            if let Some(bytes) = state.aircraft_definition.as_ref() {
-                net.send_message(
-                    Payloads::AircraftDefinition {
-                        bytes: bytes.clone(),
-                    },
-                    addr,
-                )
-                .ok();
+            send_definitions(addr, state, net);
+
+            if let Some(message) = state.motd.clone() {
+                net.send_message(Payloads::Motd { message }, addr).ok();
             }
 
             info!("{} connected to hoster.", name);
@@ -163,15 +181,17 @@ fn process_payload(
             }
             return;
         }
-        Payloads::Ready => {
-            // Tell "host" to do a full sync
-            if let Some(client) = state.clients.get(&state.in_control) {
-                net.send_message(payload, client.addr).ok();
+        Payloads::Ready { .. } => {
+            // Tell "host" to do a full sync, and let the host know who has loaded in
+            for (name, client) in state.clients.iter() {
+                if *name == state.in_control || client.is_host {
+                    net.send_message(payload.clone(), client.addr).ok();
+                }
             }
 
             return;
         }
-        Payloads::Handshake { session_id } => {
+        Payloads::Handshake { session_id, .. } => {
             info!("Hoster handshake received {}", session_id);
             net.send_message(payload, addr).ok();
             return;
@@ -181,6 +201,19 @@ fn process_payload(
     send_to_all(payload, Some(&addr), state, net);
 }
 
+fn send_definitions(addr: SocketAddr, state: &ServerState, net: &mut SenderReceiver) {
+    if let Some((bytes, checksum)) = state.aircraft_definition.as_ref() {
+        net.send_message(
+            Payloads::AircraftDefinition {
+                bytes: bytes.clone(),
+                checksum: *checksum,
+            },
+            addr,
+        )
+        .ok();
+    }
+}
+
 fn set_host(name: String, state: &mut ServerState, net: &mut SenderReceiver) {
     let client = state.clients.get_mut(&name).expect("always there");
     client.is_observer = false;
@@ -231,6 +264,53 @@ fn cleanup(servers: &mut Servers) {
         .retain(|_, session_id| server_states.get(session_id).is_some());
 }
 
+// Logs the combined bandwidth and the client with the worst round trip time of each session
+fn log_session_summaries(servers: &Servers, metrics_data: &HashMap<SocketAddr, Metrics>) {
+    for (session_id, state) in servers.server_states.iter() {
+        if state.clients.is_empty() {
+            continue;
+        }
+
+        let mut total_kbps = 0.0;
+        let mut slowest: Option<(&String, &Metrics)> = None;
+
+        for (name, client) in state.clients.iter() {
+            let metrics = match metrics_data.get(&client.addr) {
+                Some(metrics) => metrics,
+                None => continue,
+            };
+
+            total_kbps += metrics.sent_kbps + metrics.receive_kbps;
+
+            let is_slowest = match slowest {
+                Some((_, slowest)) => metrics.rtt > slowest.rtt,
+                None => true,
+            };
+
+            if is_slowest {
+                slowest = Some((name, metrics));
+            }
+        }
+
+        match slowest {
+            Some((name, metrics)) => info!(
+                "Session {}: {} clients, {:.2} KB/s total, slowest {} ({:.0}ms RTT, {:.2}% loss)",
+                session_id,
+                state.clients.len(),
+                total_kbps,
+                name,
+                metrics.rtt,
+                metrics.packet_loss * 100.0
+            ),
+            None => info!(
+                "Session {}: {} clients, no metrics yet",
+                session_id,
+                state.clients.len()
+            ),
+        }
+    }
+}
+
 pub fn run_hoster(servers: Arc<Mutex<Servers>>, port: u16) {
     let socket = Socket::from_udp_socket(get_socket_duplex(port), get_socket_config(5))
         .expect("Failed to bind!");
@@ -246,6 +326,7 @@ pub fn run_hoster(servers: Arc<Mutex<Servers>>, port: u16) {
     let mut metrics_data = HashMap::new();
 
     let mut cleanup_timer = Instant::now();
+    let mut summary_timer = Instant::now();
 
     loop {
         net.poll();
@@ -256,7 +337,7 @@ pub fn run_hoster(servers: Arc<Mutex<Servers>>, port: u16) {
             match net.get_next_message() {
                 Ok(Message::Payload(addr, payload)) => {
                     // Handle initial connection verification
-                    if let Payloads::Handshake { session_id } = &payload {
+                    if let Payloads::Handshake { session_id, .. } = &payload {
                         if let Some(previous_session_id) =
                             servers.meta_state.unknown_clients.get(&addr.ip()).cloned()
                         {
@@ -338,6 +419,11 @@ pub fn run_hoster(servers: Arc<Mutex<Servers>>, port: u16) {
             cleanup_timer = Instant::now();
         }
 
+        if summary_timer.elapsed().as_secs() > SUMMARY_INTERVAL {
+            log_session_summaries(&servers, &metrics_data);
+            summary_timer = Instant::now();
+        }
+
         handle_heartbeats(&mut servers.server_states, &mut net);
 
         drop(servers);
@@ -10,7 +10,7 @@ use std::thread::sleep;
 use std::time::{Duration, Instant};
 use yourcontrols_net::{
     get_addr_from_hostname_and_port, get_socket_config, get_socket_duplex, is_actually_ipv4,
-    Message, Payloads, SenderReceiver,
+    Message, Payloads, SenderReceiver, PROTOCOL_VERSION,
 };
 use yourcontrols_types::Error;
 
@@ -45,6 +45,17 @@ fn process_message(
     counters: &mut Counters,
     servers: &mut Arc<Mutex<Servers>>,
 ) {
+    // Lets newer clients tell when this server needs updating
+    if let Payloads::RendezvousHandshake { .. } | Payloads::RequestHosting { .. } = message {
+        net.send_message(
+            Payloads::ServerVersion {
+                protocol_version: PROTOCOL_VERSION,
+            },
+            addr,
+        )
+        .ok();
+    }
+
     match message {
         Payloads::RendezvousHandshake {
             session_id,
@@ -82,6 +93,7 @@ fn process_message(
                 net.send_message(
                     Payloads::AttemptConnection {
                         peers: server_connection_info.hoster_endpoints.clone(),
+                        nonce: server_connection_info.nonce,
                     },
                     addr,
                 )
@@ -97,6 +109,7 @@ fn process_message(
                         .into_iter()
                         .flatten()
                         .collect(),
+                        nonce: None,
                     },
                     server_connection_info.hoster_addr,
                 )
@@ -137,10 +150,12 @@ fn process_message(
         Payloads::RequestHosting {
             self_hosted,
             local_endpoint,
+            nonce,
         } => {
             let session_id;
 
             if self_hosted {
+                let servers = servers.lock().unwrap();
                 session_id = sessions.map_session_id_to_socket_info(
                     addr,
                     vec![
@@ -150,6 +165,8 @@ fn process_message(
                     .into_iter()
                     .flatten()
                     .collect(),
+                    nonce,
+                    |id| servers.meta_state.active_servers.contains_key(id),
                 );
                 info!(
                     "Self hosted session created with hoster {} as {}",
@@ -174,7 +191,8 @@ fn process_message(
                 // Reserve
                 let server_hostname = var("SERVER_HOSTNAME").unwrap();
                 let hoster_addr = resolve_hoster_address(addr, &server_hostname);
-                session_id = servers.reserve_server(server_hostname, addr);
+                session_id =
+                    servers.reserve_server(server_hostname, addr, |id| sessions.has_session(id));
 
                 info!(
                     "Hosting session for hoster {} as {}",
@@ -185,6 +203,7 @@ fn process_message(
                 net.send_message(
                     Payloads::AttemptConnection {
                         peers: vec![hoster_addr],
+                        nonce: None,
                     },
                     addr,
                 )
@@ -24,7 +24,10 @@ impl Client {
 
 pub struct ServerState {
     pub clients: HashMap<String, Client>,
-    pub aircraft_definition: Option<Box<[u8]>>,
+    // Definition bytes and their checksum
+    pub aircraft_definition: Option<(Box<[u8]>, u32)>,
+    // Host's welcome message for joiners
+    pub motd: Option<String>,
     pub in_control: String,
     pub heartbeat_instant: Instant,
     pub started_at: Instant,
@@ -36,6 +39,7 @@ impl ServerState {
             clients: HashMap::new(),
             in_control: "SERVER".to_string(),
             aircraft_definition: None,
+            motd: None,
             heartbeat_instant: Instant::now(),
             started_at: Instant::now(),
         }
@@ -81,8 +85,18 @@ impl Servers {
         }
     }
 
-    pub fn reserve_server(&mut self, hostname: String, addr_who_requested: SocketAddr) -> String {
-        let id = get_random_id(SESSION_ID_LENGTH);
+    pub fn reserve_server(
+        &mut self,
+        hostname: String,
+        addr_who_requested: SocketAddr,
+        is_taken: impl Fn(&str) -> bool,
+    ) -> String {
+        let id = loop {
+            let id = get_random_id(SESSION_ID_LENGTH);
+            if !self.meta_state.active_servers.contains_key(&id) && !is_taken(&id) {
+                break id;
+            }
+        };
 
         self.meta_state
             .active_servers
@@ -5,13 +5,20 @@ use std::{collections::HashMap, net::SocketAddr};
 pub struct SessionInfo {
     pub hoster_addr: SocketAddr,
     pub hoster_endpoints: Vec<SocketAddr>,
+    // Passed on to joiners to check they reached this hoster
+    pub nonce: Option<u64>,
 }
 
 impl SessionInfo {
-    pub fn new(hoster_addr: SocketAddr, hoster_endpoints: Vec<SocketAddr>) -> Self {
+    pub fn new(
+        hoster_addr: SocketAddr,
+        hoster_endpoints: Vec<SocketAddr>,
+        nonce: Option<u64>,
+    ) -> Self {
         Self {
             hoster_addr,
             hoster_endpoints,
+            nonce,
         }
     }
 }
@@ -35,18 +42,30 @@ impl Sessions {
         &mut self,
         addr: SocketAddr,
         endpoints: Vec<SocketAddr>,
+        nonce: Option<u64>,
+        is_taken: impl Fn(&str) -> bool,
     ) -> String {
-        let id = get_random_id(SESSION_ID_LENGTH);
+        // Never hand out an id still in use, joiners would be sent to either host
+        let id = loop {
+            let id = get_random_id(SESSION_ID_LENGTH);
+            if !self.session_info.contains_key(&id) && !is_taken(&id) {
+                break id;
+            }
+        };
 
         // Point session id to hosting IP
         self.hosting_sessions.insert(addr, id.clone());
         // Point session id to all possible connectable endpoints
         self.session_info
-            .insert(id.clone(), SessionInfo::new(addr, endpoints));
+            .insert(id.clone(), SessionInfo::new(addr, endpoints, nonce));
 
         id
     }
 
+    pub fn has_session(&self, session_id: &str) -> bool {
+        self.session_info.contains_key(session_id)
+    }
+
     pub fn get_session_connection_info(&self, session_id: &str) -> Option<&SessionInfo> {
         self.session_info.get(session_id)
     }
@@ -7,6 +7,9 @@ pub enum Error {
     // Net
     IOError(io::Error),
     MismatchingIpVersion,
+    InvalidIp(String),
+    InvalidHostname(String),
+    InvalidPort(String),
 
     SocketError(laminar::ErrorKind),
     GatewayNotFound(igd::SearchError),
@@ -22,6 +25,8 @@ pub enum Error {
     MissingField(&'static str),
     InvalidSyncType(String),
     InvalidCategory(String),
+    InvalidBit(String, u8),
+    NotLocalVar(String),
     IncludeError(String, String),
 
     MissingMapping(String),
@@ -45,6 +50,13 @@ impl Display for Error {
             Error::MismatchingIpVersion => {
                 write!(f, "No hostname IPs matched the requested IP version.")
             }
+            Error::InvalidIp(ip) => write!(f, "{} is not a valid IP address.", ip),
+            Error::InvalidHostname(hostname) => {
+                write!(f, "{} is not a valid IP address or hostname.", hostname)
+            }
+            Error::InvalidPort(port) => {
+                write!(f, "{} is not a valid port, use 1 to 65535.", port)
+            }
             Error::SocketError(e) => write!(f, "Could not initialize socket! Reason: {}", e),
 
             Error::GatewayNotFound(e) => write!(f, "Gateway not found: {}", e),
@@ -55,6 +67,18 @@ impl Display for Error {
             Error::MissingField(s) => write!(f, r#"Missing field "{}""#, s),
             Error::InvalidSyncType(s) => write!(f, r#"Invalid type "{}""#, s),
             Error::InvalidCategory(s) => write!(f, r#"Invalid category "{}""#, s),
+            Error::InvalidBit(s, bit) => write!(
+                f,
+                r#"Invalid bit {} for "{}", bits need an i32 or i64 aircraft var"#,
+                bit, s
+            ),
+            Error::NotLocalVar(s) => {
+                write!(
+                    f,
+                    r#""{}" is not a local var, only L: vars can be grouped"#,
+                    s
+                )
+            }
             Error::YamlError(e, file_name) => {
                 write!(f, "Error parsing YAML in {}: {}", file_name, e)
             }
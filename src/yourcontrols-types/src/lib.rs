@@ -1,5 +1,7 @@
+mod clock;
 mod error;
 
+pub use clock::{Clock, ManualClock, SystemClock};
 pub use error::Error;
 
 use serde::{Deserialize, Serialize};
@@ -57,6 +59,18 @@ impl Display for VarReaderTypes {
     }
 }
 
+// What the host lets a client change in the shared aircraft
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientRole {
+    #[default]
+    Full,
+    // Everything except the flight controls, even when given control
+    Avionics,
+    // Nothing they send is applied
+    ViewOnly,
+}
+
 // Name of variable and the value of it
 pub type VarMap = HashMap<String, VarReaderTypes>;
 // Name of the event the DWORD data associated with it with how many times it got triggered (not a map as the event could've got triggered multiple times before the data could get send)
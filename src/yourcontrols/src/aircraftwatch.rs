@@ -0,0 +1,203 @@
+use simconnect::SimConnector;
+
+use crate::util::read_c_string;
+
+const AIRCRAFT_LOADED_EVENT_ID: u32 = 100_001;
+const AIRCRAFT_LOADED_REQUEST_ID: u32 = 5831;
+// Asked outside of a session, when no config was picked to start one
+const LOADED_AIRCRAFT_QUERY_ID: u32 = 5832;
+
+pub enum AircraftChange {
+    // Folder name of the aircraft that was loaded instead
+    Switched(String),
+    Restored,
+}
+
+// Notices a different aircraft being loaded mid-session, as its vars won't line up with the loaded definitions
+pub struct AircraftWatch {
+    session_aircraft: Option<String>,
+    switched: bool,
+}
+
+impl AircraftWatch {
+    pub fn new() -> Self {
+        Self {
+            session_aircraft: None,
+            switched: false,
+        }
+    }
+
+    pub fn on_connected(&self, conn: &SimConnector) {
+        conn.subscribe_to_system_event(AIRCRAFT_LOADED_EVENT_ID, "AircraftLoaded");
+        // What is loaded right now becomes the session's aircraft
+        conn.request_system_state(AIRCRAFT_LOADED_REQUEST_ID, "AircraftLoaded");
+    }
+
+    pub fn process_event_filename(
+        &mut self,
+        data: &simconnect::SIMCONNECT_RECV_EVENT_FILENAME,
+    ) -> Option<AircraftChange> {
+        if data._base.uEventID != AIRCRAFT_LOADED_EVENT_ID {
+            return None;
+        }
+
+        let file_name = data.szFileName;
+        self.on_aircraft_loaded(read_c_string(&file_name))
+    }
+
+    pub fn process_system_state(
+        &mut self,
+        data: &simconnect::SIMCONNECT_RECV_SYSTEM_STATE,
+    ) -> Option<AircraftChange> {
+        if data.dwRequestID != AIRCRAFT_LOADED_REQUEST_ID {
+            return None;
+        }
+
+        let path = data.szString;
+        self.on_aircraft_loaded(read_c_string(&path))
+    }
+
+    // The answer comes back through process_query
+    pub fn query_loaded(&self, conn: &SimConnector) {
+        conn.request_system_state(LOADED_AIRCRAFT_QUERY_ID, "AircraftLoaded");
+    }
+
+    // Folder name of the aircraft loaded in the sim
+    pub fn process_query(&self, data: &simconnect::SIMCONNECT_RECV_SYSTEM_STATE) -> Option<String> {
+        if data.dwRequestID != LOADED_AIRCRAFT_QUERY_ID {
+            return None;
+        }
+
+        let path = data.szString;
+        Some(get_aircraft_folder(&read_c_string(&path)).to_string())
+    }
+
+    fn on_aircraft_loaded(&mut self, path: String) -> Option<AircraftChange> {
+        let session_aircraft = match self.session_aircraft.as_ref() {
+            Some(aircraft) => aircraft,
+            None => {
+                self.session_aircraft = Some(path);
+                return None;
+            }
+        };
+
+        // Loading a new flight in the same aircraft fires this too
+        let switched = !session_aircraft.eq_ignore_ascii_case(&path);
+        if switched == self.switched {
+            return None;
+        }
+
+        self.switched = switched;
+
+        if switched {
+            Some(AircraftChange::Switched(
+                get_aircraft_folder(&path).to_string(),
+            ))
+        } else {
+            Some(AircraftChange::Restored)
+        }
+    }
+
+    // Folder name of the aircraft the session started with, once the sim has said
+    pub fn get_session_aircraft(&self) -> Option<&str> {
+        self.session_aircraft.as_deref().map(get_aircraft_folder)
+    }
+
+    // The session ended, the next aircraft loaded starts a new one
+    pub fn reset(&mut self) {
+        self.session_aircraft = None;
+        self.switched = false;
+    }
+}
+
+// SimObjects\Airplanes\Asobo_C172SP_AS1000\aircraft.CFG -> Asobo_C172SP_AS1000
+fn get_aircraft_folder(path: &str) -> &str {
+    let mut parts = path.rsplit(|x| x == '\\' || x == '/').skip(1);
+    parts.next().filter(|x| !x.is_empty()).unwrap_or(path)
+}
+
+// Asobo_C172SP_AS1000 -> asobo, c, 172, sp, as, 1000
+fn get_tokens(name: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for c in name.chars() {
+        let split = match current.chars().last() {
+            Some(last) => !c.is_alphanumeric() || last.is_ascii_digit() != c.is_ascii_digit(),
+            None => false,
+        };
+
+        if split {
+            tokens.push(std::mem::take(&mut current));
+        }
+
+        if c.is_alphanumeric() {
+            current.extend(c.to_lowercase());
+        }
+    }
+    tokens.push(current);
+
+    // Single letters like the C in C172 match nearly everything
+    tokens.retain(|x| x.chars().count() > 1 || x.chars().all(|c| c.is_ascii_digit()));
+    tokens.dedup();
+    tokens
+}
+
+// Configs sharing the most words with the aircraft's folder name, best first. Words found in fewer configs count for more.
+pub fn suggest_configs(aircraft: &str, configs: &[String], count: usize) -> Vec<String> {
+    let aircraft_tokens = get_tokens(aircraft);
+    let config_tokens: Vec<Vec<String>> = configs
+        .iter()
+        .map(|x| get_tokens(x.trim_end_matches(".yaml")))
+        .collect();
+
+    let mut scored: Vec<(f64, &String)> = configs
+        .iter()
+        .zip(config_tokens.iter())
+        .map(|(config, tokens)| {
+            let score = tokens
+                .iter()
+                .filter(|x| aircraft_tokens.contains(x))
+                .map(|token| {
+                    let used_by = config_tokens.iter().filter(|x| x.contains(token)).count();
+                    1.0 / used_by as f64
+                })
+                .sum();
+            (score, config)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    scored
+        .into_iter()
+        .take(count)
+        .map(|(_, config)| config.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suggest_configs() {
+        let configs: Vec<String> = vec![
+            "Asobo Studio - Cessna 172SP Skyhawk (G1000).yaml",
+            "Asobo Studio - Cessna 152.yaml",
+            "Asobo Studio - Airbus A320neo.yaml",
+            "FlyByWire - Airbus A32NX.yaml",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let suggestions = suggest_configs("Asobo_C172SP_AS1000", &configs, 3);
+        assert_eq!(suggestions[0], configs[0]);
+
+        let suggestions = suggest_configs("Asobo_A320_NEO", &configs, 3);
+        assert_eq!(suggestions[0], configs[2]);
+
+        assert!(suggest_configs("Unknown", &configs, 3).is_empty());
+    }
+}
@@ -1,12 +1,19 @@
+use crate::metrics::SessionMetrics;
 use crate::simconfig;
+use crate::tray::{self, Tray};
+use crate::util::get_name_color;
 
 use base64::Engine;
 use crossbeam_channel::{unbounded, Receiver, TryRecvError};
-use laminar::Metrics;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
-use std::{io::Read, net::IpAddr};
+use std::io::Read;
+use std::time::{Duration, Instant};
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering::SeqCst},
@@ -14,8 +21,10 @@ use std::{
     },
     thread,
 };
+use yourcontrols_net::ConnectionStage;
+use yourcontrols_types::ClientRole;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub enum ConnectionMethod {
     Direct,
@@ -23,6 +32,29 @@ pub enum ConnectionMethod {
     CloudServer,
 }
 
+// How a session actually ended up connected, which can differ from the method picked
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectionPath {
+    Direct,
+    // Direct with the port forwarded through UPnP
+    DirectUpnp,
+    // Hole punched through the cloud server, then peer to peer
+    CloudServer,
+    // Everything goes through the cloud server
+    Relayed,
+}
+
+impl ConnectionPath {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionPath::Direct => "connection_path_direct",
+            ConnectionPath::DirectUpnp => "connection_path_direct_upnp",
+            ConnectionPath::CloudServer => "connection_path_cloud_server",
+            ConnectionPath::Relayed => "connection_path_relayed",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum AppMessage {
@@ -38,7 +70,8 @@ pub enum AppMessage {
         username: String,
         session_id: Option<String>,
         isipv6: bool,
-        ip: Option<IpAddr>,
+        // Kept as text so link-local IPv6 addresses can carry their zone, e.g. fe80::1%12
+        ip: Option<String>,
         hostname: Option<String>,
         port: Option<u16>,
         method: ConnectionMethod,
@@ -50,17 +83,81 @@ pub enum AppMessage {
         target: String,
         is_observer: bool,
     },
+    SetPeerMuted {
+        target: String,
+        is_muted: bool,
+    },
+    SetPeerTag {
+        target: String,
+        tag: Option<String>,
+    },
+    SetPeerRole {
+        target: String,
+        role: ClientRole,
+    },
+    // Sends to everyone if no target is given
+    ResendDefinitions {
+        target: Option<String>,
+    },
     LoadAircraft {
         config_file_name: String,
     },
+    // Checked as the direct connect form is typed in
+    ValidateAddress {
+        address: String,
+        port: String,
+    },
     Disconnect,
+    // Gives up on a connection that is still being set up
+    CancelConnect,
     Startup,
     RunUpdater,
     ForceTakeControl,
+    // Host stops observing and takes control no matter who has it
+    ReclaimControl,
+    // Controller sends everything again so everyone matches its aircraft
+    PushFullState,
+    CopyDiagnostics,
     UpdateConfig {
         new_config: simconfig::Config,
     },
-    GoObserver
+    GoObserver,
+    // Host starts or stops sending its position for everyone to follow
+    SetLeading {
+        is_leading: bool,
+    },
+    // Connect again using the last session that was joined
+    RejoinLast,
+    // Connect again with the same details after the connection dropped
+    Reconnect,
+    // From the tray menu
+    ToggleWindow,
+    // Same as the transfer control hotkey
+    TransferControlToNext,
+    // Disconnect from the tray, the window asks first if confirm_disconnect is on
+    RequestDisconnect,
+    // Shareable string of the community relevant settings
+    ExportConfig,
+    ImportConfig {
+        text: String,
+    },
+}
+
+// Status updates where only the latest matters are dispatched at most this often
+const COALESCE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct Coalesced {
+    last_sent: Option<Instant>,
+    pending: Option<String>,
+}
+
+impl Coalesced {
+    fn is_waiting(&self) -> bool {
+        self.last_sent
+            .map(|t| t.elapsed() < COALESCE_INTERVAL)
+            .unwrap_or(false)
+    }
 }
 
 fn get_message_str(type_string: &str, data: &str) -> String {
@@ -74,11 +171,29 @@ pub struct App {
     app_handle: Arc<Mutex<Option<web_view::Handle<i32>>>>,
     exited: Arc<AtomicBool>,
     rx: Receiver<AppMessage>,
+    coalesced: RefCell<HashMap<&'static str, Coalesced>>,
+    title: String,
+    _tray: Tray,
+}
+
+// Copies of the UI files placed here are used instead of the built in ones, so the UI can be themed
+const UI_OVERRIDE_PATH: &str = "web/";
+
+fn load_ui_file(file_name: &str, embedded: &'static str) -> Cow<'static, str> {
+    match std::fs::read_to_string(format!("{}{}", UI_OVERRIDE_PATH, file_name)) {
+        Ok(contents) => {
+            info!("[UI] Loaded {} from disk", file_name);
+            Cow::Owned(contents)
+        }
+        Err(_) => Cow::Borrowed(embedded),
+    }
 }
 
 impl App {
     pub fn setup(title: String) -> Self {
         let (tx, rx) = unbounded();
+        let tray = Tray::start(title.clone(), tx.clone());
+        let window_title = title.clone();
 
         let mut logo = vec![];
         File::open("assets/logo.png")
@@ -111,21 +226,34 @@ impl App {
                     {jquery}
                     {bootstrapjs}
                     {js1}
+                    {js2}
                     {js}
                 </script>
                 </html>
             "##,
-                    css = include_str!("../web/stylesheet.css"),
-                    js = include_str!("../web/main.js"),
-                    js1 = include_str!("../web/list.js"),
-                    body = include_str!("../web/index.html"),
-                    jquery = include_str!("../web/jquery.min.js"),
-                    bootstrapjs = include_str!("../web/bootstrap.bundle.min.js"),
-                    bootstrapcss = include_str!("../web/bootstrap.min.css"),
+                    css = load_ui_file("stylesheet.css", include_str!("../web/stylesheet.css")),
+                    js = load_ui_file("main.js", include_str!("../web/main.js")),
+                    js1 = load_ui_file("list.js", include_str!("../web/list.js")),
+                    js2 = load_ui_file("lang.js", include_str!("../web/lang.js")),
+                    body = load_ui_file("index.html", include_str!("../web/index.html")),
+                    jquery = load_ui_file("jquery.min.js", include_str!("../web/jquery.min.js")),
+                    bootstrapjs = load_ui_file(
+                        "bootstrap.bundle.min.js",
+                        include_str!("../web/bootstrap.bundle.min.js")
+                    ),
+                    bootstrapcss = load_ui_file(
+                        "bootstrap.min.css",
+                        include_str!("../web/bootstrap.min.css")
+                    ),
                     logo = base64::engine::general_purpose::STANDARD_NO_PAD.encode(logo.as_slice())
                 )))
                 .invoke_handler(move |_, arg| {
-                    tx.try_send(serde_json::from_str(arg).unwrap()).ok();
+                    match serde_json::from_str(arg) {
+                        Ok(message) => {
+                            tx.try_send(message).ok();
+                        }
+                        Err(e) => error!("[UI] Invalid message {}: {}", arg, e),
+                    }
 
                     Ok(())
                 })
@@ -148,9 +276,22 @@ impl App {
             app_handle: handle,
             exited,
             rx,
+            coalesced: RefCell::new(HashMap::new()),
+            title: window_title,
+            _tray: tray,
         }
     }
 
+    pub fn toggle_window(&self) {
+        tray::toggle_window(&self.title);
+    }
+
+    // Shown so the disconnect confirmation can be seen
+    pub fn request_disconnect(&self) {
+        tray::show_window(&self.title);
+        self.invoke("request_disconnect", None);
+    }
+
     pub fn exited(&self) -> bool {
         self.exited.load(SeqCst)
     }
@@ -179,10 +320,67 @@ impl App {
             .ok();
     }
 
+    // Holds back updates that come in faster than the webview needs them, keeping only the newest
+    fn invoke_latest(&self, type_string: &'static str, data: String) {
+        let mut coalesced = self.coalesced.borrow_mut();
+        let entry = coalesced.entry(type_string).or_default();
+
+        if entry.is_waiting() {
+            entry.pending = Some(data);
+            return;
+        }
+
+        entry.last_sent = Some(Instant::now());
+        entry.pending = None;
+        drop(coalesced);
+
+        self.invoke(type_string, Some(&data));
+    }
+
+    // Dispatches whatever invoke_latest held back once its interval is up
+    pub fn flush(&self) {
+        let mut to_send = Vec::new();
+
+        for (type_string, entry) in self.coalesced.borrow_mut().iter_mut() {
+            if entry.is_waiting() {
+                continue;
+            }
+
+            if let Some(data) = entry.pending.take() {
+                entry.last_sent = Some(Instant::now());
+                to_send.push((*type_string, data));
+            }
+        }
+
+        for (type_string, data) in to_send {
+            self.invoke(type_string, Some(&data));
+        }
+    }
+
+    // Held back session updates would otherwise show up after the UI was reset
+    fn drop_pending(&self) {
+        for entry in self.coalesced.borrow_mut().values_mut() {
+            entry.pending = None;
+        }
+    }
+
     pub fn error(&self, msg: &str) {
         self.invoke("error", Some(msg));
     }
 
+    pub fn definitions_missing(&self, expected_path: &str) {
+        self.invoke("definitions_missing", Some(expected_path));
+    }
+
+    // Where the config file is and why it couldn't be written
+    pub fn config_not_saved(&self, reason: &str) {
+        self.invoke("config_not_saved", Some(reason));
+    }
+
+    pub fn state_pushed(&self) {
+        self.invoke("state_pushed", None);
+    }
+
     pub fn attempt(&self) {
         self.invoke("attempt", None);
     }
@@ -191,11 +389,58 @@ impl App {
         self.invoke("connected", None);
     }
 
+    pub fn connection_path(&self, path: ConnectionPath) {
+        self.invoke("connection_path", Some(path.as_str()));
+    }
+
+    pub fn connection_stage(&self, stage: ConnectionStage) {
+        self.invoke("connection_stage", Some(stage.as_str()));
+    }
+
+    // Joined and caught up with the host's aircraft
+    pub fn synced(&self) {
+        self.invoke("synced", None);
+    }
+
+    // Shown once after connecting, the other fields are left out when unknown
+    pub fn connection_summary(
+        &self,
+        players: &[String],
+        in_control: Option<&str>,
+        has_control: bool,
+        aircraft: Option<&str>,
+        path: Option<ConnectionPath>,
+    ) {
+        self.invoke(
+            "connection_summary",
+            Some(
+                &json!({
+                    "players": players,
+                    "inControl": in_control,
+                    "hasControl": has_control,
+                    "aircraft": aircraft,
+                    "path": path.map(|x| x.as_str()),
+                })
+                .to_string(),
+            ),
+        );
+    }
+
     pub fn server_fail(&self, reason: &str) {
+        self.drop_pending();
         self.invoke("server_fail", Some(reason));
     }
 
+    // Reasons the address or port are invalid, None if they're fine
+    pub fn address_validated(&self, address: Option<String>, port: Option<String>) {
+        self.invoke(
+            "address_validated",
+            Some(&json!({ "address": address, "port": port }).to_string()),
+        );
+    }
+
     pub fn client_fail(&self, reason: &str) {
+        self.drop_pending();
         self.invoke("client_fail", Some(reason));
     }
 
@@ -207,6 +452,21 @@ impl App {
         self.invoke("lostcontrol", None);
     }
 
+    pub fn port_forward_failed(&self, reason: &str) {
+        self.invoke("upnp_fail", Some(reason));
+    }
+
+    pub fn write_failed(&self, var_names: &[&String]) {
+        self.invoke(
+            "write_fail",
+            Some(&serde_json::to_string(var_names).unwrap()),
+        );
+    }
+
+    pub fn name_assigned(&self, name: &str) {
+        self.invoke("name_assigned", Some(name));
+    }
+
     pub fn server_started(&self) {
         self.invoke("server", None);
     }
@@ -215,8 +475,79 @@ impl App {
         self.invoke("session", Some(code));
     }
 
+    pub fn sim_waiting(&self) {
+        self.invoke("sim_waiting", None);
+    }
+
+    pub fn sim_reconnected(&self) {
+        self.invoke("sim_reconnected", None);
+    }
+
+    pub fn aircraft_switched(&self, aircraft: &str) {
+        self.invoke("aircraft_switched", Some(aircraft));
+    }
+
+    pub fn aircraft_restored(&self) {
+        self.invoke("aircraft_restored", None);
+    }
+
+    pub fn flight_plan_loaded(&self, name: &str) {
+        self.invoke("flight_plan_loaded", Some(name));
+    }
+
+    pub fn set_session_time(&self, seconds: u64) {
+        self.invoke_latest("session_time", seconds.to_string());
+    }
+
+    pub fn set_sync_rate(&self, requested: u64, achieved: f64) {
+        self.invoke_latest(
+            "sync_rate",
+            json!({"requested": requested, "achieved": achieved.round() as u64}).to_string(),
+        );
+    }
+
+    pub fn following(&self, leader: &str) {
+        self.invoke("following", Some(leader));
+    }
+
+    pub fn stopped_following(&self) {
+        self.invoke("stopped_following", None);
+    }
+
+    pub fn set_local_address(&self, addr: &str) {
+        self.invoke("local_address", Some(addr));
+    }
+
     pub fn new_connection(&self, name: &str) {
-        self.invoke("newconnection", Some(name));
+        self.invoke(
+            "newconnection",
+            Some(
+                json!({
+                    "name": name,
+                    "color": get_name_color(name)
+                })
+                .to_string()
+                .as_str(),
+            ),
+        );
+    }
+
+    pub fn set_peer_tag(&self, name: &str, tag: Option<&str>) {
+        self.invoke(
+            "set_tag",
+            Some(json!({"name": name, "tag": tag}).to_string().as_str()),
+        );
+    }
+
+    pub fn set_role(&self, name: &str, role: ClientRole) {
+        self.invoke(
+            "set_role",
+            Some(json!({"name": name, "role": role}).to_string().as_str()),
+        );
+    }
+
+    pub fn control_unassigned(&self, name: &str) {
+        self.invoke("control_unassigned", Some(name));
     }
 
     pub fn lost_connection(&self, name: &str) {
@@ -231,6 +562,11 @@ impl App {
         }
     }
 
+    // Observing after handing off control, unlike observing() control can still be taken
+    pub fn auto_observing(&self) {
+        self.invoke("auto_observing", None);
+    }
+
     pub fn set_observing(&self, name: &str, observing: bool) {
         if observing {
             self.invoke("set_observing", Some(name));
@@ -239,6 +575,42 @@ impl App {
         }
     }
 
+    pub fn set_afk(&self, name: &str, is_afk: bool) {
+        if is_afk {
+            self.invoke("set_afk", Some(name));
+        } else {
+            self.invoke("set_not_afk", Some(name));
+        }
+    }
+
+    pub fn set_overloaded(&self, overloaded: bool) {
+        if overloaded {
+            self.invoke("overloaded", None);
+        } else {
+            self.invoke("stable", None);
+        }
+    }
+
+    pub fn set_all_ready(&self, all_ready: bool) {
+        if all_ready {
+            self.invoke("all_ready", None);
+        } else {
+            self.invoke("not_all_ready", None);
+        }
+    }
+
+    pub fn motd(&self, message: &str) {
+        self.invoke("motd", Some(message));
+    }
+
+    pub fn can_reconnect(&self) {
+        self.invoke("can_reconnect", None);
+    }
+
+    pub fn controller_relieved(&self, name: &str) {
+        self.invoke("controller_relieved", Some(name));
+    }
+
     pub fn set_incontrol(&self, name: &str) {
         self.invoke("set_incontrol", Some(name));
     }
@@ -247,6 +619,18 @@ impl App {
         self.invoke("add_aircraft", Some(name));
     }
 
+    pub fn select_aircraft(&self, name: &str) {
+        self.invoke("select_aircraft", Some(name));
+    }
+
+    // Closest configs to the aircraft loaded in the sim, when none was selected
+    pub fn config_suggestions(&self, aircraft: &str, suggestions: &[String]) {
+        self.invoke(
+            "config_suggestions",
+            Some(&json!({ "aircraft": aircraft, "suggestions": suggestions }).to_string()),
+        );
+    }
+
     pub fn version(&self, version: &str) {
         self.invoke("version", Some(version))
     }
@@ -259,22 +643,24 @@ impl App {
         self.invoke("config_msg", Some(value));
     }
 
-    pub fn send_network(&self, metrics: &Metrics) {
-        self.invoke(
-            "metrics",
-            Some(
-                json!({
-                    "sentPackets": metrics.sent_packets,
-                    "receivePackets": metrics.received_packets,
-                    "sentBandwidth": metrics.sent_kbps,
-                    "receiveBandwidth": metrics.receive_kbps,
-                    "packetLoss": metrics.packet_loss,
-                    "ping": metrics.rtt/2.0
-                })
-                .to_string()
-                .as_str(),
-            ),
-        )
+    pub fn send_metrics(&self, metrics: &SessionMetrics) {
+        self.invoke_latest("metrics", serde_json::to_string(metrics).unwrap())
+    }
+
+    pub fn send_config_export(&self, text: &str) {
+        self.invoke("config_export", Some(text));
+    }
+
+    pub fn config_imported(&self, value: &str) {
+        self.invoke("config_imported", Some(value));
+    }
+
+    pub fn config_import_failed(&self, reason: &str) {
+        self.invoke("config_import_failed", Some(reason));
+    }
+
+    pub fn send_diagnostics(&self, report: &str) {
+        self.invoke("diagnostics", Some(report));
     }
 
     pub fn set_host(&self) {
@@ -1,13 +1,23 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use yourcontrols_types::ClientRole;
 
 #[derive(Default)]
 pub struct Client {
     pub observer_mode: bool,
     pub is_server: bool,
+    // Label assigned by the host for display in the roster
+    pub tag: Option<String>,
+    // Updates from this client are ignored locally while set
+    pub muted: bool,
+    // Finished loading and sent Ready
+    pub ready: bool,
+    // Which parts of the aircraft their updates may change
+    pub role: ClientRole,
 }
 
 pub struct ClientManager {
-    clients: HashMap<String, Client>,
+    // In the order they joined, so the roster doesn't shuffle
+    clients: IndexMap<String, Client>,
     current_control: Option<String>,
     next_control: Option<String>,
 }
@@ -15,7 +25,7 @@ pub struct ClientManager {
 impl ClientManager {
     pub fn new() -> Self {
         Self {
-            clients: HashMap::new(),
+            clients: IndexMap::new(),
             current_control: None,
             // Client joined "first", cycling to next "first" person after client leaves
             next_control: None,
@@ -57,7 +67,7 @@ impl ClientManager {
     }
 
     pub fn remove_client(&mut self, name: &str) {
-        self.clients.remove(name);
+        self.clients.shift_remove(name);
 
         if let Some(next_control) = self.next_control.as_ref() {
             if next_control == name {
@@ -66,8 +76,20 @@ impl ClientManager {
         }
     }
 
+    // Skips anyone not allowed to fly, in join order after the usual next client
     pub fn get_next_client_for_control(&self) -> Option<&String> {
-        self.next_control.as_ref()
+        self.next_control
+            .as_ref()
+            .filter(|name| self.can_take_control(name))
+            .or_else(|| {
+                self.clients.keys().find(|name| {
+                    self.can_take_control(name) && Some(*name) != self.current_control.as_ref()
+                })
+            })
+    }
+
+    pub fn get_client_names(&self) -> Vec<String> {
+        self.clients.keys().cloned().collect()
     }
 
     pub fn is_observer(&self, name: &str) -> bool {
@@ -83,6 +105,19 @@ impl ClientManager {
         }
     }
 
+    pub fn is_muted(&self, name: &str) -> bool {
+        if let Some(client) = self.clients.get(name) {
+            return client.muted;
+        }
+        false
+    }
+
+    pub fn set_muted(&mut self, name: &str, muted: bool) {
+        if let Some(client) = self.clients.get_mut(name) {
+            client.muted = muted;
+        }
+    }
+
     pub fn set_server(&mut self, name: &str, is_server: bool) {
         if let Some(client) = self.clients.get_mut(name) {
             client.is_server = is_server;
@@ -96,6 +131,69 @@ impl ClientManager {
         false
     }
 
+    pub fn set_tag(&mut self, name: &str, tag: Option<String>) {
+        if let Some(client) = self.clients.get_mut(name) {
+            client.tag = tag;
+        }
+    }
+
+    pub fn get_role(&self, name: &str) -> ClientRole {
+        if let Some(client) = self.clients.get(name) {
+            return client.role;
+        }
+        ClientRole::Full
+    }
+
+    pub fn set_role(&mut self, name: &str, role: ClientRole) {
+        if let Some(client) = self.clients.get_mut(name) {
+            client.role = role;
+        }
+    }
+
+    // Their role allows the flight controls and they aren't observing
+    pub fn can_take_control(&self, name: &str) -> bool {
+        self.get_role(name) == ClientRole::Full && !self.is_observer(name)
+    }
+
+    // Everyone the host has tagged
+    pub fn get_tags(&self) -> Vec<(String, String)> {
+        self.clients
+            .iter()
+            .filter_map(|(name, x)| x.tag.clone().map(|tag| (name.clone(), tag)))
+            .collect()
+    }
+
+    // Everyone the host has given something other than full control
+    pub fn get_restricted_roles(&self) -> Vec<(String, ClientRole)> {
+        self.clients
+            .iter()
+            .filter(|(_, x)| x.role != ClientRole::Full)
+            .map(|(name, x)| (name.clone(), x.role))
+            .collect()
+    }
+
+    pub fn set_ready(&mut self, name: &str) {
+        if let Some(client) = self.clients.get_mut(name) {
+            client.ready = true;
+        }
+    }
+
+    // Everyone flying has loaded in, observers aren't waited on
+    pub fn all_ready(&self) -> bool {
+        let mut flying = self
+            .clients
+            .values()
+            .filter(|x| !x.observer_mode)
+            .peekable();
+
+        flying.peek().is_some() && flying.all(|x| x.ready)
+    }
+
+    // Someone has loaded in, so a flight is under way
+    pub fn any_ready(&self) -> bool {
+        self.clients.values().any(|x| x.ready)
+    }
+
     pub fn reset(&mut self) {
         self.clients.clear();
         self.current_control = None;
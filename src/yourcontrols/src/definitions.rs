@@ -1,26 +1,34 @@
 use indexmap::IndexMap;
+use log::{info, warn};
 use serde::Deserialize;
 use serde_yaml::{self, Value};
 use simconnect::SimConnector;
 use std::collections::{hash_map, HashMap, HashSet, VecDeque};
-use std::fmt::{Debug, Display};
+use std::fmt::{Debug, Display, Write};
 use std::fs::File;
 use std::mem::swap;
 use std::path::Path;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::sync::gaugecommunicator::{GetResult, InterpolateData, InterpolationType};
+use crate::history::ReceiveHistory;
+use crate::sync::gaugecommunicator::{
+    GetResult, InterpolateData, InterpolationStats, InterpolationType,
+};
 use crate::sync::jscommunicator::{JSCommunicator, JSPayloads};
 use crate::sync::transfer::{AircraftVars, Events, LVarSyncer};
 use crate::syncdefs::MultiplyDifferenceLocalVarSet;
 use crate::syncdefs::ResetWhenEquals;
 use crate::syncdefs::{
-    CustomCalculator, NumDigitSet, NumIncrement, NumSet, Syncable, ToggleSwitch,
+    AutopilotModes, CustomCalculator, LatchedSwitch, NumDigitSet, NumIncrement, NumSet, Syncable,
+    ToggleSwitch,
 };
-use crate::util::{Category, InDataTypes};
+use crate::util::{get_bit, set_bit, Category, InDataTypes};
 use crate::{corrector::Corrector, syncdefs::LocalVarProxy};
 
-use yourcontrols_types::{AllNeedSync, Error, Event, EventData, VarMap, VarReaderTypes};
+use yourcontrols_types::{
+    AllNeedSync, ClientRole, Clock, Error, Event, EventData, SystemClock, VarMap, VarReaderTypes,
+};
 
 // Checks if a field in a Value exists, otherwise will return an error with the name of the field
 macro_rules! check_and_return_field {
@@ -72,29 +80,48 @@ macro_rules! execute_mapping {
                 ActionType::VarOnly => $var_only_action
                 _ => {}
             }
-            _ => {}
+
+            VarReaderTypes::I64(_) => match &mut $mapping.action {
+                ActionType::VarOnly => $var_only_action
+                _ => {}
+            }
         }
     }
 }
 
-fn set_did_write_recently(map: &mut HashMap<String, Instant>, data_name: &str) {
+// While slewing positions jump around, so interpolating them only drags the remote aircraft through the jumps
+const SLEW_VAR_NAME: &str = "IS SLEW ACTIVE";
+// Received position moving further than this in one update counts as a snap, about a kilometer
+const SNAP_DISTANCE_DEGREES: f64 = 0.01;
+// How far back received updates are kept to explain a snap
+const RECEIVE_HISTORY_SECONDS: u64 = 5;
+// Received autopilot modes get pressed again this often until the sim's match, in case one press changed more than expected
+const MODE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const MODE_RETRY_ATTEMPTS: u8 = 2;
+
+fn set_did_write_recently(map: &mut HashMap<String, Instant>, data_name: &str, now: Instant) {
     if let Some(instant) = map.get_mut(data_name) {
-        *instant = Instant::now();
+        *instant = now;
     } else {
-        map.insert(data_name.to_string(), Instant::now());
+        map.insert(data_name.to_string(), now);
     }
 }
 
-fn check_did_write_recently(map: &mut HashMap<String, Instant>, data_name: &str) -> bool {
+fn check_did_write_recently(
+    map: &mut HashMap<String, Instant>,
+    data_name: &str,
+    now: Instant,
+) -> bool {
     return map
         .get(data_name)
-        .map(|x| x.elapsed().as_secs() < 1)
+        .map(|x| now.saturating_duration_since(*x).as_secs() < 1)
         .unwrap_or(false);
 }
 
 fn get_data_type_from_string(string: &str) -> Result<InDataTypes, Error> {
     Ok(match string {
         "i32" => InDataTypes::I32,
+        "i64" => InDataTypes::I64,
         "f64" => InDataTypes::F64,
         "bool" => InDataTypes::Bool,
         _ => return Err(Error::MissingField("var_type")),
@@ -210,6 +237,7 @@ pub struct SyncPermission {
     pub is_server: bool,
     pub is_master: bool,
     pub is_init: bool,
+    pub role: ClientRole,
 }
 
 // Serde types
@@ -258,6 +286,28 @@ struct VarEntry {
     interpolate: Option<InterpolationType>,
     #[serde(default)]
     unreliable: bool,
+    // Always send reliably, even if interpolated
+    #[serde(default)]
+    reliable: bool,
+    // Steps between detents, so always written as received and never interpolated
+    #[serde(default)]
+    discrete: bool,
+    // Only sync this bit of a packed integer var
+    bit: Option<u8>,
+    #[serde(default)]
+    cancel_h_events: bool,
+}
+
+#[derive(Deserialize)]
+struct LatchedSwitchEntry {
+    var_name: String,
+    var_units: Option<String>,
+    // Sets the state directly, like PARKING_BRAKE_SET
+    event_name: String,
+    // Event parameters for on and off, 1 and 0 if not given
+    on_param: Option<u32>,
+    off_param: Option<u32>,
+    condition: Option<Condition>,
     #[serde(default)]
     cancel_h_events: bool,
 }
@@ -288,6 +338,8 @@ struct NumSetGenericEntry<T> {
     multiply_by: Option<T>,
     add_by: Option<T>,
     interpolate: Option<InterpolationType>,
+    // Interpolate only while observing, so visuals stay smooth without changing how control is synced
+    observer_interpolate: Option<InterpolationType>,
     #[serde(default)]
     use_calculator: bool,
     #[serde(default)]
@@ -299,6 +351,12 @@ struct NumSetGenericEntry<T> {
     swap_event_name: Option<String>,
     #[serde(default)]
     unreliable: bool,
+    // Always send reliably, even if interpolated
+    #[serde(default)]
+    reliable: bool,
+    // Steps between detents, so always written as received and never interpolated
+    #[serde(default)]
+    discrete: bool,
 }
 
 #[derive(Deserialize)]
@@ -380,6 +438,41 @@ struct ProgramActionEntry {
     action: ProgramAction,
 }
 
+#[derive(Deserialize)]
+struct AutopilotModeEntry {
+    var_name: String,
+    var_units: Option<String>,
+    // Momentary button that flips this mode
+    event_name: String,
+}
+
+// Modes of an autopilot panel synced as a whole, so everyone ends up with the same annunciations
+#[derive(Deserialize)]
+struct AutopilotModesEntry {
+    modes: Vec<AutopilotModeEntry>,
+    #[serde(default)]
+    use_calculator: bool,
+    condition: Option<Condition>,
+    #[serde(default)]
+    cancel_h_events: bool,
+}
+
+// Local vars of an instrument cluster that change every frame, only sent all together every so often
+#[derive(Deserialize)]
+struct LocalVarGroupEntry {
+    vars: Vec<String>,
+    var_units: Option<String>,
+    update_every: f64,
+    #[serde(default)]
+    unreliable: bool,
+}
+
+// Vars of other entries that have to be applied in this order when they arrive together, like an engine start
+#[derive(Deserialize)]
+struct SequenceEntry {
+    vars: Vec<String>,
+}
+
 #[derive(Deserialize)]
 struct ProgramActionEventEntry {
     event_name: String,
@@ -418,18 +511,18 @@ impl Period {
         }
     }
 
-    fn do_update(&mut self) -> bool {
+    fn do_update(&mut self, now: Instant) -> bool {
         match self.last_update {
             Some(time) => {
-                if time.elapsed().as_secs_f64() >= self.time {
-                    self.last_update = Some(Instant::now());
+                if now.saturating_duration_since(time).as_secs_f64() >= self.time {
+                    self.last_update = Some(now);
                     true
                 } else {
                     false
                 }
             }
             None => {
-                self.last_update = Some(Instant::now());
+                self.last_update = Some(now);
                 true
             }
         }
@@ -452,6 +545,34 @@ impl Default for Mapping {
     }
 }
 
+// Changed values of a local var group waiting for the group's next turn to be sent
+struct VarGroup {
+    period: Period,
+    pending: VarMap,
+}
+
+// A single bit of a packed aircraft var, synced as its own bool
+struct BitVar {
+    name: String,
+    bit: u8,
+}
+
+fn get_packed_value(value: &VarReaderTypes) -> Option<i64> {
+    match value {
+        VarReaderTypes::I32(v) => Some(*v as i64),
+        VarReaderTypes::I64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn set_packed_bit(value: VarReaderTypes, bit: u8, on: bool) -> VarReaderTypes {
+    match value {
+        VarReaderTypes::I32(v) => VarReaderTypes::I32(set_bit(v as i64, bit, on) as i32),
+        VarReaderTypes::I64(v) => VarReaderTypes::I64(set_bit(v, bit, on)),
+        _ => value,
+    }
+}
+
 pub struct Definitions {
     // Serializable vec that houses all the definitions that can be sent over the network
     definitions_buffer: IndexMap<String, Vec<Value>>,
@@ -481,12 +602,78 @@ pub struct Definitions {
     event_cancel_timer: Instant,
     // Vars that shouldn't be sent reliably
     unreliable_vars: HashSet<String>,
+    // Vars that must be sent reliably, overriding interpolation and unreliable
+    reliable_vars: HashSet<String>,
     // Vars that should not be sent over the network
     do_not_sync: HashSet<String>,
     // Vars that need interpolation
     interpolate_vars: HashSet<String>,
+    // Vars that only get interpolated while observing
+    observer_interpolate_vars: HashSet<String>,
+    // Vars that are never interpolated, even if another entry asks for it
+    discrete_vars: HashSet<String>,
+    is_observing: bool,
     // For indicating that an event has been triggered and the control should be transferred to the next person
     pending_action: Option<ProgramAction>,
+    // Whether interpolated vars were last written directly because of slew mode
+    snapping_for_slew: bool,
+    // Write interpolated vars as they arrive, for connections fast enough not to need smoothing
+    interpolation_disabled: bool,
+    // Every var goes over the reliable channel, whatever its entry says
+    reliable_only: bool,
+    // Received vars that could not be written to the sim since the last report, and how many times
+    write_failures: HashMap<String, u32>,
+    // Recently received updates, and what they looked like when the aircraft last snapped
+    receive_history: ReceiveHistory,
+    last_received_position: Option<(f64, f64)>,
+    snap_report: Option<String>,
+    // Last time the local pilot triggered an event or interacted with the cockpit
+    last_local_input: Instant,
+    // Vars and events the aircraft declares as pilot activity, any local event counts when empty
+    activity_names: HashSet<String>,
+    // Events and L: vars the aircraft freezes with instead of the default freeze events
+    freeze_method: Vec<String>,
+    // Packed aircraft vars and the bits in them that are synced individually
+    bitmask_vars: HashMap<String, Vec<BitVar>>,
+    // Synced bit name to its packed var and bit index
+    bit_owners: HashMap<String, (String, u8)>,
+    // Last known state of every synced bit, to only send the ones that changed
+    bit_values: HashMap<String, bool>,
+    // Where all the timers above get the time from
+    clock: Arc<dyn Clock>,
+    // Entries from an override file waiting to replace the base entry with the same name, and their category
+    overrides: IndexMap<String, (String, Value)>,
+    // Names already replaced by an override, further base entries for them are skipped
+    overridden: HashSet<String>,
+    // Only send vars that moved away from the value everyone last saw
+    event_driven: bool,
+    // Last value sent or received for every var, what the others should currently have
+    last_shared: HashMap<String, VarReaderTypes>,
+    // Tags given to entries, so a whole class of them (e.g. sounds) can be turned off
+    tags: HashMap<String, Vec<String>>,
+    // Tags the user doesn't want synced
+    disabled_tags: HashSet<String>,
+    // Bounds received values are kept within, either can be left out
+    clamps: HashMap<String, (Option<f64>, Option<f64>)>,
+    // How far a number has to move from the last shared value to be sent again, for slow moving vars like fuel
+    tolerances: HashMap<String, f64>,
+    // Decimal places numbers are rounded to before being sent, for vars that don't need full precision
+    precisions: HashMap<String, i32>,
+    // Vars whose actions work the opposite way to the value, like a switch that reads 1 when off
+    inverted_vars: HashSet<String>,
+    // Changes to vars with their own period that came in before it was up
+    held_avars: VarMap,
+    // Received vars whose last value was NaN or infinite, so each only gets logged once
+    non_finite_vars: HashSet<String>,
+    // Bitfields of autopilot modes, and the received ones still being converged on with attempts and last attempt
+    mode_vars: HashSet<String>,
+    mode_retries: HashMap<String, (f64, u8, Instant)>,
+    // Local vars that are sent together at a reduced rate, and which group each var is in
+    var_groups: Vec<VarGroup>,
+    var_group_index: HashMap<String, usize>,
+    // Sequence and step in it of every var that has to be applied in order
+    sequence_steps: HashMap<String, (usize, usize)>,
+    sequence_count: usize,
 }
 
 fn get_category_from_string(category: &str) -> Result<Category, Error> {
@@ -499,6 +686,131 @@ fn get_category_from_string(category: &str) -> Result<Category, Error> {
     }
 }
 
+// Override entries replace base entries that share this name
+fn get_override_key(value: &Value) -> Option<String> {
+    value
+        .get("var_name")
+        .or_else(|| value.get("event_name"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+// Entries of lists like activity and freeze are plain names
+fn get_list_name(value: &Value, key: &'static str) -> Result<String, Error> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or(Error::MissingField(key))
+}
+
+// The name an entry ends up synced under, aircraft vars lose their prefix
+fn get_tag_key(value: &Value) -> Option<String> {
+    let name = get_override_key(value)?;
+
+    if !name.starts_with("A:") {
+        return Some(name);
+    }
+
+    let name = get_real_var_name(&name);
+
+    Some(match value.get("bit").and_then(Value::as_u64) {
+        Some(bit) => format!("{}#{}", name, bit),
+        None => name,
+    })
+}
+
+// How far a number has to move from the last shared value to be sent again in event driven mode
+const EVENT_DRIVEN_TOLERANCE: f64 = 0.0001;
+
+fn changed_beyond_tolerance(
+    last: Option<&VarReaderTypes>,
+    value: &VarReaderTypes,
+    tolerance: f64,
+) -> bool {
+    match (last, value) {
+        (Some(VarReaderTypes::F64(last)), VarReaderTypes::F64(value)) => {
+            (last - value).abs() > tolerance
+        }
+        (Some(last), value) => last != value,
+        (None, _) => true,
+    }
+}
+
+fn round_to_precision(value: &mut VarReaderTypes, precision: i32) {
+    if let VarReaderTypes::F64(value) = value {
+        let scale = 10f64.powi(precision);
+        let rounded = (*value * scale).round() / scale;
+        // Too big to have any decimals to lose
+        if rounded.is_finite() {
+            *value = rounded;
+        }
+    }
+}
+
+// On becomes off and the other way around, any non zero number counts as on
+fn invert_value(value: &VarReaderTypes) -> VarReaderTypes {
+    match *value {
+        VarReaderTypes::Bool(value) => VarReaderTypes::Bool(!value),
+        VarReaderTypes::I32(value) => VarReaderTypes::I32((value == 0) as i32),
+        VarReaderTypes::I64(value) => VarReaderTypes::I64((value == 0) as i64),
+        VarReaderTypes::F64(value) => VarReaderTypes::F64((value == 0.0) as i32 as f64),
+    }
+}
+
+fn is_finite(value: &VarReaderTypes) -> bool {
+    match value {
+        VarReaderTypes::F64(value) => value.is_finite(),
+        _ => true,
+    }
+}
+
+// Sums to 1 for the first mode being on, 2 for the second, 4 for the third and so on
+fn get_modes_calculator(modes: &[AutopilotModeEntry]) -> String {
+    let mut calculator = "0".to_string();
+
+    for (index, mode) in modes.iter().enumerate() {
+        let var = if mode.var_name.starts_with("A:") {
+            format!(
+                "({}, {})",
+                mode.var_name,
+                mode.var_units.as_deref().unwrap_or("Bool")
+            )
+        } else {
+            format!("({})", mode.var_name)
+        };
+
+        write!(calculator, " {} 0 != {} * +", var, 1u32 << index).ok();
+    }
+
+    calculator
+}
+
+// The value pulled back within bounds, None if it was already within them
+fn clamp_value(
+    value: &VarReaderTypes,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Option<VarReaderTypes> {
+    let number = match value {
+        VarReaderTypes::Bool(_) => return None,
+        value => value.get_as_f64(),
+    };
+
+    let clamped = match (min, max) {
+        (Some(min), _) if number < min => min,
+        (_, Some(max)) if number > max => max,
+        // NaN fails every comparison, so pull it to whichever bound there is
+        _ if number.is_nan() => min.or(max)?,
+        _ => return None,
+    };
+
+    Some(match value {
+        VarReaderTypes::I32(_) => VarReaderTypes::I32(clamped as i32),
+        VarReaderTypes::I64(_) => VarReaderTypes::I64(clamped as i64),
+        _ => VarReaderTypes::F64(clamped),
+    })
+}
+
 fn get_real_var_name(var_name: &str) -> String {
     if var_name.as_bytes()[1] == b':' {
         var_name[2..].to_string()
@@ -509,6 +821,10 @@ fn get_real_var_name(var_name: &str) -> String {
 
 impl Definitions {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         Self {
             definitions_buffer: IndexMap::new(),
             mappings: HashMap::new(),
@@ -523,21 +839,61 @@ impl Definitions {
 
             current_sync: AllNeedSync::new(),
             event_queue: VecDeque::new(),
-            event_timer: Instant::now(),
-            event_cancel_timer: Instant::now(),
+            event_timer: clock.now(),
+            event_cancel_timer: clock.now(),
 
             unreliable_vars: HashSet::new(),
+            reliable_vars: HashSet::new(),
             do_not_sync: HashSet::new(),
 
             categories: HashMap::new(),
             periods: HashMap::new(),
             interpolate_vars: HashSet::new(),
+            observer_interpolate_vars: HashSet::new(),
+            discrete_vars: HashSet::new(),
+            is_observing: false,
 
             pending_action: None,
+            snapping_for_slew: false,
+            interpolation_disabled: false,
+            reliable_only: false,
+            write_failures: HashMap::new(),
+            receive_history: ReceiveHistory::new(Duration::from_secs(RECEIVE_HISTORY_SECONDS)),
+            last_received_position: None,
+            snap_report: None,
+            last_local_input: clock.now(),
+            activity_names: HashSet::new(),
+            freeze_method: Vec::new(),
+            bitmask_vars: HashMap::new(),
+            bit_owners: HashMap::new(),
+            bit_values: HashMap::new(),
+            clock,
+            overrides: IndexMap::new(),
+            overridden: HashSet::new(),
+            event_driven: false,
+            last_shared: HashMap::new(),
+            tags: HashMap::new(),
+            clamps: HashMap::new(),
+            tolerances: HashMap::new(),
+            precisions: HashMap::new(),
+            inverted_vars: HashSet::new(),
+            held_avars: VarMap::new(),
+            non_finite_vars: HashSet::new(),
+            mode_vars: HashSet::new(),
+            mode_retries: HashMap::new(),
+            disabled_tags: HashSet::new(),
+            var_groups: Vec::new(),
+            var_group_index: HashMap::new(),
+            sequence_steps: HashMap::new(),
+            sequence_count: 0,
         }
     }
 
     fn add_var(&mut self, category: &str, var: VarEntry) -> Result<(), Error> {
+        if let Some(bit) = var.bit {
+            return self.add_bit_var(category, var, bit);
+        }
+
         let (var_name, var_type) = self.add_var_string(
             category,
             &var.var_name,
@@ -545,8 +901,13 @@ impl Definitions {
             var.var_type.unwrap_or(InDataTypes::F64),
         )?;
 
+        let discrete = var.discrete;
+        if discrete {
+            self.discrete_vars.insert(var_name.clone());
+        }
+
         // Handle interpolation for this variable
-        if let Some(interpolate) = var.interpolate {
+        if let Some(interpolate) = var.interpolate.filter(|_| !discrete) {
             self.interpolate_vars.insert(var_name.clone());
 
             if std::matches!(var_type, VarType::AircraftVar) {
@@ -563,6 +924,10 @@ impl Definitions {
             self.unreliable_vars.insert(var_name.clone());
         }
 
+        if var.reliable {
+            self.reliable_vars.insert(var_name.clone());
+        }
+
         // Handle custom periods
         if let Some(period) = var.update_every {
             self.periods.insert(var_name.clone(), Period::new(period));
@@ -580,6 +945,62 @@ impl Definitions {
         Ok(())
     }
 
+    fn add_bit_var(&mut self, category: &str, var: VarEntry, bit: u8) -> Result<(), Error> {
+        let bit_count = match var.var_type {
+            Some(InDataTypes::I32) => 32,
+            Some(InDataTypes::I64) => 64,
+            _ => 0,
+        };
+
+        if bit >= bit_count || !var.var_name.starts_with("A:") {
+            return Err(Error::InvalidBit(var.var_name, bit));
+        }
+
+        let (packed_name, _) = self.add_var_string(
+            category,
+            &var.var_name,
+            var.var_units.as_deref(),
+            var.var_type.unwrap(),
+        )?;
+
+        let bit_name = format!("{}#{}", packed_name, bit);
+
+        self.categories
+            .insert(bit_name.clone(), get_category_from_string(category)?);
+
+        if var.unreliable {
+            self.unreliable_vars.insert(bit_name.clone());
+        }
+
+        if var.reliable {
+            self.reliable_vars.insert(bit_name.clone());
+        }
+
+        if let Some(period) = var.update_every {
+            self.periods.insert(bit_name.clone(), Period::new(period));
+        }
+
+        self.bitmask_vars
+            .entry(packed_name.clone())
+            .or_default()
+            .push(BitVar {
+                name: bit_name.clone(),
+                bit,
+            });
+        self.bit_owners.insert(bit_name.clone(), (packed_name, bit));
+
+        self.add_mapping(
+            bit_name,
+            Mapping {
+                action: ActionType::VarOnly,
+                condition: var.condition,
+                cancel_h_events: var.cancel_h_events,
+            },
+        )?;
+
+        Ok(())
+    }
+
     fn add_event(&mut self, category: &str, event: EventEntry) -> Result<(), Error> {
         let category = get_category_from_string(category)?;
 
@@ -745,6 +1166,39 @@ impl Definitions {
         Ok(())
     }
 
+    fn add_latched_switch(&mut self, category: &str, var: LatchedSwitchEntry) -> Result<(), Error> {
+        let event_id = self.events.get_or_map_event_id(&var.event_name, false);
+
+        let (var_string, var_type) = self.add_var_string(
+            category,
+            &var.var_name,
+            var.var_units.as_deref(),
+            InDataTypes::Bool,
+        )?;
+
+        let action = LatchedSwitch::new(
+            event_id,
+            var.on_param.unwrap_or(1),
+            var.off_param.unwrap_or(0),
+        );
+
+        let mapping = match var_type {
+            VarType::AircraftVar => ActionType::Bool(Box::new(action)),
+            VarType::LocalVar => ActionType::F64(Box::new(action)),
+        };
+
+        self.add_mapping(
+            var_string,
+            Mapping {
+                action: mapping,
+                condition: var.condition,
+                cancel_h_events: var.cancel_h_events,
+            },
+        )?;
+
+        Ok(())
+    }
+
     #[allow(clippy::type_complexity)]
     fn add_num_set_generic<T>(
         &mut self,
@@ -760,7 +1214,16 @@ impl Definitions {
         let (var_string, _) =
             self.add_var_string(category, &var.var_name, var.var_units.as_deref(), data_type)?;
 
-        if let Some(interpolate_type) = var.interpolate {
+        if var.reliable {
+            self.reliable_vars.insert(var_string.clone());
+        }
+
+        let discrete = var.discrete;
+        if discrete {
+            self.discrete_vars.insert(var_string.clone());
+        }
+
+        if let Some(interpolate_type) = var.interpolate.filter(|_| !discrete) {
             self.lvarstransfer.transfer.add_interpolate_mapping(
                 &format!("K:{}", &var.event_name),
                 var_string.clone(),
@@ -783,6 +1246,16 @@ impl Definitions {
                 self.unreliable_vars.insert(var_string.clone());
             }
 
+            if let Some(interpolate_type) = var.observer_interpolate.filter(|_| !discrete) {
+                self.lvarstransfer.transfer.add_interpolate_mapping(
+                    &format!("K:{}", &var.event_name),
+                    var_string.clone(),
+                    var.var_units.as_deref(),
+                    interpolate_type,
+                );
+                self.observer_interpolate_vars.insert(var_string.clone());
+            }
+
             if var.use_calculator || var.event_param.is_some() {
                 action.set_calculator_event_name(Some(&var.event_name), var.event_param.is_some())
             }
@@ -1074,6 +1547,100 @@ impl Definitions {
         Ok(())
     }
 
+    fn add_sequence(&mut self, sequence: SequenceEntry) {
+        let index = self.sequence_count;
+        self.sequence_count += 1;
+
+        for (step, var_name) in sequence.vars.into_iter().enumerate() {
+            let var_name = var_name
+                .strip_prefix("A:")
+                .map(str::to_string)
+                .unwrap_or(var_name);
+
+            // Interpolating or sending a step unreliably would let it land after the next one
+            self.discrete_vars.insert(var_name.clone());
+            self.sequence_steps.insert(var_name, (index, step));
+        }
+    }
+
+    fn add_autopilot_modes(
+        &mut self,
+        category: &str,
+        var: AutopilotModesEntry,
+    ) -> Result<(), Error> {
+        if let Some(mode) = var.modes.get(32) {
+            return Err(Error::InvalidBit(mode.var_name.clone(), 32));
+        }
+
+        let category = get_category_from_string(category)?;
+        let var_name = self
+            .lvarstransfer
+            .add_custom_var(get_modes_calculator(&var.modes));
+
+        let mut action = AutopilotModes::new();
+        for mode in var.modes {
+            let event_id = self.events.get_or_map_event_id(&mode.event_name, false);
+            action.add_mode(
+                event_id,
+                Some(mode.event_name).filter(|_| var.use_calculator),
+            );
+        }
+
+        self.categories.insert(var_name.clone(), category);
+        self.mode_vars.insert(var_name.clone());
+        self.add_mapping(
+            var_name,
+            Mapping {
+                action: ActionType::F64(Box::new(action)),
+                condition: var.condition,
+                cancel_h_events: var.cancel_h_events,
+            },
+        )
+    }
+
+    fn add_local_var_group(
+        &mut self,
+        category: &str,
+        group: LocalVarGroupEntry,
+    ) -> Result<(), Error> {
+        let index = self.var_groups.len();
+
+        for var_name in group.vars {
+            if !var_name.starts_with("L:") {
+                return Err(Error::NotLocalVar(var_name));
+            }
+
+            let (var_name, _) = self.add_var_string(
+                category,
+                &var_name,
+                group.var_units.as_deref(),
+                InDataTypes::F64,
+            )?;
+
+            if group.unreliable {
+                self.unreliable_vars.insert(var_name.clone());
+            }
+
+            self.var_group_index.insert(var_name.clone(), index);
+
+            self.add_mapping(
+                var_name,
+                Mapping {
+                    action: ActionType::VarOnly,
+                    condition: None,
+                    cancel_h_events: false,
+                },
+            )?;
+        }
+
+        self.var_groups.push(VarGroup {
+            period: Period::new(group.update_every),
+            pending: VarMap::new(),
+        });
+
+        Ok(())
+    }
+
     fn add_program_action_event(
         &mut self,
         category: &str,
@@ -1115,10 +1682,53 @@ impl Definitions {
         // self.check_other_common_fields(&value);
         let value_clone = value.clone();
 
+        if let (Some(name), Some(tags)) = (
+            get_tag_key(&value),
+            value.get("tags").and_then(Value::as_sequence),
+        ) {
+            let tags = tags
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_lowercase)
+                .collect();
+            self.tags.insert(name, tags);
+        }
+
+        let clamp = (
+            value.get("min").and_then(Value::as_f64),
+            value.get("max").and_then(Value::as_f64),
+        );
+        if let (Some(name), true) = (get_tag_key(&value), clamp != (None, None)) {
+            self.clamps.insert(name, clamp);
+        }
+
+        if let (Some(name), Some(tolerance)) = (
+            get_tag_key(&value),
+            value.get("tolerance").and_then(Value::as_f64),
+        ) {
+            self.tolerances.insert(name, tolerance);
+        }
+
+        if let (Some(name), Some(precision)) = (
+            get_tag_key(&value),
+            value.get("precision").and_then(Value::as_u64),
+        ) {
+            // f64 has no more than 15 to give
+            self.precisions.insert(name, precision.min(15) as i32);
+        }
+
+        if let (Some(name), Some(true)) = (
+            get_tag_key(&value),
+            value.get("invert").and_then(Value::as_bool),
+        ) {
+            self.inverted_vars.insert(name);
+        }
+
         match type_str.to_uppercase().as_str() {
             "VAR" => self.add_var(&category, try_cast_yaml!(value))?,
             "EVENT" => self.add_event(&category, try_cast_yaml!(value))?,
             "TOGGLESWITCH" => self.add_toggle_switch(&category, try_cast_yaml!(value))?,
+            "LATCHEDSWITCH" => self.add_latched_switch(&category, try_cast_yaml!(value))?,
             "NUMSET" => self.add_num_set(&category, try_cast_yaml!(value))?,
             "NUMINCREMENT" => self.add_num_increment(&category, try_cast_yaml!(value))?,
             "NUMDIGITSET" => self.add_num_digit_set(&category, try_cast_yaml!(value))?,
@@ -1132,6 +1742,9 @@ impl Definitions {
             "PROGRAMACTIONEVENT" => {
                 self.add_program_action_event(&category, try_cast_yaml!(value))?
             }
+            "LOCALVARGROUP" => self.add_local_var_group(&category, try_cast_yaml!(value))?,
+            "AUTOPILOTMODES" => self.add_autopilot_modes(&category, try_cast_yaml!(value))?,
+            "SEQUENCE" => self.add_sequence(try_cast_yaml!(value)),
             _ => return Err(Error::InvalidSyncType(type_str.to_string())),
         };
 
@@ -1144,9 +1757,25 @@ impl Definitions {
         self.mappings.shrink_to_fit();
         self.categories.shrink_to_fit();
         self.periods.shrink_to_fit();
+        self.tags.shrink_to_fit();
+        self.clamps.shrink_to_fit();
+        self.tolerances.shrink_to_fit();
+        self.precisions.shrink_to_fit();
+        self.inverted_vars.shrink_to_fit();
+        self.var_groups.shrink_to_fit();
+        self.mode_vars.shrink_to_fit();
+        self.var_group_index.shrink_to_fit();
+        self.sequence_steps.shrink_to_fit();
         self.unreliable_vars.shrink_to_fit();
+        self.reliable_vars.shrink_to_fit();
+        self.bitmask_vars.shrink_to_fit();
+        self.bit_owners.shrink_to_fit();
         self.do_not_sync.shrink_to_fit();
+        self.activity_names.shrink_to_fit();
+        self.freeze_method.shrink_to_fit();
         self.interpolate_vars.shrink_to_fit();
+        self.observer_interpolate_vars.shrink_to_fit();
+        self.discrete_vars.shrink_to_fit();
 
         self.lvarstransfer.shrink_maps();
         self.events.shrink_maps();
@@ -1178,8 +1807,34 @@ impl Definitions {
                         .insert(ignore_value.as_str().unwrap().to_string());
                     self.add_to_buffer(key.clone(), ignore_value);
                 }
+            } else if key == "activity" {
+                for activity_value in value {
+                    self.activity_names
+                        .insert(get_list_name(&activity_value, "activity")?);
+                    self.add_to_buffer(key.clone(), activity_value);
+                }
+            } else if key == "freeze" {
+                for freeze_value in value {
+                    self.freeze_method
+                        .push(get_list_name(&freeze_value, "freeze")?);
+                    self.add_to_buffer(key.clone(), freeze_value);
+                }
             } else {
                 for var_data in value {
+                    let override_key = get_override_key(&var_data);
+
+                    if let Some(name) = override_key.as_ref() {
+                        if self.overridden.contains(name) {
+                            continue;
+                        }
+
+                        if let Some((category, override_data)) = self.overrides.remove(name) {
+                            self.overridden.insert(name.clone());
+                            self.parse_var(category, override_data)?;
+                            continue;
+                        }
+                    }
+
                     self.parse_var(key.clone(), var_data)?;
                 }
             }
@@ -1203,28 +1858,98 @@ impl Definitions {
         self.parse_yaml(yaml)
     }
 
-    pub fn load_config_from_bytes(&mut self, bytes: Box<[u8]>) -> Result<(), Error> {
-        let yaml: IndexMap<String, Vec<Value>> = rmp_serde::from_slice(&bytes)?;
+    // Base entries get replaced by override entries with the same var name as they're loaded, the base file is left untouched
+    pub fn load_config_with_overrides(
+        &mut self,
+        path: impl AsRef<Path> + Display,
+        override_path: impl AsRef<Path> + Display,
+    ) -> Result<(), Error> {
+        let override_path_string = override_path.to_string();
 
-        self.parse_yaml(yaml)
-    }
+        let file = File::open(override_path).map_err(Error::IOError)?;
 
-    fn process_local_var(&mut self, result: GetResult) {
-        let mut should_write = !check_did_write_recently(&mut self.last_written, &result.var_name)
-            && !self.do_not_sync.contains(&result.var_name);
+        let yaml: IndexMap<String, Vec<Value>> = serde_yaml::from_reader(file)
+            .map_err(|e| Error::YamlError(e, override_path_string.clone()))?;
 
-        if let Some(mappings) = self.mappings.get_mut(&result.var_name) {
-            for mapping in mappings {
-                if mapping.cancel_h_events {
-                    self.event_cancel_timer = Instant::now();
-                }
+        self.set_overrides(yaml)?;
+        self.load_config(path)?;
+        self.apply_remaining_overrides()
+    }
 
-                execute_mapping!(
-                    new_value,
-                    action,
-                    VarReaderTypes::F64(result.value),
-                    mapping,
-                    { action.set_current(new_value) },
+    fn set_overrides(&mut self, yaml: IndexMap<String, Vec<Value>>) -> Result<(), Error> {
+        for (key, value) in yaml {
+            if key == "ignore" {
+                for ignore_value in value {
+                    self.do_not_sync
+                        .insert(ignore_value.as_str().unwrap().to_string());
+                    self.add_to_buffer(key.clone(), ignore_value);
+                }
+            } else if key == "activity" {
+                for activity_value in value {
+                    self.activity_names
+                        .insert(get_list_name(&activity_value, "activity")?);
+                    self.add_to_buffer(key.clone(), activity_value);
+                }
+            } else if key == "freeze" {
+                for freeze_value in value {
+                    self.freeze_method
+                        .push(get_list_name(&freeze_value, "freeze")?);
+                    self.add_to_buffer(key.clone(), freeze_value);
+                }
+            } else if key != "include" {
+                for var_data in value {
+                    if let Some(name) = get_override_key(&var_data) {
+                        self.overrides.insert(name, (key.clone(), var_data));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Overrides for vars the base doesn't define get added as new entries
+    fn apply_remaining_overrides(&mut self) -> Result<(), Error> {
+        for (_, (category, var_data)) in std::mem::take(&mut self.overrides) {
+            self.parse_var(category, var_data)?;
+        }
+
+        self.overridden.clear();
+        self.shrink_maps();
+
+        Ok(())
+    }
+
+    pub fn load_config_from_bytes(&mut self, bytes: Box<[u8]>) -> Result<(), Error> {
+        let yaml: IndexMap<String, Vec<Value>> = rmp_serde::from_slice(&bytes)?;
+
+        self.parse_yaml(yaml)
+    }
+
+    fn process_local_var(&mut self, result: GetResult) {
+        let written_recently =
+            check_did_write_recently(&mut self.last_written, &result.var_name, self.clock.now());
+        let mut should_write = !written_recently && !self.do_not_sync.contains(&result.var_name);
+
+        if !written_recently {
+            self.note_activity_var(&result.var_name);
+        }
+
+        let action_value =
+            self.get_action_value(&result.var_name, &VarReaderTypes::F64(result.value));
+
+        if let Some(mappings) = self.mappings.get_mut(&result.var_name) {
+            for mapping in mappings {
+                if mapping.cancel_h_events {
+                    self.event_cancel_timer = self.clock.now();
+                }
+
+                execute_mapping!(
+                    new_value,
+                    action,
+                    action_value,
+                    mapping,
+                    { action.set_current(new_value) },
                     {},
                     {
                         self.pending_action = Some(action.clone());
@@ -1238,6 +1963,14 @@ impl Definitions {
             return;
         }
 
+        // Held back until the whole group is due
+        if let Some(index) = self.var_group_index.get(&result.var_name) {
+            self.var_groups[*index]
+                .pending
+                .insert(result.var_name, VarReaderTypes::F64(result.value));
+            return;
+        }
+
         self.current_sync
             .lvars
             .insert(result.var_name, VarReaderTypes::F64(result.value));
@@ -1255,10 +1988,11 @@ impl Definitions {
             match message.payload {
                 JSPayloads::Interaction { name } => {
                     if self.do_not_sync.contains(&name[5..])
-                        || self.event_cancel_timer.elapsed().as_millis() < 300
+                        || self.clock.elapsed(self.event_cancel_timer).as_millis() < 300
                     {
                         return;
                     };
+                    self.note_activity_event(&name[5..]);
                     self.current_sync.events.push(Event::JSEvent { name });
                 }
                 JSPayloads::Input { id, value } => {
@@ -1271,11 +2005,12 @@ impl Definitions {
                         return;
                     }
 
+                    self.note_activity_event(&input_full_name);
                     self.current_sync.events.push(Event::JSInput {
                         instrument: message.instrument_name,
                         value,
                         id,
-                    })
+                    });
                 }
                 JSPayloads::Time {
                     hour,
@@ -1328,17 +2063,18 @@ impl Definitions {
                 }
 
                 if mapping.cancel_h_events {
-                    self.event_cancel_timer = Instant::now();
+                    self.event_cancel_timer = self.clock.now();
                 }
 
                 // Check timer
-                if check_did_write_recently(&mut self.last_written, &event_name) {
+                if check_did_write_recently(&mut self.last_written, &event_name, self.clock.now()) {
                     return;
                 }
             }
         }
 
         if should_write {
+            self.note_activity_event(&event_name);
             self.current_sync.events.push(Event::KeyEvent {
                 name: event_name,
                 value: data.dwData,
@@ -1346,6 +2082,19 @@ impl Definitions {
         }
     }
 
+    // Without a declared list every local event counts as the pilot being there
+    fn note_activity_event(&mut self, name: &str) {
+        if self.activity_names.is_empty() || self.activity_names.contains(name) {
+            self.last_local_input = self.clock.now();
+        }
+    }
+
+    fn note_activity_var(&mut self, var_name: &str) {
+        if self.activity_names.contains(var_name) {
+            self.last_local_input = self.clock.now();
+        }
+    }
+
     // Process changed aircraft variables and update SyncActions related to it
     #[allow(unused_variables)]
     pub fn process_sim_object_data(&mut self, data: &simconnect::SIMCONNECT_RECV_SIMOBJECT_DATA) {
@@ -1359,22 +2108,36 @@ impl Definitions {
             // self.physics_corrector.remove_components(&mut data);
             // Update all syncactions with the changed values
             for (var_name, value) in &data {
+                if self.bitmask_vars.contains_key(var_name) {
+                    self.queue_changed_bits(var_name, value);
+                    // Only the bits get synced unless the whole var is defined too
+                    if !self.mappings.contains_key(var_name) {
+                        continue;
+                    }
+                }
                 // Determine if this variable should be updated
-                let mut should_write = !check_did_write_recently(&mut self.last_written, var_name)
-                    && !self.do_not_sync.contains(var_name);
+                let written_recently =
+                    check_did_write_recently(&mut self.last_written, var_name, self.clock.now());
+                let mut should_write = !written_recently && !self.do_not_sync.contains(var_name);
+
+                if !written_recently {
+                    self.note_activity_var(var_name);
+                }
+
+                let action_value = self.get_action_value(var_name, value);
                 // Set current var syncactions
                 if let Some(mappings) = self.mappings.get_mut(var_name) {
                     for mapping in mappings {
                         if mapping.cancel_h_events {
-                            self.event_cancel_timer = Instant::now();
+                            self.event_cancel_timer = self.clock.now();
                         }
 
                         execute_mapping!(
                             new_value,
                             action,
-                            value,
+                            action_value,
                             mapping,
-                            { action.set_current(*new_value) },
+                            { action.set_current(new_value) },
                             {},
                             {}
                         );
@@ -1382,10 +2145,15 @@ impl Definitions {
                 }
 
                 if let Some(period) = self.periods.get_mut(var_name) {
-                    should_write = should_write && period.do_update();
+                    if should_write && !period.do_update(self.clock.now()) {
+                        // Still sent once the period is up, or a var that settles in between would never be
+                        self.held_avars.insert(var_name.clone(), *value);
+                        should_write = false;
+                    }
                 }
 
                 if should_write {
+                    self.held_avars.remove(var_name);
                     // Queue data for reading
                     self.current_sync.avars.insert(var_name.clone(), *value);
                 }
@@ -1393,8 +2161,40 @@ impl Definitions {
         }
     }
 
+    fn queue_changed_bits(&mut self, packed_name: &str, value: &VarReaderTypes) {
+        let (bits, packed) = match (self.bitmask_vars.get(packed_name), get_packed_value(value)) {
+            (Some(bits), Some(packed)) => (bits, packed),
+            _ => return,
+        };
+
+        let now = self.clock.now();
+
+        for bit_var in bits {
+            let is_set = get_bit(packed, bit_var.bit);
+
+            if self.bit_values.insert(bit_var.name.clone(), is_set) == Some(is_set) {
+                continue;
+            }
+
+            let should_write =
+                !check_did_write_recently(&mut self.last_written, &bit_var.name, self.clock.now())
+                    && !self.do_not_sync.contains(&bit_var.name)
+                    && self
+                        .periods
+                        .get_mut(&bit_var.name)
+                        .map(|period| period.do_update(now))
+                        .unwrap_or(true);
+
+            if should_write {
+                self.current_sync
+                    .avars
+                    .insert(bit_var.name.clone(), VarReaderTypes::Bool(is_set));
+            }
+        }
+    }
+
     fn process_js_interaction(&mut self, conn: &SimConnector, name: String) {
-        if self.event_timer.elapsed().as_millis() < 50 {
+        if self.clock.elapsed(self.event_timer).as_millis() < 50 {
             self.event_queue.push_front(Event::JSEvent { name });
             return;
         }
@@ -1402,7 +2202,7 @@ impl Definitions {
         // Use gauge to transmit H: event
         self.lvarstransfer.set_unchecked(conn, &name, None, "");
 
-        self.event_timer = Instant::now();
+        self.event_timer = self.clock.now();
     }
 
     fn process_key_event(
@@ -1411,11 +2211,17 @@ impl Definitions {
         name: String,
         value: u32,
     ) -> Result<(), Error> {
-        for mapping in self
-            .mappings
-            .get(&name)
-            .ok_or_else(|| Error::MissingMapping(name.clone()))?
-        {
+        let mappings = match self.mappings.get(&name) {
+            Some(mappings) => mappings,
+            None => {
+                self.record_write_failure(&name);
+                return Ok(());
+            }
+        };
+
+        let mut failed = false;
+
+        for mapping in mappings {
             if let ActionType::Event(mapping) = &mapping.action {
                 if mapping.use_calculator {
                     self.lvarstransfer.set_unchecked(
@@ -1424,12 +2230,16 @@ impl Definitions {
                         None,
                         value.to_string().as_str(),
                     );
-                } else {
-                    self.events.trigger_event(conn, &name, value).unwrap();
+                } else if self.events.trigger_event(conn, &name, value).is_err() {
+                    failed = true;
                 }
             }
         }
 
+        if failed {
+            self.record_write_failure(&name);
+        }
+
         Ok(())
     }
 
@@ -1473,12 +2283,46 @@ impl Definitions {
 
     pub fn step(&mut self, conn: &SimConnector) -> Result<(), Error> {
         self.process_js_data();
+        self.retry_autopilot_modes(conn);
         self.process_events(conn)
     }
 
+    // Presses whatever modes still differ, does nothing once they match
+    fn retry_autopilot_modes(&mut self, conn: &SimConnector) {
+        if self.mode_retries.is_empty() {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mappings = &mut self.mappings;
+        let lvarstransfer = &mut self.lvarstransfer;
+        let last_written = &mut self.last_written;
+
+        self.mode_retries
+            .retain(|var_name, (target, attempts, last_attempt)| {
+                if now.saturating_duration_since(*last_attempt) < MODE_RETRY_INTERVAL {
+                    return true;
+                }
+
+                for mapping in mappings.get_mut(var_name).into_iter().flatten() {
+                    if let ActionType::F64(action) = &mut mapping.action {
+                        action.set_new(*target, conn, lvarstransfer);
+                    }
+                }
+                set_did_write_recently(last_written, var_name, now);
+
+                *attempts += 1;
+                *last_attempt = now;
+                *attempts < MODE_RETRY_ATTEMPTS
+            });
+    }
+
     fn split_unreliable(&self, data: &mut AllNeedSync) -> AllNeedSync {
         data.filter_keep(|name| {
-            self.interpolate_vars.contains(name) || self.unreliable_vars.contains(name)
+            !self.reliable_only
+                && !self.reliable_vars.contains(name)
+                && !self.discrete_vars.contains(name)
+                && (self.interpolate_vars.contains(name) || self.unreliable_vars.contains(name))
         })
     }
 
@@ -1506,12 +2350,174 @@ impl Definitions {
         &mut self,
         sync_permission: &SyncPermission,
     ) -> (Option<AllNeedSync>, Option<AllNeedSync>) {
+        let now = self.clock.now();
+        for group in self.var_groups.iter_mut() {
+            if !group.pending.is_empty() && group.period.do_update(now) {
+                self.current_sync.lvars.extend(group.pending.drain());
+            }
+        }
+
+        let periods = &mut self.periods;
+        let current_sync = &mut self.current_sync;
+        self.held_avars.retain(|name, value| {
+            let due = periods
+                .get_mut(name)
+                .map(|period| period.do_update(now))
+                .unwrap_or(true);
+            if due {
+                current_sync.avars.entry(name.clone()).or_insert(*value);
+            }
+            !due
+        });
+
         let mut data = AllNeedSync::new();
         std::mem::swap(&mut self.current_sync, &mut data);
-        self.filter_all_sync(data, sync_permission)
+        // Before comparing, so noise past the last decimal isn't a change
+        self.round_sent(&mut data);
+
+        // Entries with their own tolerance skip small changes even when not event driven
+        let event_driven = self.event_driven;
+        let last_shared = &self.last_shared;
+        let tolerances = &self.tolerances;
+        let should_send = |name: &String, value: &mut VarReaderTypes| match tolerances.get(name) {
+            Some(tolerance) => changed_beyond_tolerance(last_shared.get(name), value, *tolerance),
+            None => {
+                !event_driven
+                    || changed_beyond_tolerance(
+                        last_shared.get(name),
+                        value,
+                        EVENT_DRIVEN_TOLERANCE,
+                    )
+            }
+        };
+        data.avars.retain(should_send);
+        data.lvars.retain(should_send);
+
+        let (unreliable, regular) = self.filter_all_sync(data, sync_permission);
+        self.remember_shared(unreliable.as_ref());
+        self.remember_shared(regular.as_ref());
+
+        (unreliable, regular)
+    }
+
+    // Everything we're allowed to sync regardless of whether it changed, to refresh anyone who missed an update
+    pub fn get_keep_alive(
+        &mut self,
+        sync_permission: &SyncPermission,
+    ) -> (Option<AllNeedSync>, Option<AllNeedSync>) {
+        let mut data = self.get_all_current();
+        self.round_sent(&mut data);
+
+        let (unreliable, regular) = self.filter_all_sync(data, sync_permission);
+        self.remember_shared(unreliable.as_ref());
+        self.remember_shared(regular.as_ref());
+
+        (unreliable, regular)
+    }
+
+    // Synced as read, only what the actions see is flipped so both ends agree on the raw value
+    fn get_action_value(&self, var_name: &str, value: &VarReaderTypes) -> VarReaderTypes {
+        if self.inverted_vars.contains(var_name) {
+            invert_value(value)
+        } else {
+            *value
+        }
+    }
+
+    fn round_sent(&self, data: &mut AllNeedSync) {
+        if self.precisions.is_empty() {
+            return;
+        }
+
+        for (name, value) in data.avars.iter_mut().chain(data.lvars.iter_mut()) {
+            if let Some(precision) = self.precisions.get(name) {
+                round_to_precision(value, *precision);
+            }
+        }
+    }
+
+    fn remember_shared(&mut self, data: Option<&AllNeedSync>) {
+        if let Some(data) = data {
+            for (name, value) in data.avars.iter().chain(data.lvars.iter()) {
+                self.last_shared.insert(name.clone(), *value);
+            }
+        }
+    }
+
+    // Interpolating towards NaN or infinity corrupts the var for good, so keep the last good value instead
+    fn drop_non_finite(&mut self, vars: &mut VarMap) {
+        let non_finite_vars = &mut self.non_finite_vars;
+
+        vars.retain(|name, value| {
+            if is_finite(value) {
+                if non_finite_vars.remove(name) {
+                    info!("[DEFINITIONS] Receiving finite values for {} again", name);
+                }
+                return true;
+            }
+
+            if non_finite_vars.insert(name.clone()) {
+                warn!(
+                    "[DEFINITIONS] Ignoring {:?} received for {}, keeping the last good value",
+                    value, name
+                );
+            }
+            false
+        });
+    }
+
+    // A corrupt or extreme value would otherwise be written as is
+    fn clamp_received(&self, data: &mut AllNeedSync) {
+        if self.clamps.is_empty() {
+            return;
+        }
+
+        for (name, value) in data.avars.iter_mut().chain(data.lvars.iter_mut()) {
+            if let Some((min, max)) = self.clamps.get(name) {
+                if let Some(clamped) = clamp_value(value, *min, *max) {
+                    *value = clamped;
+                }
+            }
+        }
+    }
+
+    pub fn set_disabled_tags(&mut self, tags: &[String]) {
+        self.disabled_tags = tags.iter().map(|tag| tag.to_lowercase()).collect();
+    }
+
+    fn is_tag_disabled(&self, name: &str) -> bool {
+        self.tags.get(name).map_or(false, |tags| {
+            tags.iter().any(|tag| self.disabled_tags.contains(tag))
+        })
+    }
+
+    pub fn set_interpolation_disabled(&mut self, disabled: bool) {
+        self.interpolation_disabled = disabled;
+    }
+
+    pub fn set_reliable_only(&mut self, reliable_only: bool) {
+        self.reliable_only = reliable_only;
+    }
+
+    pub fn set_event_driven(&mut self, event_driven: bool) {
+        self.event_driven = event_driven;
+        self.last_shared.clear();
     }
 
     fn can_sync(&self, var_name: &str, sync_permission: &SyncPermission) -> bool {
+        if self.is_tag_disabled(var_name) {
+            return false;
+        }
+        // Check role, avionics are the shared vars and flight controls are the master ones
+        match sync_permission.role {
+            ClientRole::Full => {}
+            ClientRole::Avionics => {
+                if let Some(Category::Master) = self.categories.get(var_name) {
+                    return false;
+                }
+            }
+            ClientRole::ViewOnly => return false,
+        }
         // Check categories
         match self.categories.get(var_name) {
             Some(Category::Shared) => true,
@@ -1533,14 +2539,83 @@ impl Definitions {
 
         let mut interpolation_data = Vec::new();
 
+        let should_snap = match data.get(SLEW_VAR_NAME) {
+            Some(value) => *value == VarReaderTypes::Bool(true),
+            None => self.is_slewing(),
+        };
+
+        let now = self.clock.now();
+        self.receive_history.push(now, &data);
+
+        let jumped = self.position_jumped(&data) && !should_snap;
+        let started_slewing = should_snap && !self.snapping_for_slew;
+
+        if started_slewing {
+            // Drop whatever the gauge was still interpolating towards
+            self.lvarstransfer.transfer.stop_interpolation(conn);
+        }
+
+        if jumped || started_slewing {
+            self.snap_report = Some(self.receive_history.dump(now));
+        }
+        self.snapping_for_slew = should_snap;
+        // Either way the values get written as they arrive
+        let write_directly = should_snap || self.interpolation_disabled;
+
         // Add some local computed components
         // self.physics_corrector.add_components(&mut data);
 
         // Only sync vars that are defined as so
-        for (var_name, data) in data {
-            set_did_write_recently(&mut self.last_written, &var_name);
+        for (var_name, data) in self.in_sequence_order(data) {
+            // Earlier steps of a sequence have to be written before the next one is applied
+            if self.sequence_steps.contains_key(&var_name) && !to_sync.is_empty() {
+                self.avarstransfer.set_vars(conn, &to_sync);
+                to_sync.clear();
+            }
+
+            set_did_write_recently(&mut self.last_written, &var_name, self.clock.now());
+
+            let write_directly = write_directly || self.discrete_vars.contains(&var_name);
+
+            // Flip just this bit on top of whatever the packed var currently holds
+            if let Some((packed_name, bit)) = self.bit_owners.get(&var_name) {
+                let current = to_sync
+                    .get(packed_name)
+                    .or_else(|| self.avarstransfer.get_var(packed_name))
+                    .copied();
+
+                match current {
+                    Some(current) => {
+                        let is_set = data == VarReaderTypes::Bool(true);
+
+                        self.bit_values.insert(var_name.clone(), is_set);
+                        set_did_write_recently(
+                            &mut self.last_written,
+                            packed_name,
+                            self.clock.now(),
+                        );
+                        to_sync.insert(packed_name.clone(), set_packed_bit(current, *bit, is_set));
+                    }
+                    None => self.record_write_failure(&var_name),
+                }
+
+                continue;
+            }
+
+            // Observers aren't flying, so smooth motion matters more than setting values through events
+            if self.is_observing
+                && !write_directly
+                && self.observer_interpolate_vars.contains(&var_name)
+            {
+                interpolation_data.push(InterpolateData {
+                    name: var_name.clone(),
+                    value: data.get_as_f64(),
+                });
+                continue;
+            }
 
             // Otherwise sync them using defined events
+            let action_value = self.get_action_value(&var_name, &data);
             if let Some(mappings) = self.mappings.get_mut(&var_name) {
                 for mapping in mappings {
                     if !evaluate_conditions(
@@ -1556,11 +2631,11 @@ impl Definitions {
                     execute_mapping!(
                         new_value,
                         action,
-                        data,
+                        action_value,
                         mapping,
                         { action.set_new(new_value, conn, &mut self.lvarstransfer) },
                         {
-                            if self.interpolate_vars.contains(&var_name) {
+                            if self.interpolate_vars.contains(&var_name) && !write_directly {
                                 // Queue data for interpolation
                                 interpolation_data.push(InterpolateData {
                                     name: var_name.clone(),
@@ -1574,6 +2649,8 @@ impl Definitions {
                         {}
                     );
                 }
+            } else {
+                self.record_write_failure(&var_name);
             }
         }
 
@@ -1592,7 +2669,8 @@ impl Definitions {
 
     #[allow(unused_variables)]
     fn write_local_data(&mut self, conn: &SimConnector, data: VarMap) -> Result<(), Error> {
-        for (var_name, value) in &data {
+        for (var_name, value) in &self.in_sequence_order(data.clone()) {
+            let action_value = self.get_action_value(var_name, value);
             match self.mappings.get_mut(var_name) {
                 Some(mappings) => {
                     for mapping in mappings {
@@ -1609,9 +2687,9 @@ impl Definitions {
                         execute_mapping!(
                             new_value,
                             action,
-                            value,
+                            action_value,
                             mapping,
-                            { action.set_new(*new_value, conn, &mut self.lvarstransfer) },
+                            { action.set_new(new_value, conn, &mut self.lvarstransfer) },
                             {
                                 self.lvarstransfer
                                     .set(conn, var_name, value.to_string().as_ref());
@@ -1619,10 +2697,17 @@ impl Definitions {
                             {}
                         );
 
-                        set_did_write_recently(&mut self.last_written, var_name);
+                        set_did_write_recently(&mut self.last_written, var_name, self.clock.now());
+                    }
+
+                    if self.mode_vars.contains(var_name) {
+                        self.mode_retries.insert(
+                            var_name.clone(),
+                            (action_value.get_as_f64(), 0, self.clock.now()),
+                        );
                     }
                 }
-                None => return Err(Error::MissingMapping(var_name.clone())),
+                None => self.record_write_failure(var_name),
             }
         }
 
@@ -1637,6 +2722,66 @@ impl Definitions {
         Ok(())
     }
 
+    // Vars that are part of a sequence go last, in the order their sequence declares them
+    fn in_sequence_order(&self, data: VarMap) -> Vec<(String, VarReaderTypes)> {
+        let mut ordered: Vec<(String, VarReaderTypes)> = data.into_iter().collect();
+        ordered.sort_by_key(|(var_name, _)| self.sequence_steps.get(var_name).copied());
+        ordered
+    }
+
+    fn record_write_failure(&mut self, var_name: &str) {
+        *self.write_failures.entry(var_name.to_string()).or_default() += 1;
+    }
+
+    // Returns the vars that failed to write since the last call
+    // Received latitude and longitude in degrees, whatever units the definitions read them in
+    fn get_received_position(&self, data: &VarMap) -> Option<(f64, f64)> {
+        let to_degrees = |name: &str| {
+            let value = data.get(name)?.get_as_f64();
+
+            match self.avarstransfer.get_units(name) {
+                Some(units) if units.eq_ignore_ascii_case("radians") => Some(value.to_degrees()),
+                _ => Some(value),
+            }
+        };
+
+        Some((
+            to_degrees("PLANE LATITUDE")?,
+            to_degrees("PLANE LONGITUDE")?,
+        ))
+    }
+
+    fn position_jumped(&mut self, data: &VarMap) -> bool {
+        let position = match self.get_received_position(data) {
+            Some(position) => position,
+            None => return false,
+        };
+
+        let jumped = match self.last_received_position {
+            Some((latitude, longitude)) => {
+                // Crossing the antimeridian isn't a jump
+                let longitude_diff = (position.1 - longitude).abs() % 360.0;
+
+                (position.0 - latitude).abs() > SNAP_DISTANCE_DEGREES
+                    || longitude_diff.min(360.0 - longitude_diff) > SNAP_DISTANCE_DEGREES
+            }
+            None => false,
+        };
+
+        self.last_received_position = Some(position);
+
+        jumped
+    }
+
+    // Updates received in the seconds before the aircraft last snapped, the last one being the cause
+    pub fn take_snap_report(&mut self) -> Option<String> {
+        self.snap_report.take()
+    }
+
+    pub fn take_write_failures(&mut self) -> HashMap<String, u32> {
+        std::mem::take(&mut self.write_failures)
+    }
+
     pub fn on_receive_data(
         &mut self,
         conn: &SimConnector,
@@ -1645,6 +2790,10 @@ impl Definitions {
         sync_permission: &SyncPermission,
     ) -> Result<(), Error> {
         data.filter(|name| self.can_sync(name, sync_permission));
+        self.drop_non_finite(&mut data.avars);
+        self.drop_non_finite(&mut data.lvars);
+        self.clamp_received(&mut data);
+        self.remember_shared(Some(&data));
 
         // In this specific order
         // Aircraft var data should overwrite any event data
@@ -1662,9 +2811,11 @@ impl Definitions {
         self.lvarstransfer.on_connected(conn);
         self.physics_corrector.on_connected(conn);
 
-        // Might be running another instance
+        // Might be running another instance, finish connecting anyway and report it at the end
         #[cfg(not(feature = "skip_sim_connect"))]
-        self.jstransfer.start().map_err(|_| ())?;
+        let js_started = self.jstransfer.start().is_ok();
+        #[cfg(feature = "skip_sim_connect")]
+        let js_started = true;
 
         // Notify simulator we are connected
         self.lvarstransfer
@@ -1683,18 +2834,44 @@ impl Definitions {
             0,
         );
 
-        Ok(())
+        if js_started {
+            Ok(())
+        } else {
+            Err(())
+        }
     }
 
     pub fn get_all_current(&self) -> AllNeedSync {
-        let avars = self
+        let mut avars: VarMap = self
             .avarstransfer
             .get_all_vars()
             .clone()
             .into_iter()
-            .filter(|(x, _)| !self.do_not_sync.contains(x))
+            .filter(|(x, _)| {
+                !self.do_not_sync.contains(x)
+                    && (!self.bitmask_vars.contains_key(x) || self.mappings.contains_key(x))
+            })
             .collect();
 
+        // Packed vars are sent as their individual bits
+        for (packed_name, bits) in self.bitmask_vars.iter() {
+            let packed = match self
+                .avarstransfer
+                .get_var(packed_name)
+                .and_then(get_packed_value)
+            {
+                Some(packed) => packed,
+                None => continue,
+            };
+
+            for bit_var in bits {
+                avars.insert(
+                    bit_var.name.clone(),
+                    VarReaderTypes::Bool(get_bit(packed, bit_var.bit)),
+                );
+            }
+        }
+
         // self.physics_corrector.remove_components(&mut avars);
 
         AllNeedSync {
@@ -1713,6 +2890,16 @@ impl Definitions {
     pub fn reset_sync(&mut self) {
         self.current_sync.clear();
         self.last_written.clear();
+        self.last_shared.clear();
+        self.held_avars.clear();
+        self.non_finite_vars.clear();
+        self.mode_retries.clear();
+        self.receive_history.clear();
+        self.last_received_position = None;
+
+        for group in self.var_groups.iter_mut() {
+            group.pending.clear();
+        }
     }
 
     pub fn get_number_avars(&self) -> usize {
@@ -1727,6 +2914,29 @@ impl Definitions {
         self.lvarstransfer.get_number_defined()
     }
 
+    pub fn is_slewing(&self) -> bool {
+        std::matches!(
+            self.avarstransfer.get_var(SLEW_VAR_NAME),
+            Some(VarReaderTypes::Bool(true))
+        )
+    }
+
+    pub fn get_last_local_input(&self) -> Instant {
+        self.last_local_input
+    }
+
+    pub fn get_freeze_method(&self) -> &[String] {
+        &self.freeze_method
+    }
+
+    pub fn set_observing(&mut self, is_observing: bool) {
+        self.is_observing = is_observing;
+    }
+
+    pub fn get_interpolation_stats(&self) -> &InterpolationStats {
+        self.lvarstransfer.transfer.get_interpolation_stats()
+    }
+
     pub fn get_next_pending_action(&mut self) -> Option<ProgramAction> {
         self.pending_action.as_ref()?;
 
@@ -1736,3 +2946,733 @@ impl Definitions {
         next_action
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossbeam_channel::{unbounded, Receiver, Sender};
+    use std::time::Duration;
+    use yourcontrols_net::{NetworkSimulation, Payloads, ReceiveMessage, TransferClient};
+    use yourcontrols_types::ManualClock;
+
+    const TEST_DEFINITIONS: &str = r#"
+shared:
+  - type: ToggleSwitch
+    var_name: A:GEAR HANDLE POSITION
+    var_units: Bool
+    var_type: bool
+    event_name: GEAR_TOGGLE
+    invert: true
+  - type: NumSet
+    var_name: A:FLAPS HANDLE INDEX
+    var_units: Number
+    var_type: i32
+    event_name: FLAPS_SET
+  - type: NumIncrement
+    var_name: A:ADF VOLUME:1
+    var_units: Percent
+    var_type: i32
+    up_event_name: ADF_VOLUME_INC
+    down_event_name: ADF_VOLUME_DEC
+    increment_by: 2
+    tags: [sound]
+    min: 0
+    max: 100
+  - type: LocalVarGroup
+    update_every: 0.25
+    vars:
+      - L:FMS_CURSOR_X
+      - L:FMS_CURSOR_Y
+master:
+  - type: NumSet
+    var_name: A:GENERAL ENG THROTTLE LEVER POSITION:1
+    var_units: Percent
+    var_type: f64
+    event_name: THROTTLE1_SET
+    unreliable: true
+  - type: NumSet
+    var_name: A:GEAR CENTER POSITION
+    var_units: Bool
+    var_type: i32
+    event_name: GEAR_SET
+    unreliable: true
+    reliable: true
+  - type: var
+    var_name: A:SPOILERS HANDLE POSITION
+    var_units: Percent
+    var_type: f64
+    interpolate: Default
+    discrete: true
+  - type: var
+    var_name: A:LIGHT ON STATES
+    var_units: Mask
+    var_type: i32
+    bit: 0
+  - type: var
+    var_name: A:LIGHT ON STATES
+    var_units: Mask
+    var_type: i32
+    bit: 2
+  - type: var
+    var_name: A:FUEL TANK LEFT MAIN QUANTITY
+    var_units: Gallons
+    var_type: f64
+    update_every: 2
+    tolerance: 0.1
+  - type: var
+    var_name: A:KOHLSMAN SETTING HG
+    var_units: inHg
+    var_type: f64
+    precision: 2
+"#;
+
+    // Stands in for a network client by passing every sent payload through the wire format and back
+    struct LoopbackClient {
+        transmitter: Sender<(Payloads, Option<String>)>,
+        outgoing: Receiver<(Payloads, Option<String>)>,
+        server_transmitter: Sender<ReceiveMessage>,
+        receiver: Receiver<ReceiveMessage>,
+    }
+
+    impl LoopbackClient {
+        fn new() -> Self {
+            let (transmitter, outgoing) = unbounded();
+            let (server_transmitter, receiver) = unbounded();
+
+            Self {
+                transmitter,
+                outgoing,
+                server_transmitter,
+                receiver,
+            }
+        }
+
+        fn deliver(&self) {
+            while let Ok((payload, _)) = self.outgoing.try_recv() {
+                let bytes = rmp_serde::to_vec(&payload).unwrap();
+                let payload = rmp_serde::from_slice(&bytes).unwrap();
+
+                self.server_transmitter
+                    .send(ReceiveMessage::Payload(payload))
+                    .unwrap();
+            }
+        }
+    }
+
+    impl TransferClient for LoopbackClient {
+        fn is_host(&self) -> bool {
+            false
+        }
+
+        fn get_transmitter(&self) -> &Sender<(Payloads, Option<String>)> {
+            &self.transmitter
+        }
+
+        fn get_server_transmitter(&self) -> &Sender<ReceiveMessage> {
+            &self.server_transmitter
+        }
+
+        fn get_receiver(&self) -> &Receiver<ReceiveMessage> {
+            &self.receiver
+        }
+
+        fn get_server_name(&self) -> &str {
+            "loopback"
+        }
+
+        fn get_session_id(&self) -> Option<String> {
+            None
+        }
+
+        fn set_server_name(&mut self, _: String) {}
+
+        fn set_network_simulation(&self, _: NetworkSimulation) {}
+
+        fn stop(&mut self, _: String) {}
+    }
+
+    fn get_test_definitions() -> Definitions {
+        get_test_definitions_with_clock(Arc::new(SystemClock))
+    }
+
+    fn get_test_definitions_with_clock(clock: Arc<dyn Clock>) -> Definitions {
+        let yaml: IndexMap<String, Vec<Value>> = serde_yaml::from_str(TEST_DEFINITIONS).unwrap();
+        let mut definitions = Definitions::with_clock(clock);
+        definitions.parse_yaml(yaml).unwrap();
+        definitions
+    }
+
+    fn get_permission(is_master: bool) -> SyncPermission {
+        SyncPermission {
+            is_server: false,
+            is_master,
+            is_init: false,
+            role: ClientRole::Full,
+        }
+    }
+
+    // Sends whatever is queued for sync over the loopback and returns what the other side received
+    fn round_trip(definitions: &mut Definitions, permission: &SyncPermission) -> VarMap {
+        let client = LoopbackClient::new();
+
+        let (unreliable, regular) = definitions.get_sync(permission);
+        if let Some(data) = unreliable {
+            client.update(data, true);
+        }
+        if let Some(data) = regular {
+            client.update(data, false);
+        }
+
+        client.deliver();
+
+        let mut received = VarMap::new();
+        while let Ok(message) = client.get_next_message() {
+            if let ReceiveMessage::Payload(Payloads::Update { mut data, .. }) = message {
+                // The receiving side only applies what the sender was permitted to sync
+                data.filter(|name| definitions.can_sync(name, permission));
+                received.extend(data.avars);
+                received.extend(data.lvars);
+            }
+        }
+
+        received
+    }
+
+    #[test]
+    fn test_sync_round_trip() {
+        let mut definitions = get_test_definitions();
+
+        let mut sent = VarMap::new();
+        sent.insert(
+            "GEAR HANDLE POSITION".to_string(),
+            VarReaderTypes::Bool(true),
+        );
+        sent.insert("FLAPS HANDLE INDEX".to_string(), VarReaderTypes::I32(3));
+        sent.insert("ADF VOLUME:1".to_string(), VarReaderTypes::I32(40));
+        definitions.current_sync.avars = sent.clone();
+
+        let received = round_trip(&mut definitions, &get_permission(false));
+
+        assert_eq!(received, sent);
+        // Every received var has somewhere to be written to
+        for var_name in received.keys() {
+            assert!(definitions.mappings.contains_key(var_name));
+        }
+    }
+
+    #[test]
+    fn test_sync_respects_permissions() {
+        let mut definitions = get_test_definitions();
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1".to_string();
+
+        definitions
+            .current_sync
+            .avars
+            .insert(throttle.clone(), VarReaderTypes::F64(75.5));
+        assert!(round_trip(&mut definitions, &get_permission(false)).is_empty());
+
+        definitions
+            .current_sync
+            .avars
+            .insert(throttle.clone(), VarReaderTypes::F64(75.5));
+        let received = round_trip(&mut definitions, &get_permission(true));
+        assert_eq!(received.get(&throttle), Some(&VarReaderTypes::F64(75.5)));
+    }
+
+    #[test]
+    fn test_avionics_role_cannot_fly() {
+        let definitions = get_test_definitions();
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1";
+        let flaps = "FLAPS HANDLE INDEX";
+
+        // Even while in control
+        let mut permission = get_permission(true);
+        permission.role = ClientRole::Avionics;
+        assert!(!definitions.can_sync(throttle, &permission));
+        assert!(definitions.can_sync(flaps, &permission));
+
+        permission.role = ClientRole::ViewOnly;
+        assert!(!definitions.can_sync(throttle, &permission));
+        assert!(!definitions.can_sync(flaps, &permission));
+    }
+
+    #[test]
+    fn test_reliable_overrides_unreliable() {
+        let mut definitions = get_test_definitions();
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1".to_string();
+        let gear = "GEAR CENTER POSITION".to_string();
+
+        definitions
+            .current_sync
+            .avars
+            .insert(throttle.clone(), VarReaderTypes::F64(75.5));
+        definitions
+            .current_sync
+            .avars
+            .insert(gear.clone(), VarReaderTypes::I32(1));
+
+        let (unreliable, regular) = definitions.get_sync(&get_permission(true));
+        let unreliable = unreliable.unwrap();
+        let regular = regular.unwrap();
+
+        assert!(unreliable.avars.contains_key(&throttle));
+        assert!(regular.avars.contains_key(&gear));
+        assert!(!unreliable.avars.contains_key(&gear));
+    }
+
+    #[test]
+    fn test_reliable_only_overrides_entries() {
+        let mut definitions = get_test_definitions();
+        definitions.set_reliable_only(true);
+
+        let mut data = AllNeedSync::new();
+        data.avars.insert(
+            "GENERAL ENG THROTTLE LEVER POSITION:1".to_string(),
+            VarReaderTypes::F64(50.0),
+        );
+
+        let (unreliable, regular) = definitions.filter_all_sync(data, &get_permission(true));
+        assert!(unreliable.is_none());
+        assert_eq!(regular.unwrap().avars.len(), 1);
+    }
+
+    #[test]
+    fn test_discrete_never_interpolated() {
+        let mut definitions = get_test_definitions();
+        let spoilers = "SPOILERS HANDLE POSITION".to_string();
+
+        assert!(!definitions.interpolate_vars.contains(&spoilers));
+
+        // Interpolated vars go out unreliably, but a detent change has to arrive
+        definitions
+            .current_sync
+            .avars
+            .insert(spoilers.clone(), VarReaderTypes::F64(50.0));
+        let (unreliable, regular) = definitions.get_sync(&get_permission(true));
+        assert!(unreliable.is_none());
+        assert!(regular.unwrap().avars.contains_key(&spoilers));
+    }
+
+    #[test]
+    fn test_sequence_applied_in_order() {
+        let mut definitions = get_test_definitions();
+        definitions
+            .parse_yaml(
+                serde_yaml::from_str(
+                    r#"
+master:
+  - type: Sequence
+    vars:
+      - A:GEAR CENTER POSITION
+      - A:GENERAL ENG THROTTLE LEVER POSITION:1
+"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1".to_string();
+        let gear = "GEAR CENTER POSITION".to_string();
+        let flaps = "FLAPS HANDLE INDEX".to_string();
+
+        let mut data = VarMap::new();
+        data.insert(throttle.clone(), VarReaderTypes::F64(100.0));
+        data.insert(flaps.clone(), VarReaderTypes::I32(1));
+        data.insert(gear.clone(), VarReaderTypes::I32(1));
+
+        let order: Vec<String> = definitions
+            .in_sequence_order(data.clone())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(order, vec![flaps, gear, throttle.clone()]);
+
+        // Steps can't go out unreliably, or they could arrive after the next one
+        definitions.current_sync.avars = data;
+        let (unreliable, regular) = definitions.get_sync(&get_permission(true));
+        assert!(unreliable.is_none());
+        assert!(regular.unwrap().avars.contains_key(&throttle));
+    }
+
+    #[test]
+    fn test_bits_sync_independently() {
+        let mut definitions = get_test_definitions();
+        let first = "LIGHT ON STATES#0".to_string();
+        let second = "LIGHT ON STATES#2".to_string();
+
+        definitions.queue_changed_bits("LIGHT ON STATES", &VarReaderTypes::I32(0b001));
+        let received = round_trip(&mut definitions, &get_permission(true));
+        assert_eq!(received.get(&first), Some(&VarReaderTypes::Bool(true)));
+        assert_eq!(received.get(&second), Some(&VarReaderTypes::Bool(false)));
+
+        // Only the bit that flipped gets sent again
+        definitions.queue_changed_bits("LIGHT ON STATES", &VarReaderTypes::I32(0b101));
+        let received = round_trip(&mut definitions, &get_permission(true));
+        assert_eq!(received.get(&first), None);
+        assert_eq!(received.get(&second), Some(&VarReaderTypes::Bool(true)));
+    }
+
+    #[test]
+    fn test_event_driven_skips_unchanged() {
+        let mut definitions = get_test_definitions();
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1".to_string();
+        definitions.set_event_driven(true);
+
+        let send = |definitions: &mut Definitions, value: f64| {
+            definitions
+                .current_sync
+                .avars
+                .insert(throttle.clone(), VarReaderTypes::F64(value));
+            round_trip(definitions, &get_permission(true))
+        };
+
+        assert!(!send(&mut definitions, 75.5).is_empty());
+        // Same value or within tolerance stays quiet
+        assert!(send(&mut definitions, 75.5).is_empty());
+        assert!(send(&mut definitions, 75.50001).is_empty());
+        assert!(!send(&mut definitions, 76.0).is_empty());
+
+        // Everything gets sent again after a reset
+        definitions.reset_sync();
+        assert!(!send(&mut definitions, 76.0).is_empty());
+    }
+
+    #[test]
+    fn test_own_tolerance_and_period() {
+        let clock = ManualClock::new();
+        let mut definitions = get_test_definitions_with_clock(Arc::new(clock.clone()));
+        let fuel = "FUEL TANK LEFT MAIN QUANTITY".to_string();
+        let permission = get_permission(true);
+
+        let send = |definitions: &mut Definitions, value: f64| {
+            definitions
+                .current_sync
+                .avars
+                .insert(fuel.clone(), VarReaderTypes::F64(value));
+            round_trip(definitions, &permission)
+        };
+
+        // Applies without event driven sync
+        assert!(!send(&mut definitions, 20.0).is_empty());
+        assert!(send(&mut definitions, 20.05).is_empty());
+        assert!(!send(&mut definitions, 20.5).is_empty());
+
+        // A change inside the period is still sent once it's up
+        definitions
+            .periods
+            .get_mut(&fuel)
+            .unwrap()
+            .do_update(clock.now());
+        definitions
+            .held_avars
+            .insert(fuel.clone(), VarReaderTypes::F64(30.0));
+        assert!(round_trip(&mut definitions, &permission).is_empty());
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(
+            round_trip(&mut definitions, &permission).get(&fuel),
+            Some(&VarReaderTypes::F64(30.0))
+        );
+        assert!(definitions.held_avars.is_empty());
+    }
+
+    #[test]
+    fn test_precision_rounds_sent_values() {
+        let mut definitions = get_test_definitions();
+        let kohlsman = "KOHLSMAN SETTING HG".to_string();
+        let permission = get_permission(true);
+
+        let mut send = |value: f64| {
+            definitions
+                .current_sync
+                .avars
+                .insert(kohlsman.clone(), VarReaderTypes::F64(value));
+            round_trip(&mut definitions, &permission)
+                .get(&kohlsman)
+                .copied()
+        };
+
+        assert_eq!(send(29.921_348), Some(VarReaderTypes::F64(29.92)));
+        assert_eq!(send(30.006), Some(VarReaderTypes::F64(30.01)));
+
+        let mut value = VarReaderTypes::F64(1e300);
+        round_to_precision(&mut value, 15);
+        assert_eq!(value, VarReaderTypes::F64(1e300));
+    }
+
+    #[test]
+    fn test_invert_value() {
+        assert_eq!(
+            invert_value(&VarReaderTypes::Bool(true)),
+            VarReaderTypes::Bool(false)
+        );
+        assert_eq!(
+            invert_value(&VarReaderTypes::I32(0)),
+            VarReaderTypes::I32(1)
+        );
+        assert_eq!(
+            invert_value(&VarReaderTypes::F64(2.0)),
+            VarReaderTypes::F64(0.0)
+        );
+
+        let definitions = get_test_definitions();
+        let gear = "GEAR HANDLE POSITION";
+        assert_eq!(
+            definitions.get_action_value(gear, &VarReaderTypes::Bool(true)),
+            VarReaderTypes::Bool(false)
+        );
+        assert_eq!(
+            definitions.get_action_value("FLAPS HANDLE INDEX", &VarReaderTypes::I32(1)),
+            VarReaderTypes::I32(1)
+        );
+    }
+
+    #[test]
+    fn test_disabled_tags_not_synced() {
+        let mut definitions = get_test_definitions();
+        let volume = "ADF VOLUME:1".to_string();
+        let gear = "GEAR HANDLE POSITION".to_string();
+
+        let queue = |definitions: &mut Definitions| {
+            definitions
+                .current_sync
+                .avars
+                .insert(volume.clone(), VarReaderTypes::I32(40));
+            definitions
+                .current_sync
+                .avars
+                .insert(gear.clone(), VarReaderTypes::Bool(true));
+        };
+
+        queue(&mut definitions);
+        assert_eq!(
+            round_trip(&mut definitions, &get_permission(false)).len(),
+            2
+        );
+
+        definitions.set_disabled_tags(&["Sound".to_string()]);
+        queue(&mut definitions);
+        let received = round_trip(&mut definitions, &get_permission(false));
+        assert_eq!(received.get(&volume), None);
+        assert!(received.contains_key(&gear));
+    }
+
+    #[test]
+    fn test_received_values_clamped() {
+        let definitions = get_test_definitions();
+        let volume = "ADF VOLUME:1".to_string();
+
+        let mut data = AllNeedSync::new();
+        data.avars.insert(volume.clone(), VarReaderTypes::I32(250));
+        definitions.clamp_received(&mut data);
+        assert_eq!(data.avars.get(&volume), Some(&VarReaderTypes::I32(100)));
+
+        data.avars.insert(volume.clone(), VarReaderTypes::I32(40));
+        definitions.clamp_received(&mut data);
+        assert_eq!(data.avars.get(&volume), Some(&VarReaderTypes::I32(40)));
+
+        assert_eq!(
+            clamp_value(&VarReaderTypes::F64(f64::NAN), Some(0.0), None),
+            Some(VarReaderTypes::F64(0.0))
+        );
+    }
+
+    #[test]
+    fn test_non_finite_values_dropped() {
+        let mut definitions = get_test_definitions();
+        let throttle = "GENERAL ENG THROTTLE LEVER POSITION:1".to_string();
+
+        let mut vars = VarMap::new();
+        vars.insert(throttle.clone(), VarReaderTypes::F64(f64::NAN));
+        vars.insert(
+            "L:FMS_CURSOR_X".to_string(),
+            VarReaderTypes::F64(f64::INFINITY),
+        );
+        vars.insert(
+            "GEAR HANDLE POSITION".to_string(),
+            VarReaderTypes::Bool(true),
+        );
+        definitions.drop_non_finite(&mut vars);
+
+        assert_eq!(vars.len(), 1);
+        assert!(definitions.non_finite_vars.contains(&throttle));
+
+        vars.insert(throttle.clone(), VarReaderTypes::F64(50.0));
+        definitions.drop_non_finite(&mut vars);
+        assert_eq!(vars.get(&throttle), Some(&VarReaderTypes::F64(50.0)));
+        assert!(!definitions.non_finite_vars.contains(&throttle));
+    }
+
+    #[test]
+    fn test_local_var_group_sent_together() {
+        let clock = ManualClock::new();
+        let mut definitions = get_test_definitions_with_clock(Arc::new(clock.clone()));
+        let permission = get_permission(false);
+
+        let set = |definitions: &mut Definitions, var_name: &str, value: f64| {
+            definitions.process_local_var(GetResult {
+                var_name: var_name.to_string(),
+                value,
+            });
+        };
+
+        set(&mut definitions, "L:FMS_CURSOR_X", 1.0);
+        assert_eq!(round_trip(&mut definitions, &permission).len(), 1);
+
+        // Held back until the group is due again
+        set(&mut definitions, "L:FMS_CURSOR_X", 2.0);
+        set(&mut definitions, "L:FMS_CURSOR_Y", 3.0);
+        assert!(round_trip(&mut definitions, &permission).is_empty());
+
+        clock.advance(Duration::from_millis(250));
+        let received = round_trip(&mut definitions, &permission);
+        assert_eq!(
+            received.get("L:FMS_CURSOR_X"),
+            Some(&VarReaderTypes::F64(2.0))
+        );
+        assert_eq!(
+            received.get("L:FMS_CURSOR_Y"),
+            Some(&VarReaderTypes::F64(3.0))
+        );
+    }
+
+    #[test]
+    fn test_activity_vars_count_as_input() {
+        let clock = ManualClock::new();
+        let mut definitions = get_test_definitions_with_clock(Arc::new(clock.clone()));
+        definitions
+            .parse_yaml(serde_yaml::from_str("activity:\n  - L:FMS_CURSOR_X\n").unwrap())
+            .unwrap();
+
+        let set = |definitions: &mut Definitions, var_name: &str| {
+            definitions.process_local_var(GetResult {
+                var_name: var_name.to_string(),
+                value: 1.0,
+            });
+        };
+
+        clock.advance(Duration::from_secs(60));
+        set(&mut definitions, "L:FMS_CURSOR_Y");
+        assert_eq!(
+            clock
+                .now()
+                .saturating_duration_since(definitions.get_last_local_input()),
+            Duration::from_secs(60)
+        );
+
+        set(&mut definitions, "L:FMS_CURSOR_X");
+        assert_eq!(definitions.get_last_local_input(), clock.now());
+
+        // Undeclared events no longer count once the aircraft lists its own
+        clock.advance(Duration::from_secs(60));
+        definitions.note_activity_event("TOGGLE_MASTER_BATTERY");
+        assert_ne!(definitions.get_last_local_input(), clock.now());
+    }
+
+    #[test]
+    fn test_malformed_name_lists_fail_to_load() {
+        let mut definitions = get_test_definitions();
+        assert!(matches!(
+            definitions.parse_yaml(
+                serde_yaml::from_str("activity:\n  - var_name: L:FMS_CURSOR_X\n").unwrap()
+            ),
+            Err(Error::MissingField("activity"))
+        ));
+        assert!(matches!(
+            definitions.parse_yaml(serde_yaml::from_str("freeze:\n  - 5\n").unwrap()),
+            Err(Error::MissingField("freeze"))
+        ));
+    }
+
+    #[test]
+    fn test_modes_calculator() {
+        let modes: Vec<AutopilotModeEntry> = serde_yaml::from_str(
+            r#"
+- var_name: A:AUTOPILOT HEADING LOCK
+  event_name: AP_PANEL_HEADING_HOLD
+- var_name: L:AP_NAV_ARMED
+  event_name: AP_NAV1_HOLD
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_modes_calculator(&modes),
+            "0 (A:AUTOPILOT HEADING LOCK, Bool) 0 != 1 * + (L:AP_NAV_ARMED) 0 != 2 * +"
+        );
+    }
+
+    #[test]
+    fn test_period_with_clock() {
+        let clock = ManualClock::new();
+        let mut period = Period::new(0.5);
+
+        assert!(period.do_update(clock.now()));
+        assert!(!period.do_update(clock.now()));
+
+        clock.advance(Duration::from_millis(499));
+        assert!(!period.do_update(clock.now()));
+
+        clock.advance(Duration::from_millis(1));
+        assert!(period.do_update(clock.now()));
+    }
+
+    #[test]
+    fn test_recently_written_not_sent_back() {
+        let clock = ManualClock::new();
+        let mut definitions = get_test_definitions_with_clock(Arc::new(clock.clone()));
+        let first = "LIGHT ON STATES#0".to_string();
+
+        // Just written from the network, so the change shouldn't echo back
+        set_did_write_recently(&mut definitions.last_written, &first, clock.now());
+        definitions.queue_changed_bits("LIGHT ON STATES", &VarReaderTypes::I32(0b001));
+        assert_eq!(
+            round_trip(&mut definitions, &get_permission(true)).get(&first),
+            None
+        );
+
+        clock.advance(Duration::from_secs(1));
+        definitions.queue_changed_bits("LIGHT ON STATES", &VarReaderTypes::I32(0b000));
+        let received = round_trip(&mut definitions, &get_permission(true));
+        assert_eq!(received.get(&first), Some(&VarReaderTypes::Bool(false)));
+    }
+
+    #[test]
+    fn test_overrides_replace_by_var_name() {
+        let overrides = r#"
+master:
+  - type: NumSet
+    var_name: A:FLAPS HANDLE INDEX
+    var_units: Number
+    var_type: i32
+    event_name: FLAPS_SET
+  - type: var
+    var_name: A:FLAPS HANDLE PERCENT
+    var_units: Percent
+    var_type: f64
+ignore:
+  - GEAR HANDLE POSITION
+"#;
+
+        let mut definitions = Definitions::new();
+        definitions
+            .set_overrides(serde_yaml::from_str(overrides).unwrap())
+            .unwrap();
+        definitions
+            .parse_yaml(serde_yaml::from_str(TEST_DEFINITIONS).unwrap())
+            .unwrap();
+        definitions.apply_remaining_overrides().unwrap();
+
+        // Moved from shared to master
+        assert!(definitions.can_sync("FLAPS HANDLE INDEX", &get_permission(true)));
+        assert!(!definitions.can_sync("FLAPS HANDLE INDEX", &get_permission(false)));
+        // Not in the base, so added
+        assert!(definitions.mappings.contains_key("FLAPS HANDLE PERCENT"));
+        assert!(definitions.do_not_sync.contains("GEAR HANDLE POSITION"));
+        // Joiners get the overridden definitions
+        let buffer = &definitions.definitions_buffer;
+        assert!(!buffer["shared"]
+            .iter()
+            .any(|x| get_override_key(x).as_deref() == Some("A:FLAPS HANDLE INDEX")));
+    }
+}
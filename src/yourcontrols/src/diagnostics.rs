@@ -0,0 +1,68 @@
+use laminar::Metrics;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::app::ConnectionMethod;
+use crate::simconfig::Config;
+
+const LOG_LINES_TO_INCLUDE: usize = 100;
+// Fields that could identify the user or their network
+const SENSITIVE_CONFIG_FIELDS: [&str; 2] = ["ip", "name"];
+
+fn get_sanitized_config(config: &Config) -> String {
+    let mut value = serde_json::to_value(config).unwrap();
+
+    if let Some(fields) = value.as_object_mut() {
+        for field in SENSITIVE_CONFIG_FIELDS.iter() {
+            fields.remove(*field);
+        }
+    }
+
+    serde_json::to_string_pretty(&value).unwrap()
+}
+
+fn get_last_log_lines(log_path: impl AsRef<Path>) -> String {
+    match read_to_string(log_path) {
+        Ok(contents) => {
+            let lines: Vec<&str> = contents.lines().collect();
+            let start = lines.len().saturating_sub(LOG_LINES_TO_INCLUDE);
+            lines[start..].join("\n")
+        }
+        Err(e) => format!("Could not read log file! Reason: {}", e),
+    }
+}
+
+// Formats everything useful for a bug report into one blob
+pub fn build_report(
+    version: &str,
+    config: &Config,
+    method: Option<&ConnectionMethod>,
+    metrics: Option<&Metrics>,
+    log_path: impl AsRef<Path>,
+) -> String {
+    let method = method
+        .map(|x| format!("{:?}", x))
+        .unwrap_or_else(|| "Not connected".to_string());
+
+    let metrics = match metrics {
+        Some(metrics) => format!(
+            "Sent {:.2} KB/s ({} packets), Received {:.2} KB/s ({} packets), Loss {:.2}%, RTT {:.0}ms",
+            metrics.sent_kbps,
+            metrics.sent_packets,
+            metrics.receive_kbps,
+            metrics.received_packets,
+            metrics.packet_loss * 100.0,
+            metrics.rtt
+        ),
+        None => "None".to_string(),
+    };
+
+    format!(
+        "YourControls v{}\nConnection method: {}\nMetrics: {}\n\nConfig:\n{}\n\nLog:\n{}",
+        version,
+        method,
+        metrics,
+        get_sanitized_config(config),
+        get_last_log_lines(log_path)
+    )
+}
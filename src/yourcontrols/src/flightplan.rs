@@ -0,0 +1,111 @@
+use log::{info, warn};
+use simconnect::SimConnector;
+use std::{fs, io, path::PathBuf};
+
+use crate::util::read_c_string;
+
+const FLIGHT_PLAN_ACTIVATED_EVENT_ID: u32 = 100_002;
+// Received flight plans are written here before being loaded into the sim
+const RECEIVED_FLIGHT_PLANS_PATH: &str = "flightplans/";
+
+// Shares the host's active flight plan. Aircraft with their own FMC may not pick up a plan loaded this way.
+pub struct FlightPlanSync {
+    // Name and contents of the last plan activated or loaded
+    current: Option<(String, Box<[u8]>)>,
+}
+
+impl FlightPlanSync {
+    pub fn new() -> Self {
+        Self { current: None }
+    }
+
+    pub fn on_connected(&self, conn: &SimConnector) {
+        conn.subscribe_to_system_event(FLIGHT_PLAN_ACTIVATED_EVENT_ID, "FlightPlanActivated");
+    }
+
+    // Reads the plan the sim just activated, returning it if it's a new one
+    pub fn process_event_filename(
+        &mut self,
+        data: &simconnect::SIMCONNECT_RECV_EVENT_FILENAME,
+    ) -> Option<(String, Box<[u8]>)> {
+        if data._base.uEventID != FLIGHT_PLAN_ACTIVATED_EVENT_ID {
+            return None;
+        }
+
+        let file_name = data.szFileName;
+        let path = PathBuf::from(read_c_string(&file_name));
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes.into_boxed_slice(),
+            Err(e) => {
+                warn!(
+                    "[FLIGHTPLAN] Could not read activated flight plan {}: {}",
+                    path.display(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        let name = path
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "flightplan".to_string());
+
+        if self.is_current(&bytes) {
+            return None;
+        }
+
+        info!("[FLIGHTPLAN] Flight plan {} activated", name);
+
+        self.current = Some((name, bytes));
+        self.current.clone()
+    }
+
+    pub fn get_current(&self) -> Option<&(String, Box<[u8]>)> {
+        self.current.as_ref()
+    }
+
+    // Writes a received plan to disk and has the sim load it
+    pub fn load(&mut self, conn: &SimConnector, name: String, bytes: Box<[u8]>) -> io::Result<()> {
+        // The same plan can arrive more than once when relayed
+        if self.is_current(&bytes) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(RECEIVED_FLIGHT_PLANS_PATH)?;
+
+        // Only keep the name, never write outside the folder
+        let file_stem: String = name
+            .chars()
+            .filter(|x| x.is_alphanumeric() || *x == ' ' || *x == '-' || *x == '_')
+            .collect();
+        let file_stem = if file_stem.is_empty() {
+            "flightplan".to_string()
+        } else {
+            file_stem
+        };
+
+        let path = std::env::current_dir()?
+            .join(RECEIVED_FLIGHT_PLANS_PATH)
+            .join(&file_stem);
+
+        fs::write(path.with_extension("pln"), &bytes)?;
+        // The sim adds the extension itself
+        conn.flight_plan_load(&path.to_string_lossy());
+
+        info!("[FLIGHTPLAN] Loaded flight plan {}", name);
+
+        self.current = Some((name, bytes));
+
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    fn is_current(&self, bytes: &[u8]) -> bool {
+        matches!(self.current.as_ref(), Some((_, current)) if current.as_ref() == bytes)
+    }
+}
@@ -0,0 +1,113 @@
+use simconnect::SimConnector;
+use yourcontrols_types::{VarMap, VarReaderTypes};
+
+use crate::sync::transfer::AircraftVars;
+use crate::util::{offset_coordinates, InDataTypes};
+
+#[derive(Default, Clone, Copy)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+    // Feet
+    pub altitude: f64,
+    // True heading
+    pub heading: f64,
+}
+
+// Reads our own position to lead with, and places us near the leader's when following.
+// Kept apart from the definitions so it works no matter what the aircraft config syncs.
+pub struct Follow {
+    avars: AircraftVars,
+    current: Option<Position>,
+}
+
+impl Follow {
+    pub fn new(define_id: u32) -> Self {
+        let mut avars = AircraftVars::new(define_id);
+
+        avars.add_var("PLANE LATITUDE", "Degrees", InDataTypes::F64);
+        avars.add_var("PLANE LONGITUDE", "Degrees", InDataTypes::F64);
+        avars.add_var("PLANE ALTITUDE", "Feet", InDataTypes::F64);
+        avars.add_var("PLANE HEADING DEGREES TRUE", "Degrees", InDataTypes::F64);
+
+        Self {
+            avars,
+            current: None,
+        }
+    }
+
+    pub fn on_connected(&self, conn: &SimConnector) {
+        self.avars.on_connected(conn);
+        conn.request_data_on_sim_object(
+            5830,
+            self.avars.define_id,
+            0,
+            simconnect::SIMCONNECT_PERIOD_SIMCONNECT_PERIOD_SECOND,
+            simconnect::SIMCONNECT_CLIENT_DATA_REQUEST_FLAG_TAGGED,
+            0,
+            0,
+            0,
+        );
+    }
+
+    pub fn process_sim_object_data(&mut self, data: &simconnect::SIMCONNECT_RECV_SIMOBJECT_DATA) {
+        if self.avars.define_id != data.dwDefineID {
+            return;
+        }
+
+        if self.avars.read_vars(data).is_err() {
+            return;
+        }
+
+        let get = |var_name| match self.avars.get_var(var_name) {
+            Some(VarReaderTypes::F64(value)) => Some(*value),
+            _ => None,
+        };
+
+        if let (Some(latitude), Some(longitude), Some(altitude), Some(heading)) = (
+            get("PLANE LATITUDE"),
+            get("PLANE LONGITUDE"),
+            get("PLANE ALTITUDE"),
+            get("PLANE HEADING DEGREES TRUE"),
+        ) {
+            self.current = Some(Position {
+                latitude,
+                longitude,
+                altitude,
+                heading,
+            });
+        }
+    }
+
+    pub fn get_position(&self) -> Option<Position> {
+        self.current
+    }
+
+    // Offsets are in meters relative to where the leader is pointing
+    pub fn snap_to(&self, conn: &SimConnector, leader: &Position, behind: f64, right: f64) {
+        let (latitude, longitude) = offset_coordinates(
+            leader.latitude,
+            leader.longitude,
+            leader.heading,
+            behind,
+            right,
+        );
+
+        let mut data = VarMap::new();
+        data.insert("PLANE LATITUDE".to_string(), VarReaderTypes::F64(latitude));
+        data.insert(
+            "PLANE LONGITUDE".to_string(),
+            VarReaderTypes::F64(longitude),
+        );
+        data.insert(
+            "PLANE ALTITUDE".to_string(),
+            VarReaderTypes::F64(leader.altitude),
+        );
+        data.insert(
+            "PLANE HEADING DEGREES TRUE".to_string(),
+            VarReaderTypes::F64(leader.heading),
+        );
+
+        self.avars.set_vars(conn, &data);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+use yourcontrols_types::VarMap;
+
+// Aircraft var updates received over the last few seconds, so what led up to a snap can be looked at afterwards
+pub struct ReceiveHistory {
+    entries: VecDeque<(Instant, VarMap)>,
+    keep_for: Duration,
+}
+
+impl ReceiveHistory {
+    pub fn new(keep_for: Duration) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            keep_for,
+        }
+    }
+
+    pub fn push(&mut self, now: Instant, data: &VarMap) {
+        self.entries.push_back((now, data.clone()));
+
+        while let Some((received, _)) = self.entries.front() {
+            if now.saturating_duration_since(*received) <= self.keep_for {
+                break;
+            }
+            self.entries.pop_front();
+        }
+    }
+
+    // Oldest first, the last update listed is the one that caused the snap
+    pub fn dump(&self, now: Instant) -> String {
+        let mut report = String::new();
+
+        for (received, data) in self.entries.iter() {
+            let mut vars: Vec<String> = data
+                .iter()
+                .map(|(name, value)| format!("{}={:?}", name, value))
+                .collect();
+            vars.sort();
+
+            writeln!(
+                report,
+                "-{:.2}s: {}",
+                now.saturating_duration_since(*received).as_secs_f64(),
+                vars.join(", ")
+            )
+            .ok();
+        }
+
+        report
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yourcontrols_types::VarReaderTypes;
+
+    #[test]
+    fn test_history_keeps_recent() {
+        let mut history = ReceiveHistory::new(Duration::from_secs(5));
+        let start = Instant::now();
+
+        let mut data = VarMap::new();
+        data.insert("PLANE ALTITUDE".to_string(), VarReaderTypes::F64(1000.0));
+        history.push(start, &data);
+
+        data.insert("PLANE ALTITUDE".to_string(), VarReaderTypes::F64(9000.0));
+        history.push(start + Duration::from_secs(3), &data);
+
+        // First update falls out of the window
+        let now = start + Duration::from_secs(6);
+        history.push(now, &data);
+
+        let report = history.dump(now);
+        assert_eq!(report.lines().count(), 2);
+        assert!(report.starts_with("-3.00s: PLANE ALTITUDE=F64(9000.0)"));
+        assert!(report.ends_with("-0.00s: PLANE ALTITUDE=F64(9000.0)\n"));
+    }
+}
@@ -0,0 +1,201 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use spin_sleep::sleep;
+use std::{
+    collections::VecDeque,
+    io,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+use tungstenite::{accept, HandshakeError, Message, WebSocket};
+
+use crate::metrics::SessionMetrics;
+
+// Bound to loopback only, for launchers on this machine to set things up before the UI is used
+const LOCAL_API_PORT: u16 = 7781;
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ApiRequest {
+    ListAircraft,
+    LoadAircraft { config_file_name: String },
+    // Poll this, metrics change about once a second. All zero when not in a session.
+    GetMetrics,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ApiResponse {
+    AircraftList { configs: Vec<AircraftConfigInfo> },
+    AircraftLoaded { config_file_name: String },
+    Metrics { metrics: SessionMetrics },
+    Error { reason: String },
+}
+
+#[derive(Serialize, Debug)]
+pub struct AircraftConfigInfo {
+    pub file_name: String,
+    pub developer: Option<String>,
+    pub aircraft: String,
+}
+
+impl AircraftConfigInfo {
+    // Config files are named "Developer - Aircraft.yaml"
+    pub fn from_file_name(file_name: &str) -> Self {
+        let name = file_name.trim_end_matches(".yaml");
+
+        let (developer, aircraft) = match name.split_once(" - ") {
+            Some((developer, aircraft)) => (Some(developer.to_string()), aircraft.to_string()),
+            None => (None, name.to_string()),
+        };
+
+        Self {
+            file_name: file_name.to_string(),
+            developer,
+            aircraft,
+        }
+    }
+}
+
+pub struct ApiMessage {
+    pub request: ApiRequest,
+    pub stream_id: u32,
+}
+
+struct StreamInfo {
+    stream: WebSocket<TcpStream>,
+    id: u32,
+}
+
+pub struct LocalApi {
+    streams: Vec<StreamInfo>,
+    listener: Option<TcpListener>,
+    incoming_requests: VecDeque<ApiMessage>,
+    next_stream_id: u32,
+}
+
+impl LocalApi {
+    pub fn new() -> Self {
+        Self {
+            streams: Vec::new(),
+            listener: None,
+            incoming_requests: VecDeque::new(),
+            next_stream_id: 0,
+        }
+    }
+
+    pub fn start(&mut self) -> Result<(), io::Error> {
+        if self.listener.is_some() {
+            return Ok(());
+        }
+
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, LOCAL_API_PORT)))?;
+        listener.set_nonblocking(true).ok();
+
+        info!("[API] Listening on {:?}", listener.local_addr());
+
+        self.listener = Some(listener);
+
+        Ok(())
+    }
+
+    // Closes the listener and every connected launcher
+    pub fn stop(&mut self) {
+        self.listener = None;
+        self.streams.clear();
+        self.incoming_requests.clear();
+    }
+
+    pub fn poll(&mut self) -> Option<ApiMessage> {
+        self.accept_connections();
+        self.read_messages();
+
+        self.incoming_requests.pop_front()
+    }
+
+    pub fn respond(&mut self, stream_id: u32, response: ApiResponse) {
+        let message = Message::Text(serde_json::to_string(&response).unwrap());
+
+        if let Some(info) = self.streams.iter_mut().find(|x| x.id == stream_id) {
+            info.stream.send(message).ok();
+        }
+    }
+
+    fn accept_connections(&mut self) {
+        if let Some(listener) = self.listener.as_mut() {
+            if let Ok((stream, _)) = listener.accept() {
+                // Keep trying to handshake
+                let mut result = accept(stream);
+                loop {
+                    match result {
+                        Ok(stream) => {
+                            info!("[API] Local API client connected");
+
+                            self.streams.push(StreamInfo {
+                                stream,
+                                id: self.next_stream_id,
+                            });
+                            self.next_stream_id = self.next_stream_id.wrapping_add(1);
+
+                            break;
+                        }
+                        Err(HandshakeError::Interrupted(mid)) => result = mid.handshake(),
+                        Err(HandshakeError::Failure(_)) => break,
+                    }
+                    sleep(Duration::from_millis(1))
+                }
+            }
+        }
+    }
+
+    fn read_messages(&mut self) {
+        let incoming_requests = &mut self.incoming_requests;
+
+        self.streams.retain_mut(|info| {
+            match info.stream.read() {
+                Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                    Ok(request) => {
+                        incoming_requests.push_back(ApiMessage {
+                            request,
+                            stream_id: info.id,
+                        });
+                    }
+                    Err(e) => {
+                        error!("[API] Invalid request! Data: {} Reason: {}", text, e);
+
+                        let response = ApiResponse::Error {
+                            reason: e.to_string(),
+                        };
+                        info.stream
+                            .send(Message::Text(serde_json::to_string(&response).unwrap()))
+                            .ok();
+                    }
+                },
+                Ok(Message::Close(_)) => {
+                    return false;
+                }
+                Err(tungstenite::Error::AlreadyClosed) => return false,
+                _ => {}
+            }
+
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_config_info_from_file_name() {
+        let info = AircraftConfigInfo::from_file_name("Asobo - Cessna 172.yaml");
+        assert_eq!(info.developer.as_deref(), Some("Asobo"));
+        assert_eq!(info.aircraft, "Cessna 172");
+        assert_eq!(info.file_name, "Asobo - Cessna 172.yaml");
+
+        // No developer in the name
+        let info = AircraftConfigInfo::from_file_name("Generic.yaml");
+        assert_eq!(info.developer, None);
+        assert_eq!(info.aircraft, "Generic");
+    }
+}
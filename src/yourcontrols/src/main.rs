@@ -1,28 +1,43 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 #![allow(non_snake_case)]
 
+mod aircraftwatch;
 mod app;
 mod audio;
 mod clientmanager;
 mod corrector;
 mod definitions;
+mod diagnostics;
+mod flightplan;
+mod follow;
+mod history;
+mod localapi;
+mod metrics;
 mod simconfig;
 mod sync;
 mod syncdefs;
+mod tray;
 mod update;
 mod util;
+mod validate;
 mod varreader;
 
-use app::{App, AppMessage, ConnectionMethod};
+use aircraftwatch::{suggest_configs, AircraftChange, AircraftWatch};
+use app::{App, AppMessage, ConnectionMethod, ConnectionPath};
 use audio::AudioManager;
 use clientmanager::ClientManager;
 use definitions::{Definitions, ProgramAction, SyncPermission};
+use flightplan::FlightPlanSync;
+use follow::{Follow, Position};
+use localapi::{AircraftConfigInfo, ApiRequest, ApiResponse, LocalApi};
 use log::{error, info, warn};
-use simconfig::Config;
+use metrics::{SessionMetrics, SessionReport};
+use simconfig::{Config, LastSession};
 use simconnect::{DispatchResult, SimConnector};
 
 use spin_sleep::sleep;
 use std::{
+    collections::HashMap,
     env,
     fs::{read_dir, File},
     io,
@@ -32,19 +47,46 @@ use std::{
     time::Instant,
 };
 use update::Updater;
-use yourcontrols_net::{Client, Event, Payloads, ReceiveMessage, Server, TransferClient};
-use yourcontrols_types::AllNeedSync;
+use yourcontrols_net::{
+    Client, ConnectionStage, Event, Payloads, ProtocolTrace, ReceiveMessage, Server, TransferClient,
+};
+use yourcontrols_types::{AllNeedSync, ClientRole};
 
-use crate::util::get_hostname_ip;
+use crate::util::{
+    get_hostname_ip, instance_file_name, is_valid_session_id, parse_ip_with_scope,
+    validate_address, validate_port, OverloadDetector, RateCounter,
+};
 
 use control::*;
 use sync::*;
 
 const LOG_FILENAME: &str = "log.txt";
+const PROTOCOL_LOG_FILENAME: &str = "protocol.log";
+// Overwritten by every session as it ends
+const SESSION_REPORT_FILENAME: &str = "session_report.txt";
 const CONFIG_FILENAME: &str = "config.json";
 const AIRCRAFT_DEFINITIONS_PATH: &str = "definitions/aircraft/";
+// Files here with the same name as an aircraft definition get layered on top of it
+const DEFINITION_OVERRIDES_PATH: &str = "definitions/overrides/";
 
 const LOOP_SLEEP_TIME: Duration = Duration::from_millis(10);
+// How often vars that failed to write on receive get reported
+const WRITE_FAILURE_REPORT_INTERVAL: u64 = 10;
+// Seconds between session clock updates in the UI
+const SESSION_TIME_UPDATE_INTERVAL: u64 = 1;
+// More updates than this waiting at once, or handling them taking longer than the budget, means we can't keep up
+const MAX_UPDATES_PER_LOOP: usize = 25;
+const RECEIVE_TIME_BUDGET: Duration = Duration::from_millis(20);
+// Loops in a row before the overload warning shows or clears
+const OVERLOAD_LOOPS: u32 = 50;
+// Seconds the achieved sync rate is averaged over before being shown
+const SYNC_RATE_WINDOW: u64 = 5;
+// Seconds between attempts to reach SimConnect again after the sim closed
+const SIM_RECONNECT_INTERVAL: u64 = 5;
+// Seconds between positions sent to followers while leading
+const FOLLOW_UPDATE_INTERVAL: u64 = 1;
+// Seconds without a position from the leader before going back to normal syncing
+const FOLLOW_TIMEOUT: u64 = 5;
 
 fn get_aircraft_configs() -> io::Result<Vec<String>> {
     let mut filenames = Vec::new();
@@ -64,43 +106,70 @@ fn get_aircraft_configs() -> io::Result<Vec<String>> {
     Ok(filenames)
 }
 
-fn write_configuration(config: &Config) {
-    match config.write_to_file(CONFIG_FILENAME) {
-        Ok(_) => {}
-        Err(e) => error!(
+// Returns where the file is and why it couldn't be written, to show the user
+fn write_configuration(file_name: &str, config: &Config) -> Result<(), String> {
+    config.write_to_file(file_name).map_err(|e| {
+        error!(
             "[PROGRAM] Could not write configuration file! Reason: {}",
             e
-        ),
-    };
+        );
+
+        let path = env::current_dir()
+            .map(|dir| dir.join(file_name))
+            .unwrap_or_else(|_| PathBuf::from(file_name));
+        format!("{}: {}", path.display(), e)
+    })
+}
+
+// Started fresh like the main log, then shared by every connection made while tracing is on
+fn open_protocol_trace(file_name: &str) -> Option<ProtocolTrace> {
+    match ProtocolTrace::create(file_name) {
+        Ok(trace) => {
+            info!("[NETWORK] Tracing payloads to {}", file_name);
+            Some(trace)
+        }
+        Err(e) => {
+            error!("[NETWORK] Could not create {}! Reason: {}", file_name, e);
+            None
+        }
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
 fn start_client(
     timeout: u64,
+    session_timeout: Duration,
+    max_packet_size: usize,
+    heartbeat_interval: Duration,
+    protocol_trace: Option<ProtocolTrace>,
     username: String,
     session_id: Option<String>,
     version: String,
     isipv6: bool,
-    ip: Option<IpAddr>,
+    ip: Option<(IpAddr, u32)>,
     hostname: Option<String>,
     port: Option<u16>,
     method: ConnectionMethod,
 ) -> Result<Client, String> {
     let mut client = Client::new(username, version, timeout);
+    client.set_session_timeout(session_timeout);
+    client.set_max_packet_size(max_packet_size);
+    client.set_heartbeat_interval(heartbeat_interval);
+    client.set_protocol_trace(protocol_trace);
 
     let client_result = match method {
         ConnectionMethod::Direct => {
             // Get either hostname ip or defined ip
-            let actual_ip = match hostname {
+            let (actual_ip, scope_id) = match hostname {
                 Some(hostname) => match get_hostname_ip(&hostname, isipv6) {
-                    Ok(ip) => ip,
+                    Ok(ip) => (ip, 0),
                     Err(e) => return Err(e.to_string()),
                 },
                 // If no hostname was passed, an IP must've been passed
                 None => ip.unwrap(),
             };
             // A port must've been passed with direct connect
-            client.start(actual_ip, port.unwrap(), session_id)
+            client.start(actual_ip, port.unwrap(), scope_id, session_id)
         }
         ConnectionMethod::CloudServer => client.start_with_hole_punch(session_id.unwrap(), isipv6),
         ConnectionMethod::Relay => panic!("Never should be reached!"),
@@ -132,9 +201,100 @@ fn write_update_data(
     }
 }
 
+// Same as clicking transfer on a client in the UI
+fn transfer_control_to(clients: &ClientManager, client: &dyn TransferClient, target: String) {
+    if !clients.can_take_control(&target) {
+        warn!(
+            "[CONTROL] {} has a restricted role or is observing, not giving control",
+            target
+        );
+        return;
+    }
+
+    info!("[CONTROL] Giving control to {}", target);
+    // Send server message, will send a loopback Payloads::TransferControl
+    client.transfer_control(target);
+}
+
+// Folds queued unreliable updates into the newest one from the same sender, so a backlog costs one write per sender
+fn coalesce_unreliable_updates(messages: Vec<ReceiveMessage>) -> Vec<ReceiveMessage> {
+    let mut newest: HashMap<String, usize> = HashMap::new();
+    let mut kept = Vec::with_capacity(messages.len());
+
+    for message in messages.into_iter().rev() {
+        if let ReceiveMessage::Payload(Payloads::Update {
+            data,
+            from,
+            is_unreliable,
+            ..
+        }) = &message
+        {
+            // Older updates can't be moved past a reliable one or one with events
+            if !*is_unreliable || !data.events.is_empty() {
+                newest.remove(from);
+            } else {
+                if let Some(index) = newest.get(from) {
+                    if let ReceiveMessage::Payload(Payloads::Update { data: newer, .. }) =
+                        &mut kept[*index]
+                    {
+                        for (name, value) in data.avars.iter() {
+                            newer.avars.entry(name.clone()).or_insert(*value);
+                        }
+                        for (name, value) in data.lvars.iter() {
+                            newer.lvars.entry(name.clone()).or_insert(*value);
+                        }
+                    }
+                    continue;
+                }
+
+                newest.insert(from.clone(), kept.len());
+            }
+        }
+
+        kept.push(message);
+    }
+
+    kept.reverse();
+    kept
+}
+
 fn main() {
     let is_dev_build = cfg!(debug_assertions);
 
+    // YourControls --validate path/to/config.yaml checks a definition file and exits, for authors and CI
+    let args: Vec<String> = env::args().collect();
+    if let Some(index) = args.iter().position(|x| x == "--validate") {
+        validate::attach_parent_console();
+
+        let path = match args.get(index + 1) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                eprintln!("Usage: YourControls --validate path/to/config.yaml");
+                std::process::exit(2);
+            }
+        };
+
+        let report = validate::validate_definitions(&path);
+        print!("{}", report.summary(&path));
+
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
+    // YourControls --instance NAME runs alongside another instance to connect to it on the same machine,
+    // with its own config and logs. It shares the sim with the instance flying it, so it should be the one joining.
+    let instance = args
+        .iter()
+        .position(|x| x == "--instance")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+    // Only sends, what it receives isn't applied as the other instance already flies this sim
+    let send_only_loopback = instance.is_some();
+
+    let log_filename = instance_file_name(LOG_FILENAME, instance.as_deref());
+    let protocol_log_filename = instance_file_name(PROTOCOL_LOG_FILENAME, instance.as_deref());
+    let session_report_filename = instance_file_name(SESSION_REPORT_FILENAME, instance.as_deref());
+    let config_filename = instance_file_name(CONFIG_FILENAME, instance.as_deref());
+
     if !is_dev_build {
         // Set CWD to application directory
         let exe_path = env::current_exe();
@@ -144,11 +304,13 @@ fn main() {
     simplelog::WriteLogger::init(
         simplelog::LevelFilter::Info,
         simplelog::Config::default(),
-        File::create(LOG_FILENAME).unwrap(),
+        File::create(&log_filename).unwrap(),
     )
     .ok();
+    // Shown once the UI is up
+    let mut config_write_error = None;
     // Load configuration file
-    let mut config = match Config::read_from_file(CONFIG_FILENAME) {
+    let mut config = match Config::read_from_file(&config_filename) {
         Ok(config) => config,
         Err(e) => {
             warn!(
@@ -157,13 +319,26 @@ fn main() {
             );
 
             let config = Config::default();
-            write_configuration(&config);
+            config_write_error = write_configuration(&config_filename, &config).err();
             config
         }
     };
 
+    let mut protocol_trace = if config.protocol_tracing {
+        open_protocol_trace(&protocol_log_filename)
+    } else {
+        None
+    };
+
     let mut conn = simconnect::SimConnector::new();
     let mut control = Control::new();
+    if let Some(instance) = instance.as_ref() {
+        info!(
+            "[PROGRAM] Running as send only loopback instance {}, received data won't be written to the sim",
+            instance
+        );
+        control.set_disabled(true);
+    }
     let mut clients = ClientManager::new();
 
     let mut audio = AudioManager::new();
@@ -175,10 +350,27 @@ fn main() {
     let mut updater = Updater::new();
     let mut installer_spawned = false;
 
+    let mut local_api = LocalApi::new();
+    if config.local_api {
+        if let Err(e) = local_api.start() {
+            warn!("[API] Could not start the local API! Reason: {}", e);
+        }
+    }
+
     // Set up sim connect
     let mut observing = false;
+    // Observing only because control was handed off
+    let mut auto_observing = false;
+    // Set by the host, limits what we send
+    let mut role = ClientRole::Full;
+    // Whether others were told we stepped away
+    let mut is_afk = false;
+    let mut last_ui_input = Instant::now();
     // Client stopped, need to stop transfer client
     let mut should_set_none_client = false;
+    // Sim closed but the session was kept open for it to come back
+    let mut waiting_for_sim = false;
+    let mut sim_reconnect_timer = Instant::now();
 
     let app_interface = App::setup(format!("YourControls v{}", updater.get_version()));
 
@@ -191,6 +383,54 @@ fn main() {
     let mut ready_to_process_data = false;
 
     let mut connection_time = None;
+    // Last used values, kept around after disconnecting for diagnostics
+    let mut connection_method = None;
+    // Shown once connected
+    let mut connection_path = None;
+    // Summary of the session to show once everything it lists is known
+    let mut summary_pending = false;
+    // Connection details waiting to be saved as the last session once connected
+    let mut pending_session = None;
+    // Session to offer a reconnect to if the connection drops, cleared when disconnecting on purpose
+    let mut reconnect_session: Option<LastSession> = None;
+    let mut last_metrics = None;
+    let mut session_metrics = SessionMetrics::default();
+    let mut session_report = SessionReport::default();
+
+    let mut write_failure_timer = Instant::now();
+    // When the current session was established, for the session clock
+    let mut session_start: Option<Instant> = None;
+    let mut session_time_timer = Instant::now();
+    // Incoming updates or writing them to the sim can't keep up
+    let mut receive_overload = OverloadDetector::new(OVERLOAD_LOOPS);
+    let mut send_congested = false;
+    // How many sync passes actually run against config.update_rate
+    let mut sync_rate = RateCounter::new(Duration::from_secs(SYNC_RATE_WINDOW), Instant::now());
+    let mut sync_timer = Instant::now();
+    // Only warned about when it starts, not every window
+    let mut sync_rate_low = false;
+    // Full snapshots sent every so often in event driven mode
+    let mut keep_alive_timer = Instant::now();
+    // Position only sync, the host leads and everyone else follows at an offset
+    let mut follow = Follow::new(3);
+    let mut is_leading = false;
+    let mut follow_timer = Instant::now();
+    // Sync pauses while a different aircraft than the session's is loaded
+    let mut aircraft_watch = AircraftWatch::new();
+    // Asked the sim what's loaded to suggest a config for it
+    let mut awaiting_loaded_aircraft = false;
+    // The host's active flight plan, shared if enabled
+    let mut flight_plan = FlightPlanSync::new();
+    // When the last position from the leader arrived
+    let mut following: Option<Instant> = None;
+    // Whether every joined pilot has sent Ready, shown to the host
+    let mut everyone_ready = false;
+    // Pilot in control and since when their packet loss has been too high
+    let mut lossy_controller: Option<(String, Instant)> = None;
+    // Host's welcome message is only shown once per session
+    let mut motd_shown = false;
+    // Set when the transfer control key binding is pressed in the sim
+    let mut hotkey_action: Option<ProgramAction> = None;
 
     let mut config_to_load = String::new();
     // Helper closures
@@ -203,8 +443,22 @@ fn main() {
     let load_definitions = |definitions: &mut Definitions, config_to_load: &mut String| -> bool {
         // Load aircraft configuration
         let path = get_config_path(config_to_load);
+        let override_path = PathBuf::from(DEFINITION_OVERRIDES_PATH).join(config_to_load.as_str());
+
+        let result = if override_path.exists() {
+            info!(
+                "[DEFINITIONS] Applying overrides from {}",
+                override_path.display()
+            );
+            definitions.load_config_with_overrides(
+                path.to_string_lossy().to_string(),
+                override_path.to_string_lossy().to_string(),
+            )
+        } else {
+            definitions.load_config(path.to_string_lossy().to_string())
+        };
 
-        match definitions.load_config(path.to_string_lossy().to_string()) {
+        match result {
             Ok(_) => {
                 info!("[DEFINITIONS] Loaded and mapped {} aircraft vars, {} local vars, and {} events", definitions.get_number_avars(), definitions.get_number_lvars(), definitions.get_number_events());
             }
@@ -224,19 +478,19 @@ fn main() {
         true
     };
 
-    let connect_to_sim = |conn: &mut SimConnector, definitions: &mut Definitions| {
+    let connect_to_sim = |conn: &mut SimConnector, definitions: &mut Definitions, name: &str| {
         // Connect to simconnect
         *definitions = Definitions::new();
         #[cfg(not(feature = "skip_sim_connect"))]
-        let connected = conn.connect("YourControls");
+        let connected = conn.connect(name);
         #[cfg(feature = "skip_sim_connect")]
         let connected = true;
         if connected {
             // Display not connected to server message
-            info!("[SIM] Connected to SimConnect.");
+            info!("[SIM] Connected to SimConnect as {}.", name);
         } else {
             // Display trying to connect message
-            app_interface.error("Could not connect to SimConnect! Is the sim running?");
+            app_interface.error("sim_not_running");
         };
 
         connected
@@ -246,11 +500,42 @@ fn main() {
         let timer = Instant::now();
 
         if let Some(client) = transfer_client.as_mut() {
+            if waiting_for_sim && sim_reconnect_timer.elapsed().as_secs() >= SIM_RECONNECT_INTERVAL
+            {
+                sim_reconnect_timer = Instant::now();
+
+                #[cfg(not(feature = "skip_sim_connect"))]
+                let connected = conn.connect(config.simconnect_name());
+                #[cfg(feature = "skip_sim_connect")]
+                let connected = true;
+
+                if connected {
+                    info!("[SIM] Reconnected to SimConnect.");
+
+                    definitions.on_connected(&conn).ok();
+                    control.on_connected(
+                        &conn,
+                        &config.transfer_control_hotkey,
+                        definitions.get_freeze_method(),
+                    );
+                    follow.on_connected(&conn);
+                    aircraft_watch.on_connected(&conn);
+                    flight_plan.on_connected(&conn);
+                    waiting_for_sim = false;
+                    // Go through the usual delay before syncing again
+                    connection_time = Some(Instant::now());
+                    app_interface.sim_reconnected();
+                }
+            }
+
+            let mut sim_closed = false;
+            let mut aircraft_change = None;
             // Simconnect message
             while let Ok(message) = conn.get_next_message() {
                 match message {
                     DispatchResult::SimObjectData(data) => {
                         definitions.process_sim_object_data(data);
+                        follow.process_sim_object_data(data);
                     }
                     // Exception occured
                     DispatchResult::Exception(data) => {
@@ -267,17 +552,94 @@ fn main() {
                     DispatchResult::ClientData(data) => {
                         definitions.process_client_data(data);
                     }
+                    DispatchResult::EventFilename(data) => {
+                        if let Some(change) = aircraft_watch.process_event_filename(data) {
+                            aircraft_change = Some(change);
+                        }
+
+                        if let Some((name, bytes)) = flight_plan.process_event_filename(data) {
+                            if config.sync_flight_plan && client.is_host() {
+                                info!("[FLIGHTPLAN] Sharing flight plan {}", name);
+                                client.send_flight_plan(name, bytes, None);
+                            }
+                        }
+                    }
+                    DispatchResult::SystemState(data) => {
+                        if let Some(change) = aircraft_watch.process_system_state(data) {
+                            aircraft_change = Some(change);
+                        }
+                    }
                     DispatchResult::Event(data) => {
-                        definitions.process_event_data(data);
+                        if data.uEventID == TRANSFER_HOTKEY_EVENT_ID {
+                            hotkey_action = Some(ProgramAction::TransferControls);
+                        } else {
+                            definitions.process_event_data(data);
+                        }
                     }
                     DispatchResult::Quit(_) => {
+                        if config.keep_session_on_sim_exit {
+                            info!(
+                                "[SIM] Sim closed, keeping the session open until it comes back."
+                            );
+
+                            sim_closed = true;
+                            waiting_for_sim = true;
+                            sim_reconnect_timer = Instant::now();
+                            // Stop sending and receiving until the sim is back
+                            ready_to_process_data = false;
+                            connection_time = None;
+                            app_interface.sim_waiting();
+                            break;
+                        }
+
                         client.stop("Sim closed.".to_string());
                     }
                     _ => {}
                 }
             }
 
+            // Let go of the old connection, a new one is made once the sim is back
+            if sim_closed {
+                conn.close();
+            }
+
+            match aircraft_change {
+                Some(AircraftChange::Switched(aircraft)) => {
+                    warn!(
+                        "[SIM] {} was loaded mid-session, pausing sync until the session's aircraft is back",
+                        aircraft
+                    );
+                    // Stop sending and receiving, the definitions don't match this aircraft
+                    ready_to_process_data = false;
+                    connection_time = None;
+                    app_interface.aircraft_switched(&aircraft);
+                }
+                Some(AircraftChange::Restored) => {
+                    info!("[SIM] Session's aircraft loaded again, resuming sync");
+                    // Go through the usual delay, then get a full sync
+                    connection_time = Some(Instant::now());
+                    app_interface.aircraft_restored();
+                }
+                None => {}
+            }
+
+            let receive_start = Instant::now();
+
+            let mut pending = Vec::new();
             while let Ok(message) = client.get_next_message() {
+                pending.push(message);
+            }
+
+            let received_updates = pending
+                .iter()
+                .filter(|x| matches!(x, ReceiveMessage::Payload(Payloads::Update { .. })))
+                .count();
+
+            if receive_overload.is_overloaded() {
+                pending = coalesce_unreliable_updates(pending);
+            }
+
+            for message in pending {
                 match message {
                     ReceiveMessage::Payload(payload) => match payload {
                         // Unused
@@ -290,8 +652,41 @@ fn main() {
                         | Payloads::InvalidName { .. }
                         | Payloads::RequestHosting { .. }
                         | Payloads::InitHandshake { .. }
+                        | Payloads::DefinitionFragment { .. }
+                        | Payloads::ServerVersion { .. }
                         | Payloads::Heartbeat => {}
                         // Used
+                        Payloads::FollowPosition {
+                            from,
+                            latitude,
+                            longitude,
+                            altitude,
+                            heading,
+                        } => {
+                            // Only the host can lead
+                            if !client.is_host()
+                                && clients.client_is_server(&from)
+                                && ready_to_process_data
+                            {
+                                if following.is_none() {
+                                    info!("[FOLLOW] Following {}", from);
+                                    app_interface.following(&from);
+                                }
+                                following = Some(Instant::now());
+
+                                follow.snap_to(
+                                    &conn,
+                                    &Position {
+                                        latitude,
+                                        longitude,
+                                        altitude,
+                                        heading,
+                                    },
+                                    config.follow_offset_behind,
+                                    config.follow_offset_right,
+                                );
+                            }
+                        }
                         Payloads::Update {
                             data,
                             from,
@@ -310,7 +705,13 @@ fn main() {
                                 );
                             }
 
-                            if !clients.is_observer(&from) && ready_to_process_data {
+                            if !clients.is_observer(&from)
+                                && !clients.is_muted(&from)
+                                && ready_to_process_data
+                                && following.is_none()
+                                && !is_leading
+                                && !send_only_loopback
+                            {
                                 match definitions.on_receive_data(
                                     &conn,
                                     data,
@@ -319,6 +720,7 @@ fn main() {
                                         is_server: clients.client_is_server(&from),
                                         is_master: clients.client_has_control(&from),
                                         is_init: true,
+                                        role: clients.get_role(&from),
                                     },
                                 ) {
                                     Ok(_) => {}
@@ -329,6 +731,7 @@ fn main() {
                             }
                         }
                         Payloads::TransferControl { from, to } => {
+                            session_report.control_transferred();
                             // Someone is transferring controls to us
                             definitions.reset_sync();
                             if to == client.get_server_name() {
@@ -336,12 +739,34 @@ fn main() {
                                 control.take_control(&conn, &definitions.lvarstransfer.transfer);
                                 app_interface.gain_control();
                                 clients.set_no_control();
+
+                                if auto_observing {
+                                    info!("[CONTROL] Stopped observing after taking control");
+                                    auto_observing = false;
+                                    observing = false;
+                                    definitions.set_observing(false);
+                                    app_interface.observing(false);
+                                    client
+                                        .set_observer(client.get_server_name().to_string(), false);
+                                }
                             // Someone else has controls, if we have controls we let go and listen for their messages
                             } else {
                                 if from == client.get_server_name() {
                                     app_interface.lose_control();
                                     control
                                         .lose_control(&conn, &definitions.lvarstransfer.transfer);
+
+                                    if config.observe_when_not_in_control && !observing {
+                                        info!("[CONTROL] Observing after handing off control");
+                                        auto_observing = true;
+                                        observing = true;
+                                        definitions.set_observing(true);
+                                        app_interface.auto_observing();
+                                        client.set_observer(
+                                            client.get_server_name().to_string(),
+                                            true,
+                                        );
+                                    }
                                 }
                                 info!("[CONTROL] {} is now in control.", to);
                                 app_interface.set_incontrol(&to);
@@ -368,10 +793,39 @@ fn main() {
                                     name.clone(),
                                 );
 
-                                if config.instructor_mode {
+                                // Joining mid-flight shouldn't disturb whoever is flying
+                                let is_late = clients.any_ready()
+                                    || clients.get_client_in_control().is_some()
+                                    || control.has_control();
+
+                                if config.joiners_start_observing(is_late) {
                                     is_observer = true;
                                     client.set_observer(name.clone(), true);
                                 }
+
+                                if !config.motd.is_empty() {
+                                    client.send_motd(config.motd.clone(), name.clone());
+                                }
+
+                                if let Some((plan_name, bytes)) = flight_plan
+                                    .get_current()
+                                    .filter(|_| config.sync_flight_plan)
+                                {
+                                    client.send_flight_plan(
+                                        plan_name.clone(),
+                                        bytes.clone(),
+                                        Some(name.clone()),
+                                    );
+                                }
+
+                                // Roles given out before they joined
+                                for (peer, peer_role) in clients.get_restricted_roles() {
+                                    client.send_role(peer, peer_role, name.clone());
+                                }
+
+                                for (peer, tag) in clients.get_tags() {
+                                    client.send_tag(peer, tag, name.clone());
+                                }
                             }
 
                             app_interface.new_connection(&name);
@@ -383,9 +837,22 @@ fn main() {
                                 app_interface.set_incontrol(&name);
                                 clients.set_client_control(name);
                             }
+
+                            // Let the new player know we're away
+                            if is_afk {
+                                client.set_afk(true);
+                            }
+                        }
+                        Payloads::SetAfk {
+                            name,
+                            is_afk: peer_is_afk,
+                        } => {
+                            app_interface.set_afk(&name, peer_is_afk);
                         }
                         // Person is ready to receive data
-                        Payloads::Ready => {
+                        Payloads::Ready { from } => {
+                            clients.set_ready(&from);
+
                             if control.has_control() {
                                 client.update(definitions.get_all_current(), false);
                             }
@@ -398,11 +865,15 @@ fn main() {
                             info!("[NETWORK] {} lost connection.", name);
 
                             clients.remove_client(&name);
+                            session_metrics.remove_client(&name);
                             // User may have been in control
                             if clients.client_has_control(&name) {
                                 clients.set_no_control();
                                 // Transfer control to myself if I'm server
-                                if client.is_host() {
+                                if client.is_host() && !config.take_back_control_on_leave {
+                                    info!("[CONTROL] {} had control, leaving it unassigned.", name);
+                                    app_interface.control_unassigned(&name);
+                                } else if client.is_host() {
                                     info!("[CONTROL] {} had control, taking control back.", name);
                                     app_interface.gain_control();
 
@@ -422,6 +893,8 @@ fn main() {
                             if to == client.get_server_name() {
                                 info!("[CONTROL] Server set us to observing? {}", is_observer);
                                 observing = is_observer;
+                                auto_observing = false;
+                                definitions.set_observing(is_observer);
                                 app_interface.observing(is_observer);
 
                                 if !observing {
@@ -433,6 +906,43 @@ fn main() {
                                 app_interface.set_observing(&to, is_observer);
                             }
                         }
+                        Payloads::SetRole {
+                            from,
+                            to,
+                            role: new_role,
+                        } => {
+                            if !clients.client_is_server(&from) {
+                                warn!("[CONTROL] {} tried to set the role of {}", from, to);
+                            } else if to == client.get_server_name() {
+                                info!("[CONTROL] Server set our role to {:?}", new_role);
+                                role = new_role;
+                                app_interface.set_role(&to, new_role);
+                            } else {
+                                info!("[CONTROL] {} role set to {:?}", to, new_role);
+                                clients.set_role(&to, new_role);
+                                app_interface.set_role(&to, new_role);
+                            }
+                        }
+                        Payloads::SetTag { from, to, tag } => {
+                            if !clients.client_is_server(&from) {
+                                warn!("[CONTROL] {} tried to tag {}", from, to);
+                            } else {
+                                app_interface.set_peer_tag(&to, tag.as_deref());
+                                clients.set_tag(&to, tag);
+                            }
+                        }
+                        Payloads::RequestDefinitions { from } => {
+                            if client.is_host() {
+                                info!(
+                                    "[DEFINITIONS] {} received corrupted definitions, resending",
+                                    from
+                                );
+                                client.send_definitions(
+                                    definitions.get_buffer_bytes().into_boxed_slice(),
+                                    from,
+                                );
+                            }
+                        }
                         Payloads::SetHost => {
                             app_interface.set_host();
                             // Host was set which means successfully established connection to hoster, need to send definitions
@@ -444,19 +954,50 @@ fn main() {
                         Payloads::ConnectionDenied { reason } => {
                             client.stop(format!("Connection Denied: {}", reason));
                         }
-                        Payloads::AircraftDefinition { bytes } => {
+                        // Shown as their own messages so people know whether to wait or fix something
+                        Payloads::SessionFull => {
+                            client.stop("session_full".to_string());
+                        }
+                        Payloads::SessionLocked => {
+                            client.stop("session_locked".to_string());
+                        }
+                        Payloads::Banned => {
+                            client.stop("banned".to_string());
+                        }
+                        Payloads::InvalidPassword => {
+                            client.stop("invalid_password".to_string());
+                        }
+                        Payloads::NameAssigned { name } => {
+                            info!("[NETWORK] Name already in use, joined as {}", name);
+                            client.set_server_name(name.clone());
+                            app_interface.name_assigned(&name);
+                        }
+                        Payloads::AircraftDefinition { bytes, .. } => {
                             match definitions.load_config_from_bytes(bytes) {
                                 Ok(_) => {
                                     info!("[DEFINITIONS] Loaded and mapped {} aircraft vars, {} local vars, and {} events from the server", definitions.get_number_avars(), definitions.get_number_lvars(), definitions.get_number_events());
-                                    control.on_connected(&conn);
+                                    control.on_connected(
+                                        &conn,
+                                        &config.transfer_control_hotkey,
+                                        definitions.get_freeze_method(),
+                                    );
+                                    follow.on_connected(&conn);
+                                    aircraft_watch.on_connected(&conn);
+                                    flight_plan.on_connected(&conn);
 
                                     let def_connect_result = definitions.on_connected(&conn);
                                     if let Err(()) = def_connect_result {
-                                        client.stop("Error starting WS server. Do you have another YourControls open?".to_string())
+                                        if send_only_loopback {
+                                            warn!("[SYNC] Panel vars are served by the other instance, not syncing them here");
+                                        } else {
+                                            client.stop("Error starting WS server. Do you have another YourControls open?".to_string())
+                                        }
                                     }
                                     // Freeze aircraft
                                     control
                                         .lose_control(&conn, &definitions.lvarstransfer.transfer);
+                                    app_interface
+                                        .connection_stage(ConnectionStage::SyncingInitialState);
                                 }
                                 Err(e) => {
                                     error!("[DEFINITIONS] Could not load server sent configuration file: {}", e);
@@ -468,11 +1009,15 @@ fn main() {
                         Payloads::AttemptHosterConnection { peer } => {
                             match start_client(
                                 config.conn_timeout,
+                                Duration::from_secs(config.session_timeout),
+                                config.max_packet_size,
+                                Duration::from_millis(config.heartbeat_interval_ms),
+                                protocol_trace.clone(),
                                 client.get_server_name().to_string(),
                                 client.get_session_id(),
                                 updater.get_version().to_string(),
                                 false,
-                                Some(peer.ip()),
+                                Some((peer.ip(), 0)),
                                 None,
                                 Some(peer.port()),
                                 ConnectionMethod::Direct,
@@ -482,6 +1027,8 @@ fn main() {
                                         "[NETWORK] New client started to connect to hosted server."
                                     );
                                     *client = Box::new(new_client);
+                                    // Couldn't punch through, going through the hoster instead
+                                    connection_path = Some(ConnectionPath::Relayed);
                                 }
                                 Err(e) => {
                                     app_interface.client_fail(e.to_string().as_str());
@@ -492,6 +1039,23 @@ fn main() {
                                 }
                             };
                         }
+                        Payloads::Motd { message } => {
+                            // A relay may pass it on again whenever someone joins
+                            if !motd_shown {
+                                motd_shown = true;
+                                app_interface.motd(&message);
+                            }
+                        }
+                        Payloads::FlightPlan { name, bytes } => {
+                            if !client.is_host() && config.sync_flight_plan {
+                                match flight_plan.load(&conn, name.clone(), bytes) {
+                                    Ok(_) => app_interface.flight_plan_loaded(&name),
+                                    Err(e) => {
+                                        warn!("[FLIGHTPLAN] Could not load {}: {}", name, e)
+                                    }
+                                }
+                            }
+                        }
                         Payloads::SetSelfObserver { name } => {
                             if client.is_host() {
                                 clients.set_observer(&name, true);
@@ -502,6 +1066,14 @@ fn main() {
                     },
                     ReceiveMessage::Event(e) => match e {
                         Event::ConnectionEstablished => {
+                            session_start = Some(Instant::now());
+                            session_report.reset();
+                            // Players and the aircraft are only known a few seconds later
+                            summary_pending = true;
+                            if let Some(path) = connection_path {
+                                info!("[NETWORK] Connected via {:?}", path);
+                                app_interface.connection_path(path);
+                            }
                             if client.is_host() {
                                 // Display server started message
                                 app_interface.server_started();
@@ -517,15 +1089,44 @@ fn main() {
                                 // Display connected message
                                 app_interface.connected();
                                 app_interface.lose_control();
+                                app_interface.connection_stage(ConnectionStage::LoadingDefinitions);
+
+                                if let Some(session) = pending_session.take() {
+                                    config.last_session = Some(session.clone());
+                                    reconnect_session = Some(session);
+                                    if let Err(reason) =
+                                        write_configuration(&config_filename, &config)
+                                    {
+                                        app_interface.config_not_saved(&reason);
+                                    }
+                                    // Otherwise the UI's copy overwrites it on the next settings save
+                                    app_interface.send_config(&config.get_json_string());
+                                }
                             }
                         }
                         Event::ConnectionLost(reason) => {
                             info!("[NETWORK] Server/Client stopped. Reason: {}", reason);
+
+                            if let Some(start) = session_start {
+                                let report = session_report.to_text(start.elapsed(), &reason);
+                                if let Err(e) = std::fs::write(&session_report_filename, report) {
+                                    warn!("[NETWORK] Could not write session report: {}", e);
+                                }
+                            }
                             // TAKE BACK CONTROL
                             control.take_control(&conn, &definitions.lvarstransfer.transfer);
 
                             clients.reset();
+                            everyone_ready = false;
+                            lossy_controller = None;
+                            motd_shown = false;
                             observing = false;
+                            auto_observing = false;
+                            role = ClientRole::Full;
+                            is_leading = false;
+                            following = None;
+                            definitions.set_observing(false);
+                            is_afk = false;
                             should_set_none_client = true;
 
                             if let Err(e) = audio.play_disconnected() {
@@ -533,6 +1134,10 @@ fn main() {
                             }
 
                             app_interface.client_fail(&reason);
+
+                            if reconnect_session.is_some() {
+                                app_interface.can_reconnect();
+                            }
                         }
                         Event::UnablePunchthrough => app_interface.client_fail(
                             "Could not connect to host! Please port forward or use 'Cloud Host'!",
@@ -541,19 +1146,155 @@ fn main() {
                         Event::SessionIdFetchFailed => app_interface
                             .server_fail("Could not connect to Cloud Server to fetch session ID."),
 
+                        Event::ConnectionStage(stage) => app_interface.connection_stage(stage),
+                        Event::SendCongested(congested) => {
+                            send_congested = congested;
+                            app_interface
+                                .set_overloaded(send_congested || receive_overload.is_overloaded());
+                        }
                         Event::Metrics(metrics) => {
-                            app_interface.send_network(&metrics);
+                            session_metrics
+                                .update_totals(&metrics, definitions.get_interpolation_stats());
+                            app_interface.send_metrics(&session_metrics);
+                            session_report.add_sample(&session_metrics);
+                            last_metrics = Some(metrics);
+                        }
+                        Event::ClientMetrics(name, metrics) => {
+                            session_metrics.update_client(&name, &metrics);
+                            let packet_loss = metrics.packet_loss * 100.0;
+
+                            if config.max_controller_packet_loss > 0
+                                && clients.client_has_control(&name)
+                            {
+                                if packet_loss <= config.max_controller_packet_loss as f32 {
+                                    lossy_controller = None;
+                                } else {
+                                    match lossy_controller.as_ref() {
+                                        Some((lossy_name, since)) if *lossy_name == name => {
+                                            if since.elapsed().as_secs()
+                                                >= config.controller_loss_seconds
+                                            {
+                                                warn!(
+                                                    "[CONTROL] Relieving {}, {:.0}% packet loss",
+                                                    name, packet_loss
+                                                );
+                                                //Will send a loopback Payloads::TransferControl
+                                                client.take_control(name.clone());
+                                                app_interface.controller_relieved(&name);
+                                                lossy_controller = None;
+                                            }
+                                        }
+                                        _ => lossy_controller = Some((name, Instant::now())),
+                                    }
+                                }
+                            }
                         }
                     },
                 }
             }
 
-            if let Err(e) = definitions.step(&conn) {
-                client.stop(e.to_string());
+            // Let the host know once everyone has loaded in
+            if client.is_host() && clients.all_ready() != everyone_ready {
+                everyone_ready = !everyone_ready;
+                app_interface.set_all_ready(everyone_ready);
+            }
+
+            if !waiting_for_sim {
+                if let Err(e) = definitions.step(&conn) {
+                    client.stop(e.to_string());
+                }
+            }
+
+            let overloaded = received_updates > MAX_UPDATES_PER_LOOP
+                || receive_start.elapsed() > RECEIVE_TIME_BUDGET;
+
+            if let Some(overloaded) = receive_overload.update(overloaded) {
+                if overloaded {
+                    warn!(
+                        "[SYNC] Can't keep up with incoming updates, merging them until caught up"
+                    );
+                } else {
+                    info!("[SYNC] Caught up with incoming updates");
+                }
+
+                app_interface.set_overloaded(overloaded || send_congested);
+            }
+
+            if let Some(report) = definitions.take_snap_report() {
+                warn!(
+                    "[SYNC] Aircraft snapped, updates received before it:\n{}",
+                    report
+                );
+            }
+
+            // Let the user know about vars that aren't landing on their sim
+            if write_failure_timer.elapsed().as_secs() >= WRITE_FAILURE_REPORT_INTERVAL {
+                let failures = definitions.take_write_failures();
+
+                if !failures.is_empty() {
+                    for (var_name, count) in failures.iter() {
+                        warn!(
+                            "[DEFINITIONS] Could not write {} to the sim {} times",
+                            var_name, count
+                        );
+                        session_report.add_error(format!(
+                            "Could not write {} to the sim {} times",
+                            var_name, count
+                        ));
+                    }
+
+                    let mut var_names: Vec<&String> = failures.keys().collect();
+                    var_names.sort();
+                    app_interface.write_failed(&var_names);
+                }
+
+                write_failure_timer = Instant::now();
+            }
+
+            if let Some(start) = session_start {
+                if session_time_timer.elapsed().as_secs() >= SESSION_TIME_UPDATE_INTERVAL {
+                    app_interface.set_session_time(start.elapsed().as_secs());
+                    session_time_timer = Instant::now();
+                }
+            }
+
+            if is_leading && follow_timer.elapsed().as_secs() >= FOLLOW_UPDATE_INTERVAL {
+                if let Some(position) = follow.get_position() {
+                    client.send_follow_position(
+                        position.latitude,
+                        position.longitude,
+                        position.altitude,
+                        position.heading,
+                    );
+                }
+                follow_timer = Instant::now();
+            }
+
+            // The leader stopped or went away
+            if let Some(true) = following.map(|t| t.elapsed().as_secs() >= FOLLOW_TIMEOUT) {
+                info!("[FOLLOW] Stopped following");
+                following = None;
+                definitions.reset_sync();
+                app_interface.stopped_following();
+            }
+
+            // Tell everyone once we haven't touched the sim or the app for a while
+            if config.afk_timeout > 0 && ready_to_process_data {
+                let last_input = definitions.get_last_local_input().max(last_ui_input);
+                let should_be_afk = last_input.elapsed().as_secs() >= config.afk_timeout * 60;
+
+                if should_be_afk != is_afk {
+                    info!("[PROGRAM] AFK: {}", should_be_afk);
+                    is_afk = should_be_afk;
+                    client.set_afk(is_afk);
+                }
             }
 
             // Handle specific program triggered actions
-            if let Some(pending_action) = definitions.get_next_pending_action() {
+            if let Some(pending_action) = hotkey_action
+                .take()
+                .or_else(|| definitions.get_next_pending_action())
+            {
                 match pending_action {
                     ProgramAction::TakeControls => {
                         if !control.has_control() && !observing {
@@ -566,7 +1307,11 @@ fn main() {
                     ProgramAction::TransferControls => {
                         if control.has_control() {
                             if let Some(next_control) = clients.get_next_client_for_control() {
-                                client.transfer_control(next_control.clone())
+                                transfer_control_to(
+                                    &clients,
+                                    client.as_ref(),
+                                    next_control.clone(),
+                                );
                             }
                         } else if let Some(in_control) = clients.get_client_in_control() {
                             control.take_control(&conn, &definitions.lvarstransfer.transfer);
@@ -579,14 +1324,49 @@ fn main() {
             // Handle initial 3 second connection delay, allows lvars to be processed
             if let Some(true) = connection_time.map(|t| t.elapsed().as_secs() >= 3) {
                 // Do not let server send initial data - wait for data to get cleared on the previous loop
-                if !observing && ready_to_process_data {
+                // Followers and the leader only share positions
+                if !observing && ready_to_process_data && following.is_none() && !is_leading {
                     let permission = SyncPermission {
                         is_server: client.is_host(),
                         is_master: control.has_control(),
                         is_init: false,
+                        role,
                     };
 
-                    write_update_data(definitions.get_sync(&permission), client, true);
+                    let requested = config.update_rate.max(1);
+
+                    if sync_timer.elapsed() >= Duration::from_secs(1) / requested as u32 {
+                        sync_timer = Instant::now();
+                        write_update_data(definitions.get_sync(&permission), client, true);
+                        sync_rate.tick();
+                    }
+
+                    if let Some(achieved) = sync_rate.take_rate(Instant::now()) {
+                        let is_low = achieved < requested as f64 * 0.5;
+                        if is_low && !sync_rate_low {
+                            warn!(
+                                "[SYNC] Only achieving {:.0}Hz of the {}Hz sync rate",
+                                achieved, requested
+                            );
+                        } else if !is_low && sync_rate_low {
+                            info!("[SYNC] Sync rate back up to {:.0}Hz", achieved);
+                        }
+                        sync_rate_low = is_low;
+
+                        app_interface.set_sync_rate(requested, achieved);
+                        session_metrics.set_sync_rate(requested, achieved);
+                    }
+
+                    // Only changes get sent, so refresh anyone who missed one
+                    if config.event_driven_sync
+                        && keep_alive_timer.elapsed().as_secs() >= config.keep_alive_interval
+                    {
+                        write_update_data(definitions.get_keep_alive(&permission), client, false);
+                        keep_alive_timer = Instant::now();
+                    }
+                } else {
+                    // Time spent not syncing shouldn't count against the rate
+                    sync_rate.reset(Instant::now());
                 }
 
                 // Tell server we're ready to receive data after 3 seconds
@@ -596,13 +1376,72 @@ fn main() {
 
                     if !client.is_host() {
                         client.send_ready();
+                        app_interface.synced();
+                    }
+                }
+
+                if summary_pending {
+                    summary_pending = false;
+
+                    let mut players = vec![client.get_server_name().to_string()];
+                    players.extend(clients.get_client_names());
+
+                    // The host knows which config it picked, joiners only what the sim loaded
+                    let aircraft = if client.is_host() && !config_to_load.is_empty() {
+                        Some(config_to_load.trim_end_matches(".yaml"))
+                    } else {
+                        aircraft_watch.get_session_aircraft()
+                    };
+
+                    app_interface.connection_summary(
+                        &players,
+                        clients.get_client_in_control().map(String::as_str),
+                        control.has_control(),
+                        aircraft,
+                        connection_path,
+                    );
+                }
+            }
+        } else if awaiting_loaded_aircraft {
+            // Nothing else reads from the sim outside of a session
+            while let Ok(message) = conn.get_next_message() {
+                if let DispatchResult::SystemState(data) = message {
+                    if let Some(aircraft) = aircraft_watch.process_query(data) {
+                        awaiting_loaded_aircraft = false;
+
+                        let configs = get_aircraft_configs().unwrap_or_default();
+                        let suggestions = suggest_configs(&aircraft, &configs, 3);
+                        info!(
+                            "[DEFINITIONS] No config selected for {}, suggesting {:?}",
+                            aircraft, suggestions
+                        );
+                        app_interface.config_suggestions(&aircraft, &suggestions);
                     }
                 }
             }
         }
 
         // GUI
+        app_interface.flush();
+
         if let Ok(msg) = app_interface.get_next_message() {
+            last_ui_input = Instant::now();
+
+            // Rejoining is connecting again with what worked last time
+            let msg = match (msg, config.last_session.as_ref()) {
+                (AppMessage::RejoinLast, Some(last_session)) => {
+                    last_session.to_connect_message(config.name.clone())
+                }
+                (msg, _) => msg,
+            };
+
+            let msg = match (msg, reconnect_session.as_ref()) {
+                (AppMessage::Reconnect, Some(session)) => {
+                    session.to_connect_message(config.name.clone())
+                }
+                (msg, _) => msg,
+            };
+
             match msg {
                 AppMessage::StartServer {
                     username,
@@ -611,19 +1450,37 @@ fn main() {
                     method,
                     use_upnp,
                 } => {
-                    let connected = connect_to_sim(&mut conn, &mut definitions);
+                    // Reconnecting only makes sense for a session that was joined
+                    reconnect_session = None;
+                    let connected =
+                        connect_to_sim(&mut conn, &mut definitions, config.simconnect_name());
 
                     if config_to_load.is_empty() {
-                        app_interface.server_fail("Select an aircraft config first!");
+                        app_interface.server_fail("select_aircraft");
+                        // Point them to configs that might fit what they're flying
+                        if connected {
+                            aircraft_watch.query_loaded(&conn);
+                            awaiting_loaded_aircraft = true;
+                        }
                     } else if !load_definitions(&mut definitions, &mut config_to_load) {
-                        app_interface.error(
-                            "Error loading definition files. Check the log for more information.",
-                        );
+                        app_interface.error("definitions_load_failed");
                     } else if connected {
+                        definitions.set_event_driven(config.event_driven_sync);
+                        definitions.set_disabled_tags(&config.disabled_tags);
+                        definitions.set_interpolation_disabled(config.disable_interpolation);
+                        definitions.set_reliable_only(config.reliable_only);
                         definitions.on_connected(&conn).ok();
-                        control.on_connected(&conn);
+                        control.on_connected(
+                            &conn,
+                            &config.transfer_control_hotkey,
+                            definitions.get_freeze_method(),
+                        );
+                        follow.on_connected(&conn);
+                        aircraft_watch.on_connected(&conn);
+                        flight_plan.on_connected(&conn);
                         // Display attempting to start server
                         app_interface.attempt();
+                        connection_method = Some(method);
 
                         match method {
                             ConnectionMethod::Direct | ConnectionMethod::CloudServer => {
@@ -633,6 +1490,16 @@ fn main() {
                                     config.conn_timeout,
                                 ));
 
+                                server.set_rename_duplicate_names(config.rename_duplicate_names);
+                                server.set_max_packet_size(config.max_packet_size);
+                                server.set_session_timeout(Duration::from_secs(
+                                    config.session_timeout,
+                                ));
+                                server.set_heartbeat_interval(Duration::from_millis(
+                                    config.heartbeat_interval_ms,
+                                ));
+                                server.set_protocol_trace(protocol_trace.clone());
+
                                 let result = match method {
                                     ConnectionMethod::Direct => {
                                         server.start(is_ipv6, port, use_upnp)
@@ -645,6 +1512,24 @@ fn main() {
 
                                 match result {
                                     Ok(_) => {
+                                        connection_path = Some(match method {
+                                            ConnectionMethod::CloudServer => {
+                                                ConnectionPath::CloudServer
+                                            }
+                                            _ => match server.get_last_port_forward_result() {
+                                                Some(Ok(_)) => ConnectionPath::DirectUpnp,
+                                                _ => ConnectionPath::Direct,
+                                            },
+                                        });
+
+                                        if let Some(Err(e)) = server.get_last_port_forward_result()
+                                        {
+                                            app_interface.port_forward_failed(&e.to_string());
+                                        }
+                                        if let Some(addr) = server.get_local_address() {
+                                            app_interface.set_local_address(&addr.to_string());
+                                        }
+                                        server.set_network_simulation(config.network_simulation());
                                         // Assign server as transfer client
                                         transfer_client = Some(server);
                                         info!("[NETWORK] Server started");
@@ -662,9 +1547,23 @@ fn main() {
                                     config.conn_timeout,
                                 ));
 
+                                client.set_max_packet_size(config.max_packet_size);
+                                client.set_session_timeout(Duration::from_secs(
+                                    config.session_timeout,
+                                ));
+                                client.set_heartbeat_interval(Duration::from_millis(
+                                    config.heartbeat_interval_ms,
+                                ));
+                                client.set_protocol_trace(protocol_trace.clone());
+
                                 match client.start_with_relay(is_ipv6) {
                                     Ok(_) => {
+                                        if let Some(addr) = client.get_local_address() {
+                                            app_interface.set_local_address(&addr.to_string());
+                                        }
+                                        client.set_network_simulation(config.network_simulation());
                                         transfer_client = Some(client);
+                                        connection_path = Some(ConnectionPath::Relayed);
                                         info!("[NETWORK] Hosting started");
                                     }
                                     Err(e) => {
@@ -677,7 +1576,9 @@ fn main() {
 
                         config.port = port;
                         config.name = username;
-                        write_configuration(&config);
+                        if let Err(reason) = write_configuration(&config_filename, &config) {
+                            app_interface.config_not_saved(&reason);
+                        }
                     }
                 }
                 AppMessage::Connect {
@@ -689,25 +1590,65 @@ fn main() {
                     isipv6,
                     hostname,
                 } => {
-                    let connected = connect_to_sim(&mut conn, &mut definitions);
-
-                    if connected {
+                    let invalid_session_id = matches!(method, ConnectionMethod::CloudServer)
+                        && !session_id.as_deref().map_or(false, is_valid_session_id);
+                    let scoped_ip = ip.as_deref().map(parse_ip_with_scope).transpose();
+
+                    if invalid_session_id {
+                        // Would otherwise only fail once the rendezvous times out
+                        warn!(
+                            "[NETWORK] Refusing to connect with malformed session code {:?}",
+                            session_id
+                        );
+                        app_interface.client_fail("invalid_session_code");
+                    } else if let Err(e) = &scoped_ip {
+                        app_interface.client_fail(&e.to_string());
+                    } else if connect_to_sim(&mut conn, &mut definitions, config.simconnect_name())
+                    {
+                        definitions.set_event_driven(config.event_driven_sync);
+                        definitions.set_disabled_tags(&config.disabled_tags);
+                        definitions.set_interpolation_disabled(config.disable_interpolation);
+                        definitions.set_reliable_only(config.reliable_only);
                         // Display attempting to start server
                         app_interface.attempt();
+                        connection_method = Some(method);
+                        // Only remembered once the connection actually goes through
+                        pending_session = Some(LastSession {
+                            method,
+                            session_id: session_id.clone(),
+                            isipv6,
+                            ip: ip.clone(),
+                            hostname: hostname.clone(),
+                            port,
+                        });
+
+                        if hostname.is_some() && matches!(method, ConnectionMethod::Direct) {
+                            app_interface.connection_stage(ConnectionStage::ResolvingHost);
+                        }
 
                         match start_client(
                             config.conn_timeout,
+                            Duration::from_secs(config.session_timeout),
+                            config.max_packet_size,
+                            Duration::from_millis(config.heartbeat_interval_ms),
+                            protocol_trace.clone(),
                             username.clone(),
                             session_id,
                             updater.get_version().to_string(),
                             isipv6,
-                            ip,
+                            scoped_ip.ok().flatten(),
                             hostname,
                             port,
                             method,
                         ) {
                             Ok(client) => {
+                                connection_path = Some(match method {
+                                    ConnectionMethod::Direct => ConnectionPath::Direct,
+                                    ConnectionMethod::CloudServer => ConnectionPath::CloudServer,
+                                    ConnectionMethod::Relay => ConnectionPath::Relayed,
+                                });
                                 info!("[NETWORK] Client started.");
+                                client.set_network_simulation(config.network_simulation());
                                 transfer_client = Some(Box::new(client));
                             }
                             Err(e) => {
@@ -719,25 +1660,45 @@ fn main() {
                         // Write config with new values
                         config.name = username;
                         config.port = port.unwrap_or(config.port);
-                        config.ip = if let Some(ip) = ip {
-                            ip.to_string()
-                        } else {
-                            String::new()
-                        };
-                        write_configuration(&config);
+                        config.ip = ip.unwrap_or_default();
+                        if let Err(reason) = write_configuration(&config_filename, &config) {
+                            app_interface.config_not_saved(&reason);
+                        }
                     }
                 }
+                AppMessage::ValidateAddress { address, port } => {
+                    app_interface.address_validated(
+                        validate_address(address.trim())
+                            .err()
+                            .map(|e| e.to_string()),
+                        validate_port(&port).err().map(|e| e.to_string()),
+                    );
+                }
+                AppMessage::ToggleWindow => app_interface.toggle_window(),
+                AppMessage::RequestDisconnect => app_interface.request_disconnect(),
+                AppMessage::TransferControlToNext => {
+                    hotkey_action = Some(ProgramAction::TransferControls);
+                }
                 AppMessage::Disconnect => {
                     info!("[NETWORK] Request to disconnect.");
+                    reconnect_session = None;
                     if let Some(client) = transfer_client.as_mut() {
                         client.stop("Stopped.".to_string());
                     }
                 }
+                AppMessage::CancelConnect => {
+                    // Once established it's a disconnect instead
+                    if session_start.is_none() {
+                        if let Some(client) = transfer_client.as_mut() {
+                            info!("[NETWORK] Connection attempt cancelled.");
+                            pending_session = None;
+                            client.stop("connect_cancelled".to_string());
+                        }
+                    }
+                }
                 AppMessage::TransferControl { target } => {
                     if let Some(client) = transfer_client.as_ref() {
-                        info!("[CONTROL] Giving control to {}", target);
-                        // Send server message, will send a loopback Payloads::TransferControl
-                        client.transfer_control(target.clone());
+                        transfer_control_to(&clients, client.as_ref(), target);
                     }
                 }
                 AppMessage::SetObserver {
@@ -750,6 +1711,59 @@ fn main() {
                         client.set_observer(target, is_observer);
                     }
                 }
+                AppMessage::SetPeerMuted { target, is_muted } => {
+                    info!("[CONTROL] Muting updates from {}? {}", target, is_muted);
+                    clients.set_muted(&target, is_muted);
+                }
+                AppMessage::SetPeerTag { target, tag } => {
+                    if let Some(client) = transfer_client.as_ref().filter(|x| x.is_host()) {
+                        app_interface.set_peer_tag(&target, tag.as_deref());
+                        clients.set_tag(&target, tag.clone());
+                        client.set_tag(target, tag);
+                    }
+                }
+                AppMessage::SetPeerRole { target, role } => {
+                    if let Some(client) = transfer_client.as_ref().filter(|x| x.is_host()) {
+                        info!("[CONTROL] Setting {} role to {:?}", target, role);
+                        clients.set_role(&target, role);
+                        app_interface.set_role(&target, role);
+                        client.set_role(target, role);
+                    }
+                }
+                AppMessage::ResendDefinitions { target } => {
+                    if let Some(client) = transfer_client.as_ref().filter(|x| x.is_host()) {
+                        let targets = match target {
+                            Some(target) => vec![target],
+                            None => clients.get_client_names(),
+                        };
+
+                        let bytes = definitions.get_buffer_bytes().into_boxed_slice();
+
+                        for target in targets {
+                            info!("[DEFINITIONS] Resending definitions to {}", target);
+                            client.send_definitions(bytes.clone(), target);
+                        }
+                    }
+                }
+                AppMessage::RejoinLast => {
+                    app_interface.error("no_last_session");
+                }
+                AppMessage::Reconnect => {
+                    app_interface.error("no_reconnect_session");
+                }
+                AppMessage::SetLeading {
+                    is_leading: leading,
+                } => {
+                    if transfer_client
+                        .as_ref()
+                        .map(|x| x.is_host())
+                        .unwrap_or(false)
+                    {
+                        info!("[FOLLOW] Leading: {}", leading);
+                        is_leading = leading;
+                        definitions.reset_sync();
+                    }
+                }
                 AppMessage::GoObserver => {
                     if let Some(client) = transfer_client.as_ref() {
                         // Requests server to set self as observer
@@ -766,18 +1780,41 @@ fn main() {
                 }
                 AppMessage::Startup => {
                     // List aircraft
-                    if let Ok(configs) = get_aircraft_configs() {
-                        info!(
-                            "[DEFINITIONS] Found {} configuration file(s).",
-                            configs.len()
-                        );
+                    match get_aircraft_configs() {
+                        Ok(configs) => {
+                            info!(
+                                "[DEFINITIONS] Found {} configuration file(s).",
+                                configs.len()
+                            );
 
-                        for aircraft_config in configs {
-                            app_interface.add_aircraft(&aircraft_config);
+                            for aircraft_config in configs {
+                                app_interface.add_aircraft(&aircraft_config);
+                            }
                         }
+                        // Usually a partial install, tell the user where the folder should be
+                        Err(e) => {
+                            let expected_path = env::current_dir()
+                                .map(|dir| dir.join(AIRCRAFT_DEFINITIONS_PATH))
+                                .unwrap_or_else(|_| PathBuf::from(AIRCRAFT_DEFINITIONS_PATH));
+
+                            error!(
+                                "[DEFINITIONS] Could not read {}: {}",
+                                expected_path.display(),
+                                e
+                            );
+                            app_interface.definitions_missing(&expected_path.display().to_string());
+                        }
+                    }
+
+                    // Picked through the local API before the UI was opened
+                    if !config_to_load.is_empty() {
+                        app_interface.select_aircraft(&config_to_load);
                     }
 
                     app_interface.send_config(&config.get_json_string());
+                    if let Some(reason) = config_write_error.take() {
+                        app_interface.config_not_saved(&reason);
+                    }
                     // Update version
                     let app_version = updater.get_version();
                     if let Ok(newest_version) = updater.get_latest_version() {
@@ -807,27 +1844,163 @@ fn main() {
                         }
                     };
                 }
-                AppMessage::UpdateConfig { new_config: config } => {
-                    audio.mute(config.sound_muted);
-                    write_configuration(&config);
+                AppMessage::UpdateConfig { mut new_config } => {
+                    // Kept by the app, not edited in the settings
+                    new_config.last_session = config.last_session.take();
+                    audio.mute(new_config.sound_muted);
+                    definitions.set_disabled_tags(&new_config.disabled_tags);
+                    if let Some(client) = transfer_client.as_ref() {
+                        client.set_network_simulation(new_config.network_simulation());
+                    }
+                    if new_config.protocol_tracing != protocol_trace.is_some() {
+                        protocol_trace = if new_config.protocol_tracing {
+                            open_protocol_trace(&protocol_log_filename)
+                        } else {
+                            None
+                        };
+                    }
+                    if new_config.local_api && !config.local_api {
+                        if let Err(e) = local_api.start() {
+                            warn!("[API] Could not start the local API! Reason: {}", e);
+                        }
+                    } else if !new_config.local_api {
+                        local_api.stop();
+                    }
+                    if let Err(reason) = write_configuration(&config_filename, &new_config) {
+                        app_interface.config_not_saved(&reason);
+                    }
+                    config = new_config;
+                }
+                AppMessage::ExportConfig => {
+                    app_interface.send_config_export(&config.export_shared());
+                }
+                AppMessage::ImportConfig { text } => match config.import_shared(&text) {
+                    // Saved and applied by the UI the same way as edited settings
+                    Ok(new_config) => app_interface.config_imported(&new_config.get_json_string()),
+                    Err(e) => {
+                        warn!("[PROGRAM] Could not import shared config! Reason: {}", e);
+                        app_interface.config_import_failed(&e.to_string());
+                    }
+                },
+                AppMessage::CopyDiagnostics => {
+                    app_interface.send_diagnostics(&diagnostics::build_report(
+                        &updater.get_version().to_string(),
+                        &config,
+                        connection_method.as_ref(),
+                        last_metrics.as_ref(),
+                        &log_filename,
+                    ));
                 }
                 AppMessage::ForceTakeControl => {
                     if let Some(client) = transfer_client.as_ref() {
                         if let Some(client_name) = clients.get_client_in_control() {
                             //Will send a loopback Payloads::TransferControl
                             client.take_control(client_name.clone())
+                        } else if client.is_host() && !control.has_control() {
+                            // Left unassigned after whoever had it left
+                            client.transfer_control(client.get_server_name().to_string())
+                        }
+                    }
+                }
+                AppMessage::PushFullState => {
+                    if let Some(client) = transfer_client.as_ref() {
+                        // Only the controller's state is authoritative
+                        if control.has_control() {
+                            info!("[SYNC] Pushing full state to everyone");
+                            client.update(definitions.get_all_current(), false);
+                            app_interface.state_pushed();
+                        }
+                    }
+                }
+                AppMessage::ReclaimControl => {
+                    if let Some(client) = transfer_client.as_ref() {
+                        if client.is_host() {
+                            info!("[CONTROL] Reclaiming control. Was observing? {}", observing);
+
+                            if observing {
+                                observing = false;
+                                auto_observing = false;
+                                definitions.set_observing(false);
+                                definitions.reset_sync();
+                                app_interface.observing(false);
+                                client.set_observer(client.get_server_name().to_string(), false);
+                            }
+
+                            if let Some(client_name) = clients.get_client_in_control() {
+                                //Will send a loopback Payloads::TransferControl
+                                client.take_control(client_name.clone())
+                            } else if !control.has_control() {
+                                client.transfer_control(client.get_server_name().to_string())
+                            }
                         }
                     }
                 }
             }
         }
 
+        if let Some(message) = local_api.poll() {
+            let response = match message.request {
+                ApiRequest::GetMetrics => ApiResponse::Metrics {
+                    metrics: session_metrics.clone(),
+                },
+                ApiRequest::ListAircraft => match get_aircraft_configs() {
+                    Ok(configs) => ApiResponse::AircraftList {
+                        configs: configs
+                            .iter()
+                            .map(|x| AircraftConfigInfo::from_file_name(x))
+                            .collect(),
+                    },
+                    Err(e) => ApiResponse::Error {
+                        reason: e.to_string(),
+                    },
+                },
+                ApiRequest::LoadAircraft { config_file_name } => {
+                    let exists = get_aircraft_configs()
+                        .map(|configs| configs.contains(&config_file_name))
+                        .unwrap_or(false);
+
+                    if exists && transfer_client.is_none() {
+                        info!(
+                            "[API] {} aircraft config selected by the local API.",
+                            config_file_name
+                        );
+                        app_interface.select_aircraft(&config_file_name);
+                        config_to_load.clone_from(&config_file_name);
+
+                        ApiResponse::AircraftLoaded { config_file_name }
+                    } else if exists {
+                        ApiResponse::Error {
+                            reason: "Cannot change aircraft while connected".to_string(),
+                        }
+                    } else {
+                        ApiResponse::Error {
+                            reason: format!("No aircraft config named {}", config_file_name),
+                        }
+                    }
+                }
+            };
+
+            local_api.respond(message.stream_id, response);
+        }
+
         if should_set_none_client {
             // Prevent sending any more data
             transfer_client = None;
             should_set_none_client = false;
             ready_to_process_data = false;
             connection_time = None;
+            session_start = None;
+            waiting_for_sim = false;
+            aircraft_watch.reset();
+            awaiting_loaded_aircraft = false;
+            summary_pending = false;
+            session_metrics.reset();
+            session_report.reset();
+            flight_plan.reset();
+            receive_overload.reset();
+            send_congested = false;
+            sync_rate.reset(Instant::now());
+            sync_rate_low = false;
             conn.close();
         }
 
@@ -0,0 +1,201 @@
+use laminar::Metrics;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::sync::gaugecommunicator::InterpolationStats;
+
+// Connection quality to one client, only known by the host
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientLinkMetrics {
+    pub rtt_ms: f64,
+    // 0 to 1
+    pub packet_loss: f64,
+}
+
+// Sent to the UI and the local API. Fields are only ever added to, tools rely on the names.
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMetrics {
+    // Totals over every connection, per second
+    pub sent_packets: u64,
+    pub received_packets: u64,
+    pub sent_kbps: f64,
+    pub received_kbps: f64,
+    // 0 to 1
+    pub packet_loss: f64,
+    pub rtt_ms: f64,
+    // Of the updates received, as seen by interpolation
+    pub jitter_ms: f64,
+    pub late_packets: u32,
+    // Updates sent per second, achieved is None until first measured
+    pub requested_sync_rate: u64,
+    pub achieved_sync_rate: Option<f64>,
+    // Keyed by name
+    pub clients: BTreeMap<String, ClientLinkMetrics>,
+}
+
+impl SessionMetrics {
+    pub fn update_totals(&mut self, metrics: &Metrics, interpolation: &InterpolationStats) {
+        self.sent_packets = metrics.sent_packets as u64;
+        self.received_packets = metrics.received_packets as u64;
+        self.sent_kbps = metrics.sent_kbps as f64;
+        self.received_kbps = metrics.receive_kbps as f64;
+        self.packet_loss = metrics.packet_loss as f64;
+        self.rtt_ms = metrics.rtt as f64;
+        self.jitter_ms = interpolation.jitter * 1000.0;
+        self.late_packets = interpolation.late;
+    }
+
+    pub fn update_client(&mut self, name: &str, metrics: &Metrics) {
+        self.clients.insert(
+            name.to_string(),
+            ClientLinkMetrics {
+                rtt_ms: metrics.rtt as f64,
+                packet_loss: metrics.packet_loss as f64,
+            },
+        );
+    }
+
+    pub fn remove_client(&mut self, name: &str) {
+        self.clients.remove(name);
+    }
+
+    pub fn set_sync_rate(&mut self, requested: u64, achieved: f64) {
+        self.requested_sync_rate = requested;
+        self.achieved_sync_rate = Some(achieved);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// Only the first of these are kept, a session with a broken config could otherwise grow it forever
+const MAX_REPORTED_ERRORS: usize = 20;
+
+// Totals over a whole session, written out when it ends
+#[derive(Default)]
+pub struct SessionReport {
+    samples: u32,
+    total_kbps: f64,
+    peak_kbps: f64,
+    total_packet_loss: f64,
+    peak_packet_loss: f64,
+    control_transfers: u32,
+    errors: Vec<String>,
+}
+
+impl SessionReport {
+    pub fn add_sample(&mut self, metrics: &SessionMetrics) {
+        let kbps = metrics.sent_kbps + metrics.received_kbps;
+
+        self.samples += 1;
+        self.total_kbps += kbps;
+        self.peak_kbps = self.peak_kbps.max(kbps);
+        self.total_packet_loss += metrics.packet_loss;
+        self.peak_packet_loss = self.peak_packet_loss.max(metrics.packet_loss);
+    }
+
+    pub fn control_transferred(&mut self) {
+        self.control_transfers += 1;
+    }
+
+    pub fn add_error(&mut self, error: String) {
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(error);
+        }
+    }
+
+    pub fn to_text(&self, duration: Duration, end_reason: &str) -> String {
+        let average = |total: f64| {
+            if self.samples == 0 {
+                0.0
+            } else {
+                total / self.samples as f64
+            }
+        };
+
+        let seconds = duration.as_secs();
+        let mut report = String::new();
+
+        writeln!(
+            report,
+            "Duration: {}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+        .ok();
+        writeln!(report, "Ended: {}", end_reason).ok();
+        writeln!(
+            report,
+            "Bandwidth: {:.2} KB/s average, {:.2} KB/s peak",
+            average(self.total_kbps),
+            self.peak_kbps
+        )
+        .ok();
+        writeln!(
+            report,
+            "Packet loss: {:.2}% average, {:.2}% peak",
+            average(self.total_packet_loss) * 100.0,
+            self.peak_packet_loss * 100.0
+        )
+        .ok();
+        writeln!(report, "Control transfers: {}", self.control_transfers).ok();
+        writeln!(report, "Errors: {}", self.errors.len()).ok();
+
+        for error in self.errors.iter() {
+            writeln!(report, "  {}", error).ok();
+        }
+
+        report
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics_field_names() {
+        let mut metrics = SessionMetrics::default();
+        metrics.update_client("Pilot", &Metrics::default());
+
+        let value = serde_json::to_value(&metrics).unwrap();
+        assert!(value.get("receivedKbps").is_some());
+        assert!(value.get("achievedSyncRate").unwrap().is_null());
+        assert!(value["clients"]["Pilot"].get("rttMs").is_some());
+
+        metrics.remove_client("Pilot");
+        assert!(metrics.clients.is_empty());
+    }
+
+    #[test]
+    fn test_session_report() {
+        let mut report = SessionReport::default();
+        let mut metrics = SessionMetrics::default();
+
+        metrics.sent_kbps = 10.0;
+        metrics.packet_loss = 0.1;
+        report.add_sample(&metrics);
+        metrics.sent_kbps = 30.0;
+        metrics.packet_loss = 0.0;
+        report.add_sample(&metrics);
+        report.control_transferred();
+        report.add_error("Could not write A".to_string());
+
+        let text = report.to_text(Duration::from_secs(3725), "Disconnected");
+        assert!(text.contains("Duration: 1:02:05"));
+        assert!(text.contains("20.00 KB/s average, 30.00 KB/s peak"));
+        assert!(text.contains("5.00% average, 10.00% peak"));
+        assert!(text.contains("Control transfers: 1"));
+        assert!(text.contains("  Could not write A"));
+    }
+}
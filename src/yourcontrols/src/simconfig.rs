@@ -1,17 +1,71 @@
+use base64::Engine;
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::io::Write;
-use std::{convert::AsRef, fs::File, io};
+use std::time::Duration;
+use std::{convert::AsRef, fs, fs::File, io};
+
+use crate::app::{AppMessage, ConnectionMethod};
+use yourcontrols_net::NetworkSimulation;
+
+const DEFAULT_SIMCONNECT_NAME: &str = "YourControls";
+// About each user or their machine, left out of shared configs and never overwritten by one.
+// Debugging options too, so a shared config can't quietly degrade someone's sessions or write traces.
+const PERSONAL_FIELDS: &[&str] = &[
+    "name",
+    "ui_dark_theme",
+    "sound_muted",
+    "language",
+    "transfer_control_hotkey",
+    "simconnect_name",
+    "last_session",
+    "simulated_latency_ms",
+    "simulated_packet_loss",
+    "protocol_tracing",
+    "local_api",
+];
 
 #[derive(From, Display)]
 pub enum ConfigLoadError {
     FileError(io::Error),
     SerializeError(serde_json::Error),
+    DecodeError(base64::DecodeError),
+    InvalidSetting(String),
+}
+
+// Connection details of the last session that was joined successfully
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LastSession {
+    pub method: ConnectionMethod,
+    pub session_id: Option<String>,
+    pub isipv6: bool,
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl LastSession {
+    pub fn to_connect_message(&self, username: String) -> AppMessage {
+        AppMessage::Connect {
+            username,
+            session_id: self.session_id.clone(),
+            isipv6: self.isipv6,
+            ip: self.ip.clone(),
+            hostname: self.hostname.clone(),
+            port: self.port,
+            method: self.method,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
 pub struct Config {
+    // Seconds to wait for a peer while connecting
     pub conn_timeout: u64,
+    // Seconds without hearing from a peer before dropping it once connected
+    pub session_timeout: u64,
     pub check_for_betas: bool,
     pub port: u16,
     pub ip: String,
@@ -20,6 +74,62 @@ pub struct Config {
     pub streamer_mode: bool,
     pub instructor_mode: bool,
     pub sound_muted: bool,
+    // Joiners start as observers until the host promotes them
+    pub observers_by_default: bool,
+    // Only joiners arriving once a flight is under way start as observers
+    pub late_joiners_observe: bool,
+    // Joiners with a taken name get a numbered one instead of being rejected
+    pub rename_duplicate_names: bool,
+    // Take control back when whoever had it leaves, otherwise stay frozen until control is taken
+    pub take_back_control_on_leave: bool,
+    // Observe after handing off control, until control is taken again
+    pub observe_when_not_in_control: bool,
+    // Minutes without input before showing as AFK, 0 to disable
+    pub afk_timeout: u64,
+    // Aircraft definitions bigger than this many bytes are sent in pieces
+    pub max_packet_size: usize,
+    // Milliseconds between heartbeats, which also stop NAT mappings expiring when little else is sent
+    pub heartbeat_interval_ms: u64,
+    // Keep the session open while the sim restarts instead of disconnecting
+    pub keep_session_on_sim_exit: bool,
+    // UI language code, messages missing from it fall back to English
+    pub language: String,
+    // Meters behind and to the right of the leader to sit at while following
+    pub follow_offset_behind: f64,
+    pub follow_offset_right: f64,
+    // SimConnect key combination that passes or takes control, empty to disable
+    pub transfer_control_hotkey: String,
+    // Sync passes per second to aim for, up to the main loop's 100
+    pub update_rate: u64,
+    // Only send vars that changed, plus a full snapshot every keep_alive_interval seconds
+    pub event_driven_sync: bool,
+    pub keep_alive_interval: u64,
+    // Definition tags (e.g. sound) that shouldn't be synced
+    pub disabled_tags: Vec<String>,
+    // Apply received positions right away instead of smoothing them, for fast LAN connections
+    pub disable_interpolation: bool,
+    // Send every update reliably, trading latency for nothing getting lost on a bad connection
+    pub reliable_only: bool,
+    // Host takes control back when the pilot in control has more than this percent packet loss
+    // for controller_loss_seconds in a row, 0 to disable
+    pub max_controller_packet_loss: u64,
+    pub controller_loss_seconds: u64,
+    // Shown to everyone who joins a session we host, empty for none
+    pub motd: String,
+    // Ask in the UI before leaving or closing a session
+    pub confirm_disconnect: bool,
+    // Send our active flight plan when hosting, load the host's when joining
+    pub sync_flight_plan: bool,
+    // For testing, delay and drop what we receive as if over a bad connection
+    pub simulated_latency_ms: u64,
+    pub simulated_packet_loss: u64,
+    // Write every payload sent and received to protocol.log, from the next connection on
+    pub protocol_tracing: bool,
+    // Name we connect to SimConnect with, to tell instances and other tools apart
+    pub simconnect_name: String,
+    // Unauthenticated WebSocket on 127.0.0.1 for launchers to list and pick aircraft configs
+    pub local_api: bool,
+    pub last_session: Option<LastSession>,
 }
 
 impl Default for Config {
@@ -27,6 +137,7 @@ impl Default for Config {
         Self {
             port: 25071,
             conn_timeout: 5,
+            session_timeout: 5,
             check_for_betas: false,
             ip: String::new(),
             name: String::new(),
@@ -34,16 +145,64 @@ impl Default for Config {
             streamer_mode: false,
             instructor_mode: false,
             sound_muted: false,
+            observers_by_default: false,
+            late_joiners_observe: false,
+            rename_duplicate_names: true,
+            take_back_control_on_leave: true,
+            observe_when_not_in_control: false,
+            afk_timeout: 5,
+            max_packet_size: 1200,
+            heartbeat_interval_ms: 500,
+            keep_session_on_sim_exit: false,
+            language: "en".to_string(),
+            follow_offset_behind: 50.0,
+            follow_offset_right: 50.0,
+            transfer_control_hotkey: String::new(),
+            event_driven_sync: false,
+            update_rate: 100,
+            keep_alive_interval: 5,
+            disabled_tags: Vec::new(),
+            disable_interpolation: false,
+            reliable_only: false,
+            max_controller_packet_loss: 0,
+            controller_loss_seconds: 10,
+            motd: String::new(),
+            confirm_disconnect: true,
+            sync_flight_plan: false,
+            simulated_latency_ms: 0,
+            simulated_packet_loss: 0,
+            protocol_tracing: false,
+            simconnect_name: DEFAULT_SIMCONNECT_NAME.to_string(),
+            local_api: false,
+            last_session: None,
         }
     }
 }
 
 impl Config {
+    // Written to a temp file and renamed over the old one so a crash mid-write can't corrupt it
     pub fn write_to_file(&self, path: impl AsRef<std::path::Path>) -> Result<(), ConfigLoadError> {
+        // Renaming over it would still work on some systems, but it was made read-only for a reason
+        if let Ok(metadata) = fs::metadata(path.as_ref()) {
+            if metadata.permissions().readonly() {
+                return Err(ConfigLoadError::FileError(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "the file is read-only",
+                )));
+            }
+        }
+
         let data_string = serde_json::to_string_pretty(self)?;
 
-        let mut file = File::create(path)?;
+        let mut temp_path = path.as_ref().as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut file = File::create(&temp_path)?;
         file.write_all(data_string.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, path)?;
 
         Ok(())
     }
@@ -56,7 +215,128 @@ impl Config {
         Ok(config)
     }
 
+    // Base64 of the settings a community would want everyone to use, to be pasted into import_shared
+    pub fn export_shared(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap();
+
+        if let Some(fields) = value.as_object_mut() {
+            for field in PERSONAL_FIELDS {
+                fields.remove(*field);
+            }
+        }
+
+        base64::engine::general_purpose::STANDARD.encode(value.to_string())
+    }
+
+    // This config with the shared settings applied over it, rejecting anything that isn't a valid setting
+    pub fn import_shared(&self, text: &str) -> Result<Self, ConfigLoadError> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(text.trim())?;
+        let shared: Map<String, Value> = serde_json::from_slice(&bytes)?;
+
+        let mut value = serde_json::to_value(self)?;
+        let fields = value.as_object_mut().unwrap();
+
+        for (name, field) in shared {
+            if PERSONAL_FIELDS.contains(&name.as_str()) || !fields.contains_key(&name) {
+                return Err(ConfigLoadError::InvalidSetting(format!(
+                    "{} can't be shared",
+                    name
+                )));
+            }
+            fields.insert(name, field);
+        }
+
+        let config: Self = serde_json::from_value(value)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // Values the settings form wouldn't let through, but a hand edited shared config could
+    fn validate(&self) -> Result<(), ConfigLoadError> {
+        let invalid = if self.port == 0 {
+            Some("port")
+        } else if self.conn_timeout == 0 {
+            Some("conn_timeout")
+        } else if self.heartbeat_interval_ms == 0 {
+            Some("heartbeat_interval_ms")
+        } else if self.session_timeout == 0
+            || self.session_timeout.saturating_mul(1000) <= self.heartbeat_interval_ms
+        {
+            // Peers would be dropped between heartbeats
+            Some("session_timeout")
+        } else if self.update_rate == 0 || self.update_rate > 100 {
+            Some("update_rate")
+        } else if self.max_packet_size == 0 {
+            Some("max_packet_size")
+        } else if self.max_controller_packet_loss > 100 {
+            Some("max_controller_packet_loss")
+        } else if self.simulated_packet_loss > 100 {
+            Some("simulated_packet_loss")
+        } else {
+            None
+        };
+
+        match invalid {
+            Some(name) => Err(ConfigLoadError::InvalidSetting(format!(
+                "{} is out of range",
+                name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn joiners_start_observing(&self, is_late: bool) -> bool {
+        self.observers_by_default || self.instructor_mode || (is_late && self.late_joiners_observe)
+    }
+
+    pub fn simconnect_name(&self) -> &str {
+        match self.simconnect_name.trim() {
+            "" => DEFAULT_SIMCONNECT_NAME,
+            name => name,
+        }
+    }
+
+    pub fn network_simulation(&self) -> NetworkSimulation {
+        NetworkSimulation {
+            latency: Duration::from_millis(self.simulated_latency_ms),
+            packet_loss: self.simulated_packet_loss.min(100) as f64 / 100.0,
+        }
+    }
+
     pub fn get_json_string(&self) -> String {
         serde_json::to_value(self).unwrap().to_string()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shared_config_leaves_out_debug_options() {
+        let exported = Config::default().export_shared();
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(exported)
+            .unwrap();
+        let shared: Map<String, Value> = serde_json::from_slice(&bytes).unwrap();
+
+        assert!(!shared.contains_key("simulated_latency_ms"));
+        assert!(!shared.contains_key("simulated_packet_loss"));
+        assert!(!shared.contains_key("protocol_tracing"));
+    }
+
+    #[test]
+    fn test_import_cant_set_debug_options() {
+        let config = Config::default();
+
+        for field in [
+            r#"{"simulated_latency_ms": 500}"#,
+            r#"{"simulated_packet_loss": 50}"#,
+            r#"{"protocol_tracing": true}"#,
+        ] {
+            let text = base64::engine::general_purpose::STANDARD.encode(field);
+            assert!(config.import_shared(&text).is_err());
+        }
+    }
+}
@@ -2,24 +2,68 @@ use simconnect::SimConnector;
 
 use super::gaugecommunicator::GaugeCommunicator;
 
+// Kept far away from the ids handed out to definition events
+pub const TRANSFER_HOTKEY_EVENT_ID: u32 = 100_000;
+const TRANSFER_HOTKEY_GROUP_ID: u32 = 100;
+const FREEZE_EVENT_BASE_ID: u32 = 1000;
+
+// Used unless the aircraft definitions pick their own way to freeze
+const DEFAULT_FREEZE_EVENTS: [&str; 3] = [
+    "FREEZE_LATITUDE_LONGITUDE_SET",
+    "FREEZE_ALTITUDE_SET",
+    "FREEZE_ATTITUDE_SET",
+];
+
 pub struct Control {
     has_control: bool,
+    // Set to 1 while frozen and 0 otherwise, L: vars go through the gauge
+    freeze_events: Vec<String>,
+    freeze_lvars: Vec<String>,
+    // Only keeps track of who has control without freezing anything, for an instance sharing the sim with another
+    disabled: bool,
 }
 
 impl Control {
     pub fn new() -> Self {
-        Self { has_control: false }
+        Self {
+            has_control: false,
+            freeze_events: Vec::new(),
+            freeze_lvars: Vec::new(),
+            disabled: false,
+        }
+    }
+
+    pub fn set_disabled(&mut self, disabled: bool) {
+        self.disabled = disabled;
     }
 
     pub fn do_transfer(&mut self, conn: &SimConnector) {
-        conn.transmit_client_event(1, 1000, !self.has_control as u32, 5, 0);
-        conn.transmit_client_event(1, 1001, !self.has_control as u32, 5, 0);
-        conn.transmit_client_event(1, 1002, !self.has_control as u32, 5, 0);
+        for index in 0..self.freeze_events.len() {
+            conn.transmit_client_event(
+                1,
+                FREEZE_EVENT_BASE_ID + index as u32,
+                !self.has_control as u32,
+                5,
+                0,
+            );
+        }
+    }
+
+    fn set_freeze_lvars(&self, conn: &SimConnector, gauge_communicator: &GaugeCommunicator) {
+        let value = if self.has_control { "0" } else { "1" };
+
+        for var_name in self.freeze_lvars.iter() {
+            gauge_communicator.set(conn, var_name, None, value);
+        }
     }
 
     pub fn take_control(&mut self, conn: &SimConnector, gauge_communicator: &GaugeCommunicator) {
         self.has_control = true;
+        if self.disabled {
+            return;
+        }
         self.do_transfer(conn);
+        self.set_freeze_lvars(conn, gauge_communicator);
         gauge_communicator.stop_interpolation(conn);
         // A32NX enable FBW
         gauge_communicator.set(conn, "L:A32NX_EXTERNAL_OVERRIDE", None, "0");
@@ -27,7 +71,11 @@ impl Control {
 
     pub fn lose_control(&mut self, conn: &SimConnector, gauge_communicator: &GaugeCommunicator) {
         self.has_control = false;
+        if self.disabled {
+            return;
+        }
         self.do_transfer(conn);
+        self.set_freeze_lvars(conn, gauge_communicator);
         // A32NX disable FBW
         gauge_communicator.stop_interpolation(conn);
         gauge_communicator.set(conn, "L:A32NX_EXTERNAL_OVERRIDE", None, "1");
@@ -37,9 +85,45 @@ impl Control {
         self.has_control
     }
 
-    pub fn on_connected(&mut self, conn: &SimConnector) {
-        conn.map_client_event_to_sim_event(1000, "FREEZE_LATITUDE_LONGITUDE_SET");
-        conn.map_client_event_to_sim_event(1001, "FREEZE_ALTITUDE_SET");
-        conn.map_client_event_to_sim_event(1002, "FREEZE_ATTITUDE_SET");
+    pub fn on_connected(&mut self, conn: &SimConnector, transfer_hotkey: &str, freeze: &[String]) {
+        if freeze.is_empty() {
+            self.freeze_events = DEFAULT_FREEZE_EVENTS
+                .iter()
+                .map(|x| x.to_string())
+                .collect();
+            self.freeze_lvars.clear();
+        } else {
+            let (lvars, events): (Vec<String>, Vec<String>) =
+                freeze.iter().cloned().partition(|x| x.starts_with("L:"));
+            self.freeze_lvars = lvars;
+            self.freeze_events = events;
+        }
+
+        for (index, event_name) in self.freeze_events.iter().enumerate() {
+            conn.map_client_event_to_sim_event(FREEZE_EVENT_BASE_ID + index as u32, event_name);
+        }
+
+        if transfer_hotkey.is_empty() {
+            return;
+        }
+
+        // Only fires while the sim has focus, and is not masked so the sim still sees the keys
+        conn.map_client_event_to_sim_event(
+            TRANSFER_HOTKEY_EVENT_ID,
+            "YourControls.TransferControl",
+        );
+        conn.map_input_event_to_client_event(
+            TRANSFER_HOTKEY_GROUP_ID,
+            transfer_hotkey,
+            TRANSFER_HOTKEY_EVENT_ID,
+            0,
+            u32::MAX, // SIMCONNECT_UNUSED, no key up event
+            0,
+            false,
+        );
+        conn.set_input_group_state(
+            TRANSFER_HOTKEY_GROUP_ID,
+            simconnect::SIMCONNECT_STATE_SIMCONNECT_STATE_ON,
+        );
     }
 }
@@ -2,6 +2,7 @@ use super::memwriter::MemWriter;
 use serde::Deserialize;
 use simconnect::SimConnector;
 use std::collections::HashMap;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct LVar {
@@ -57,6 +58,16 @@ pub enum InterpolationType {
     InvertConstant,
 }
 
+// Arrival statistics for interpolation packets, used to tell apart packet loss from a starved interpolation buffer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InterpolationStats {
+    pub received: u32,
+    // Packets that arrived with an older timestamp than one already handed to the gauge
+    pub late: u32,
+    // Smoothed inter-arrival jitter in seconds (RFC 3550 style)
+    pub jitter: f64,
+}
+
 fn format_get(var_name: &str, var_units: Option<&str>) -> String {
     if let Some(unit) = var_units {
         format!(r#"({}, {})"#, var_name, unit.trim())
@@ -68,6 +79,9 @@ fn format_get(var_name: &str, var_units: Option<&str>) -> String {
 pub struct GaugeCommunicator {
     datums: Vec<DatumData>,
     interpolate_datums: HashMap<String, InterpolateMapping>,
+    // Sender time and local arrival time of the last interpolation packet
+    last_interpolation: Option<(f64, Instant)>,
+    interpolation_stats: InterpolationStats,
 }
 
 // SEND/RECEIVE define/client data ids
@@ -83,6 +97,8 @@ impl GaugeCommunicator {
         Self {
             datums: Vec::new(),
             interpolate_datums: HashMap::new(),
+            last_interpolation: None,
+            interpolation_stats: InterpolationStats::default(),
         }
     }
 
@@ -194,12 +210,38 @@ impl GaugeCommunicator {
         );
     }
 
+    fn record_interpolation_arrival(&mut self, time: f64) {
+        let now = Instant::now();
+        let stats = &mut self.interpolation_stats;
+
+        stats.received += 1;
+
+        if let Some((last_time, last_arrival)) = self.last_interpolation {
+            if time <= last_time {
+                stats.late += 1;
+                return;
+            }
+
+            let transit_difference =
+                now.duration_since(last_arrival).as_secs_f64() - (time - last_time);
+            stats.jitter += (transit_difference.abs() - stats.jitter) / 16.0;
+        }
+
+        self.last_interpolation = Some((time, now));
+    }
+
+    pub fn get_interpolation_stats(&self) -> &InterpolationStats {
+        &self.interpolation_stats
+    }
+
     pub fn send_new_interpolation_data(
-        &self,
+        &mut self,
         conn: &SimConnector,
         time: f64,
         data: &[InterpolateData],
     ) {
+        self.record_interpolation_arrival(time);
+
         let mut writer = MemWriter::new(2048, 8).unwrap();
         let mut count = 0;
 
@@ -242,6 +242,10 @@ impl AircraftVars {
         self.current_values.get(var_name)
     }
 
+    pub fn get_units(&self, var_name: &str) -> Option<&str> {
+        self.vars.get(var_name).map(|x| x.var_units.as_str())
+    }
+
     pub fn on_connected(&self, conn: &SimConnector) {
         conn.clear_data_definition(self.define_id);
         for (var_name, var_data) in self.vars.iter() {
@@ -256,7 +260,16 @@ impl AircraftVars {
                         0.0,
                     );
                 }
-                InDataTypes::I64 => {}
+                InDataTypes::I64 => {
+                    conn.add_data_definition(
+                        self.define_id,
+                        var_name,
+                        &var_data.var_units,
+                        simconnect::SIMCONNECT_DATATYPE_SIMCONNECT_DATATYPE_INT64,
+                        var_data.datum_id,
+                        0.0,
+                    );
+                }
                 InDataTypes::F64 => {
                     conn.add_data_definition(
                         self.define_id,
@@ -156,6 +156,57 @@ impl Syncable<f64> for ToggleSwitch {
     }
 }
 
+// Controls that stay where they were put, like the parking brake. Always set straight to the wanted
+// state, as toggling from a different starting state would leave them the wrong way around.
+pub struct LatchedSwitch {
+    event_id: u32,
+    on_param: u32,
+    off_param: u32,
+    current: bool,
+}
+
+impl LatchedSwitch {
+    pub fn new(event_id: u32, on_param: u32, off_param: u32) -> Self {
+        Self {
+            event_id,
+            on_param,
+            off_param,
+            current: false,
+        }
+    }
+
+    fn set(&self, new: bool, conn: &simconnect::SimConnector) {
+        let param = if new { self.on_param } else { self.off_param };
+        conn.transmit_client_event(1, self.event_id, param, GROUP_ID, 0);
+    }
+}
+
+impl Syncable<bool> for LatchedSwitch {
+    fn set_current(&mut self, current: bool) {
+        self.current = current;
+    }
+
+    fn set_new(&mut self, new: bool, conn: &simconnect::SimConnector, _: &mut LVarSyncer) {
+        if self.current != new {
+            self.set(new, conn);
+        }
+    }
+}
+
+impl Syncable<f64> for LatchedSwitch {
+    fn set_current(&mut self, current: f64) {
+        self.current = !float_eq(&current, &0.0);
+    }
+
+    fn set_new(&mut self, new: f64, conn: &simconnect::SimConnector, _: &mut LVarSyncer) {
+        let new = !float_eq(&new, &0.0);
+
+        if self.current != new {
+            self.set(new, conn);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct NumSet<T> {
     event_id: u32,
@@ -477,6 +528,67 @@ impl Syncable<i32> for NumDigitSet {
         }
     }
 }
+struct ModeButton {
+    event_id: u32,
+    // Pressed through the calculator instead when set
+    event_name: Option<String>,
+}
+
+// Momentary buttons that each flip one mode of a bitfield, like HDG, NAV and APR. Replaying the presses
+// could leave a different set of modes on, so the buttons for whichever modes differ get pressed instead.
+pub struct AutopilotModes {
+    // Index in here is the mode's bit
+    buttons: Vec<ModeButton>,
+    current: u32,
+}
+
+impl AutopilotModes {
+    pub fn new() -> Self {
+        Self {
+            buttons: Vec::new(),
+            current: 0,
+        }
+    }
+
+    pub fn add_mode(&mut self, event_id: u32, calculator_event_name: Option<String>) {
+        self.buttons.push(ModeButton {
+            event_id,
+            event_name: calculator_event_name.map(|event_name| format!("K:{}", event_name)),
+        });
+    }
+
+    fn press(&self, modes: u32, conn: &simconnect::SimConnector, lvar_transfer: &mut LVarSyncer) {
+        for (index, button) in self.buttons.iter().enumerate() {
+            if modes & (1 << index) == 0 {
+                continue;
+            }
+
+            match button.event_name.as_ref() {
+                Some(event_name) => lvar_transfer.set_unchecked(conn, event_name, None, ""),
+                None => conn.transmit_client_event(1, button.event_id, 0, GROUP_ID, 0),
+            };
+        }
+    }
+}
+
+impl Syncable<f64> for AutopilotModes {
+    fn set_current(&mut self, current: f64) {
+        self.current = current.round() as u32;
+    }
+
+    fn set_new(
+        &mut self,
+        new: f64,
+        conn: &simconnect::SimConnector,
+        lvar_transfer: &mut LVarSyncer,
+    ) {
+        let new = new.round() as u32;
+        // Engaging a mode often drops another, so switch off the unwanted ones first
+        self.press(self.current & !new, conn, lvar_transfer);
+        self.press(new & !self.current, conn, lvar_transfer);
+    }
+}
+
 pub struct CustomCalculator {
     set_string: String,
     current: f64,
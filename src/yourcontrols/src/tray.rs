@@ -0,0 +1,362 @@
+use crossbeam_channel::Sender;
+use log::warn;
+use std::{
+    cell::RefCell,
+    ffi::OsStr,
+    mem::{size_of, zeroed},
+    os::windows::ffi::OsStrExt,
+    ptr::{null, null_mut},
+    sync::{
+        atomic::{AtomicIsize, Ordering::SeqCst},
+        Arc,
+    },
+    thread,
+};
+
+use crate::app::AppMessage;
+
+type Hwnd = isize;
+
+const WM_DESTROY: u32 = 0x0002;
+const WM_LBUTTONUP: u32 = 0x0202;
+const WM_RBUTTONUP: u32 = 0x0205;
+const WM_APP: u32 = 0x8000;
+// Sent by the shell for clicks on the icon
+const WM_TRAY: u32 = WM_APP + 1;
+
+const NIM_ADD: u32 = 0;
+const NIM_DELETE: u32 = 2;
+const NIF_MESSAGE: u32 = 1;
+const NIF_ICON: u32 = 2;
+const NIF_TIP: u32 = 4;
+
+const MF_STRING: u32 = 0;
+const MF_SEPARATOR: u32 = 0x0800;
+const TPM_RIGHTBUTTON: u32 = 0x0002;
+const TPM_RETURNCMD: u32 = 0x0100;
+
+const HWND_MESSAGE: Hwnd = -3;
+const IDI_APPLICATION: usize = 32512;
+// Given to the exe by winres
+const APP_ICON_ID: usize = 1;
+const SW_HIDE: i32 = 0;
+const SW_RESTORE: i32 = 9;
+
+const MENU_SHOW_HIDE: usize = 1;
+const MENU_TRANSFER_CONTROL: usize = 2;
+const MENU_DISCONNECT: usize = 3;
+
+#[repr(C)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[repr(C)]
+struct Msg {
+    hwnd: Hwnd,
+    message: u32,
+    w_param: usize,
+    l_param: isize,
+    time: u32,
+    pt: Point,
+}
+
+#[repr(C)]
+struct WndClass {
+    style: u32,
+    wnd_proc: extern "system" fn(Hwnd, u32, usize, isize) -> isize,
+    cls_extra: i32,
+    wnd_extra: i32,
+    instance: isize,
+    icon: isize,
+    cursor: isize,
+    background: isize,
+    menu_name: *const u16,
+    class_name: *const u16,
+}
+
+#[repr(C)]
+struct NotifyIconData {
+    size: u32,
+    hwnd: Hwnd,
+    id: u32,
+    flags: u32,
+    callback_message: u32,
+    icon: isize,
+    tip: [u16; 128],
+    state: u32,
+    state_mask: u32,
+    info: [u16; 256],
+    timeout_or_version: u32,
+    info_title: [u16; 64],
+    info_flags: u32,
+    guid_item: [u8; 16],
+    balloon_icon: isize,
+}
+
+#[link(name = "user32")]
+extern "system" {
+    fn RegisterClassW(class: *const WndClass) -> u16;
+    fn CreateWindowExW(
+        ex_style: u32,
+        class_name: *const u16,
+        window_name: *const u16,
+        style: u32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        parent: Hwnd,
+        menu: isize,
+        instance: isize,
+        param: *mut u8,
+    ) -> Hwnd;
+    fn DefWindowProcW(hwnd: Hwnd, message: u32, w_param: usize, l_param: isize) -> isize;
+    fn GetMessageW(msg: *mut Msg, hwnd: Hwnd, filter_min: u32, filter_max: u32) -> i32;
+    fn TranslateMessage(msg: *const Msg) -> i32;
+    fn DispatchMessageW(msg: *const Msg) -> isize;
+    fn PostQuitMessage(exit_code: i32);
+    fn LoadIconW(instance: isize, name: usize) -> isize;
+    fn CreatePopupMenu() -> isize;
+    fn AppendMenuW(menu: isize, flags: u32, id: usize, text: *const u16) -> i32;
+    fn TrackPopupMenu(
+        menu: isize,
+        flags: u32,
+        x: i32,
+        y: i32,
+        reserved: i32,
+        hwnd: Hwnd,
+        rect: *const u8,
+    ) -> i32;
+    fn DestroyMenu(menu: isize) -> i32;
+    fn GetCursorPos(point: *mut Point) -> i32;
+    fn SetForegroundWindow(hwnd: Hwnd) -> i32;
+    fn FindWindowW(class_name: *const u16, window_name: *const u16) -> Hwnd;
+    fn ShowWindow(hwnd: Hwnd, cmd: i32) -> i32;
+    fn IsWindowVisible(hwnd: Hwnd) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "system" {
+    fn Shell_NotifyIconW(message: u32, data: *mut NotifyIconData) -> i32;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetModuleHandleW(module_name: *const u16) -> isize;
+}
+
+thread_local! {
+    // Where menu picks go, only set on the tray's own thread
+    static SENDER: RefCell<Option<Sender<AppMessage>>> = RefCell::new(None);
+}
+
+fn to_wide(text: &str) -> Vec<u16> {
+    OsStr::new(text).encode_wide().chain(Some(0)).collect()
+}
+
+fn send(message: AppMessage) {
+    SENDER.with(|sender| {
+        if let Some(sender) = sender.borrow().as_ref() {
+            sender.try_send(message).ok();
+        }
+    });
+}
+
+fn show_menu(hwnd: Hwnd) {
+    unsafe {
+        let menu = CreatePopupMenu();
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            MENU_SHOW_HIDE,
+            to_wide("Show/Hide").as_ptr(),
+        );
+        AppendMenuW(menu, MF_SEPARATOR, 0, null());
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            MENU_TRANSFER_CONTROL,
+            to_wide("Transfer Control").as_ptr(),
+        );
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            MENU_DISCONNECT,
+            to_wide("Disconnect").as_ptr(),
+        );
+
+        let mut point = Point { x: 0, y: 0 };
+        GetCursorPos(&mut point);
+        // Otherwise the menu doesn't close when clicking elsewhere
+        SetForegroundWindow(hwnd);
+
+        let picked = TrackPopupMenu(
+            menu,
+            TPM_RIGHTBUTTON | TPM_RETURNCMD,
+            point.x,
+            point.y,
+            0,
+            hwnd,
+            null(),
+        );
+        DestroyMenu(menu);
+
+        match picked as usize {
+            MENU_SHOW_HIDE => send(AppMessage::ToggleWindow),
+            MENU_TRANSFER_CONTROL => send(AppMessage::TransferControlToNext),
+            MENU_DISCONNECT => send(AppMessage::RequestDisconnect),
+            _ => {}
+        }
+    }
+}
+
+extern "system" fn window_proc(hwnd: Hwnd, message: u32, w_param: usize, l_param: isize) -> isize {
+    match message {
+        WM_TRAY => {
+            match l_param as u32 & 0xFFFF {
+                WM_LBUTTONUP => send(AppMessage::ToggleWindow),
+                WM_RBUTTONUP => show_menu(hwnd),
+                _ => {}
+            }
+            0
+        }
+        WM_DESTROY => {
+            unsafe { PostQuitMessage(0) };
+            0
+        }
+        _ => unsafe { DefWindowProcW(hwnd, message, w_param, l_param) },
+    }
+}
+
+fn get_icon_data(hwnd: Hwnd) -> NotifyIconData {
+    let mut data: NotifyIconData = unsafe { zeroed() };
+    data.size = size_of::<NotifyIconData>() as u32;
+    data.hwnd = hwnd;
+    data.id = 1;
+    data
+}
+
+// Icon in the notification area to get at the window and the most used actions while it's hidden
+pub struct Tray {
+    hwnd: Arc<AtomicIsize>,
+}
+
+impl Tray {
+    pub fn start(tooltip: String, sender: Sender<AppMessage>) -> Self {
+        let hwnd = Arc::new(AtomicIsize::new(0));
+        let hwnd_clone = hwnd.clone();
+
+        thread::spawn(move || unsafe {
+            SENDER.with(|x| *x.borrow_mut() = Some(sender));
+
+            let instance = GetModuleHandleW(null());
+            let class_name = to_wide("YourControlsTray");
+
+            let class = WndClass {
+                style: 0,
+                wnd_proc: window_proc,
+                cls_extra: 0,
+                wnd_extra: 0,
+                instance,
+                icon: 0,
+                cursor: 0,
+                background: 0,
+                menu_name: null(),
+                class_name: class_name.as_ptr(),
+            };
+            RegisterClassW(&class);
+
+            // Never shown, only receives the icon's messages
+            let window = CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                0,
+                instance,
+                null_mut(),
+            );
+
+            if window == 0 {
+                warn!("[UI] Could not create the tray icon's window");
+                return;
+            }
+
+            let mut icon = LoadIconW(instance, APP_ICON_ID);
+            if icon == 0 {
+                icon = LoadIconW(0, IDI_APPLICATION);
+            }
+
+            let mut data = get_icon_data(window);
+            data.flags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+            data.callback_message = WM_TRAY;
+            data.icon = icon;
+            for (slot, character) in data.tip.iter_mut().take(127).zip(tooltip.encode_utf16()) {
+                *slot = character;
+            }
+
+            if Shell_NotifyIconW(NIM_ADD, &mut data) == 0 {
+                warn!("[UI] Could not add the tray icon");
+                return;
+            }
+
+            hwnd_clone.store(window, SeqCst);
+
+            let mut msg: Msg = zeroed();
+            while GetMessageW(&mut msg, 0, 0, 0) > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        });
+
+        Self { hwnd }
+    }
+}
+
+impl Drop for Tray {
+    // The icon stays behind until hovered over otherwise
+    fn drop(&mut self) {
+        let hwnd = self.hwnd.swap(0, SeqCst);
+        if hwnd != 0 {
+            let mut data = get_icon_data(hwnd);
+            unsafe { Shell_NotifyIconW(NIM_DELETE, &mut data) };
+        }
+    }
+}
+
+// Brings back the window with this title if it was hidden
+pub fn show_window(title: &str) {
+    unsafe {
+        let hwnd = FindWindowW(null(), to_wide(title).as_ptr());
+        if hwnd == 0 {
+            return;
+        }
+
+        ShowWindow(hwnd, SW_RESTORE);
+        SetForegroundWindow(hwnd);
+    }
+}
+
+// Hides the window with this title, or brings it back if it was hidden
+pub fn toggle_window(title: &str) {
+    unsafe {
+        let hwnd = FindWindowW(null(), to_wide(title).as_ptr());
+        if hwnd == 0 {
+            return;
+        }
+
+        if IsWindowVisible(hwnd) != 0 {
+            ShowWindow(hwnd, SW_HIDE);
+        } else {
+            ShowWindow(hwnd, SW_RESTORE);
+            SetForegroundWindow(hwnd);
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
-use std::{net::IpAddr, ops::Add, ops::Sub};
+use std::{
+    net::IpAddr,
+    ops::Add,
+    ops::Sub,
+    os::raw::c_char,
+    time::{Duration, Instant},
+};
 use yourcontrols_types::Error;
 
 pub fn get_hostname_ip(hostname: &str, isipv6: bool) -> Result<IpAddr, Error> {
@@ -12,6 +18,74 @@ pub fn get_hostname_ip(hostname: &str, isipv6: bool) -> Result<IpAddr, Error> {
     }
 }
 
+// Splits the zone off a link-local IPv6 address like fe80::1%12, which IpAddr can't parse
+pub fn parse_ip_with_scope(ip: &str) -> Result<(IpAddr, u32), Error> {
+    let invalid = || Error::InvalidIp(ip.to_string());
+
+    let (address, scope_id) = match ip.find('%') {
+        Some(index) => (
+            &ip[..index],
+            ip[index + 1..].parse().map_err(|_| invalid())?,
+        ),
+        None => (ip, 0),
+    };
+
+    match address.parse() {
+        Ok(IpAddr::V4(_)) if scope_id != 0 => Err(invalid()),
+        Ok(address) => Ok((address, scope_id)),
+        Err(_) => Err(invalid()),
+    }
+}
+
+// For the direct connect form, so a bad entry is caught before connecting
+pub fn validate_address(address: &str) -> Result<(), Error> {
+    if parse_ip_with_scope(address).is_ok() {
+        return Ok(());
+    }
+
+    let labels: Vec<&str> = address.split('.').collect();
+
+    let valid_labels = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-')
+    });
+    // All numbers is a mistyped IP rather than a hostname
+    let all_numeric = labels
+        .iter()
+        .all(|label| label.bytes().all(|c| c.is_ascii_digit()));
+
+    if address.len() > 253 || !valid_labels || all_numeric {
+        return Err(Error::InvalidHostname(address.to_string()));
+    }
+
+    Ok(())
+}
+
+pub fn validate_port(port: &str) -> Result<u16, Error> {
+    match port.trim().parse() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(Error::InvalidPort(port.to_string())),
+    }
+}
+
+// config.json for instance "2" is config-2.json
+pub fn instance_file_name(file_name: &str, instance: Option<&str>) -> String {
+    let instance = match instance {
+        Some(instance) => instance,
+        None => return file_name.to_string(),
+    };
+
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}-{}.{}", stem, instance, extension),
+        None => format!("{}-{}", file_name, instance),
+    }
+}
+
 pub fn wrap_diff(from: f64, to: f64, max: f64) -> f64 {
     let threshold = max * 0.5;
     if (from - to).abs() > threshold {
@@ -113,6 +187,150 @@ pub fn float_eq(lhs: &f64, rhs: &f64) -> bool {
     (rhs - lhs).abs() < f64::EPSILON
 }
 
+pub fn get_bit(value: i64, bit: u8) -> bool {
+    (value >> bit) & 1 == 1
+}
+
+pub fn set_bit(value: i64, bit: u8, on: bool) -> i64 {
+    if on {
+        value | (1 << bit)
+    } else {
+        value & !(1 << bit)
+    }
+}
+
+// Stable across runs and machines so every peer sees the same color for a given name
+pub fn get_name_color(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(5381u32, |hash, byte| hash.wrapping_mul(33) ^ byte as u32);
+
+    format!("hsl({}, 65%, 50%)", hash % 360)
+}
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+// Moves a coordinate by some meters behind and to the right of the given true heading, all in degrees
+pub fn offset_coordinates(
+    latitude: f64,
+    longitude: f64,
+    heading: f64,
+    behind: f64,
+    right: f64,
+) -> (f64, f64) {
+    let heading = heading.to_radians();
+
+    let north = -behind * heading.cos() - right * heading.sin();
+    let east = -behind * heading.sin() + right * heading.cos();
+
+    (
+        latitude + (north / EARTH_RADIUS_METERS).to_degrees(),
+        longitude + (east / (EARTH_RADIUS_METERS * latitude.to_radians().cos())).to_degrees(),
+    )
+}
+
+// Matches the codes handed out by the rendezvous server
+const SESSION_ID_LENGTH: usize = 8;
+
+// SimConnect strings are fixed size and null terminated
+pub fn read_c_string(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars
+        .iter()
+        .take_while(|x| **x != 0)
+        .map(|x| *x as u8)
+        .collect();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// Only changes state once the new one has held for several checks in a row, so one slow loop doesn't flag anything
+pub struct OverloadDetector {
+    overloaded: bool,
+    streak: u32,
+    needed: u32,
+}
+
+impl OverloadDetector {
+    pub fn new(needed: u32) -> Self {
+        Self {
+            overloaded: false,
+            streak: 0,
+            needed,
+        }
+    }
+
+    // The new state whenever it changes
+    pub fn update(&mut self, overloaded: bool) -> Option<bool> {
+        if overloaded == self.overloaded {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < self.needed {
+            return None;
+        }
+
+        self.overloaded = overloaded;
+        self.streak = 0;
+
+        Some(overloaded)
+    }
+
+    pub fn is_overloaded(&self) -> bool {
+        self.overloaded
+    }
+
+    pub fn reset(&mut self) {
+        self.overloaded = false;
+        self.streak = 0;
+    }
+}
+
+// Counts how often something happens, as a per second rate over each window
+pub struct RateCounter {
+    window: Duration,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateCounter {
+    pub fn new(window: Duration, now: Instant) -> Self {
+        Self {
+            window,
+            window_start: now,
+            count: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.count += 1;
+    }
+
+    // The rate once a full window has passed, then starts counting the next one
+    pub fn take_rate(&mut self, now: Instant) -> Option<f64> {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < self.window {
+            return None;
+        }
+
+        let rate = self.count as f64 / elapsed.as_secs_f64();
+        self.window_start = now;
+        self.count = 0;
+
+        Some(rate)
+    }
+
+    pub fn reset(&mut self, now: Instant) {
+        self.window_start = now;
+        self.count = 0;
+    }
+}
+
+pub fn is_valid_session_id(session_id: &str) -> bool {
+    session_id.len() == SESSION_ID_LENGTH && session_id.bytes().all(|c| c.is_ascii_uppercase())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -133,4 +351,138 @@ mod test {
         assert!(float_eq(&wrap_diff(350.0, 10.0, 360.0), &20.0));
         assert!(float_eq(&wrap_diff(10.0, 350.0, 360.0), &-20.0));
     }
+
+    #[test]
+    fn test_bits() {
+        let packed = set_bit(set_bit(0, 0, true), 3, true);
+        assert_eq!(packed, 0b1001);
+        assert!(get_bit(packed, 3));
+        assert!(!get_bit(packed, 1));
+        // Clearing one bit leaves the others alone
+        assert_eq!(set_bit(packed, 0, false), 0b1000);
+        assert!(get_bit(set_bit(0, 63, true), 63));
+    }
+
+    #[test]
+    fn test_offset_coordinates() {
+        // Heading north, behind is south and right is east
+        let (latitude, longitude) = offset_coordinates(0.0, 0.0, 0.0, 1000.0, 1000.0);
+        assert!(latitude < 0.0 && longitude > 0.0);
+        assert!((latitude + 0.008993).abs() < 1e-5);
+        assert!((longitude - 0.008993).abs() < 1e-5);
+        // Heading east, behind is west and right is south
+        let (latitude, longitude) = offset_coordinates(0.0, 0.0, 90.0, 1000.0, 1000.0);
+        assert!(latitude < 0.0 && longitude < 0.0);
+        // No offset stays put
+        let (latitude, longitude) = offset_coordinates(47.5, -122.3, 123.0, 0.0, 0.0);
+        assert!(float_eq(&latitude, &47.5) && float_eq(&longitude, &-122.3));
+    }
+
+    #[test]
+    fn test_name_color() {
+        assert_eq!(get_name_color("Pilot"), get_name_color("Pilot"));
+        assert_ne!(get_name_color("Pilot"), get_name_color("Copilot"));
+    }
+
+    #[test]
+    fn test_read_c_string() {
+        let mut chars = [0 as c_char; 16];
+        for (i, x) in b"C172.PLN".iter().enumerate() {
+            chars[i] = *x as c_char;
+        }
+        assert_eq!(read_c_string(&chars), "C172.PLN");
+        assert_eq!(read_c_string(&[0; 4]), "");
+    }
+
+    #[test]
+    fn test_instance_file_name() {
+        assert_eq!(instance_file_name("config.json", None), "config.json");
+        assert_eq!(
+            instance_file_name("config.json", Some("2")),
+            "config-2.json"
+        );
+        assert_eq!(instance_file_name("log", Some("joiner")), "log-joiner");
+    }
+
+    #[test]
+    fn test_validate_address() {
+        assert!(validate_address("192.168.1.20").is_ok());
+        assert!(validate_address("fe80::1%12").is_ok());
+        assert!(validate_address("my-server.example.com").is_ok());
+        assert!(validate_address("localhost").is_ok());
+
+        assert!(validate_address("").is_err());
+        assert!(validate_address("300.1.1.1").is_err());
+        assert!(validate_address("192.168.1").is_err());
+        assert!(validate_address("bad host").is_err());
+        assert!(validate_address("-server.com").is_err());
+
+        assert_eq!(validate_port("25071").ok(), Some(25071));
+        assert!(validate_port("0").is_err());
+        assert!(validate_port("99999").is_err());
+        assert!(validate_port("port").is_err());
+    }
+
+    #[test]
+    fn test_overload_detector() {
+        let mut detector = OverloadDetector::new(3);
+
+        assert_eq!(detector.update(true), None);
+        assert_eq!(detector.update(true), None);
+        // A good loop in between starts over
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(true), None);
+        assert_eq!(detector.update(true), None);
+        assert_eq!(detector.update(true), Some(true));
+        assert!(detector.is_overloaded());
+
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(false), None);
+        assert_eq!(detector.update(false), Some(false));
+    }
+
+    #[test]
+    fn test_rate_counter() {
+        let start = Instant::now();
+        let mut counter = RateCounter::new(Duration::from_secs(2), start);
+
+        for _ in 0..44 {
+            counter.tick();
+        }
+        assert_eq!(counter.take_rate(start + Duration::from_secs(1)), None);
+        assert!(float_eq(
+            &counter.take_rate(start + Duration::from_secs(2)).unwrap(),
+            &22.0
+        ));
+        // Next window starts empty
+        assert!(float_eq(
+            &counter.take_rate(start + Duration::from_secs(4)).unwrap(),
+            &0.0
+        ));
+    }
+
+    #[test]
+    fn test_session_id_validation() {
+        assert!(is_valid_session_id("ABCDEFGH"));
+        assert!(!is_valid_session_id("ABCDEFG"));
+        assert!(!is_valid_session_id("ABCDEFGHI"));
+        assert!(!is_valid_session_id("ABCD3FGH"));
+        assert!(!is_valid_session_id("abcdefgh"));
+        assert!(!is_valid_session_id(""));
+    }
+
+    #[test]
+    fn test_parse_ip_with_scope() {
+        let (ip, scope_id) = parse_ip_with_scope("fe80::1%12").unwrap();
+        assert_eq!(ip, "fe80::1".parse::<IpAddr>().unwrap());
+        assert_eq!(scope_id, 12);
+
+        let (ip, scope_id) = parse_ip_with_scope("192.168.1.2").unwrap();
+        assert_eq!(ip, "192.168.1.2".parse::<IpAddr>().unwrap());
+        assert_eq!(scope_id, 0);
+
+        assert!(parse_ip_with_scope("192.168.1.2%3").is_err());
+        assert!(parse_ip_with_scope("fe80::1%").is_err());
+        assert!(parse_ip_with_scope("not an ip").is_err());
+    }
 }
@@ -0,0 +1,169 @@
+use indexmap::IndexMap;
+use serde_yaml::Value;
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    fs::File,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use crate::definitions::Definitions;
+
+const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn AttachConsole(process_id: u32) -> i32;
+}
+
+// Release builds don't get a console of their own, so the report is written to the one that started us
+pub fn attach_parent_console() {
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+// Result of checking a definition file without the sim running
+pub struct ValidationReport {
+    pub errors: Vec<String>,
+    pub avars: usize,
+    pub lvars: usize,
+    pub events: usize,
+}
+
+impl ValidationReport {
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn summary(&self, path: &Path) -> String {
+        let mut report = String::new();
+
+        if self.passed() {
+            writeln!(
+                report,
+                "PASS {}: {} aircraft vars, {} local vars, {} events",
+                path.display(),
+                self.avars,
+                self.lvars,
+                self.events
+            )
+            .ok();
+        } else {
+            writeln!(report, "FAIL {}", path.display()).ok();
+            for error in self.errors.iter() {
+                writeln!(report, "  {}", error).ok();
+            }
+        }
+
+        report
+    }
+}
+
+// Loads the file the same way the app does. Includes are relative to the working directory like in the app.
+pub fn validate_definitions(path: &Path) -> ValidationReport {
+    let mut report = ValidationReport {
+        errors: Vec::new(),
+        avars: 0,
+        lvars: 0,
+        events: 0,
+    };
+
+    // Loading skips include files that can't be opened, so look for them first
+    find_missing_includes(path, &mut HashSet::new(), &mut report.errors);
+
+    let mut definitions = Definitions::new();
+    // Some malformed entries panic while loading instead of returning an error
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        definitions.load_config(path.to_string_lossy().to_string())
+    }));
+
+    match result {
+        Ok(Ok(_)) => {
+            report.avars = definitions.get_number_avars();
+            report.lvars = definitions.get_number_lvars();
+            report.events = definitions.get_number_events();
+        }
+        Ok(Err(e)) => report.errors.push(e.to_string()),
+        Err(_) => report
+            .errors
+            .push("Malformed entry, see the panic message above".to_string()),
+    }
+
+    report
+}
+
+fn find_missing_includes(path: &Path, visited: &mut HashSet<String>, errors: &mut Vec<String>) {
+    if !visited.insert(path.to_string_lossy().to_string()) {
+        return;
+    }
+
+    // Errors opening or parsing are reported by load_config
+    let yaml: IndexMap<String, Vec<Value>> = match File::open(path)
+        .ok()
+        .and_then(|file| serde_yaml::from_reader(file).ok())
+    {
+        Some(yaml) => yaml,
+        None => return,
+    };
+
+    for include in yaml.get("include").into_iter().flatten() {
+        let include_path = match include.as_str() {
+            Some(include_path) => Path::new(include_path),
+            None => {
+                errors.push(format!(
+                    "{}: include entry is not a file name",
+                    path.display()
+                ));
+                continue;
+            }
+        };
+
+        if include_path.exists() {
+            find_missing_includes(include_path, visited, errors);
+        } else {
+            errors.push(format!(
+                "{}: included file {} does not exist",
+                path.display(),
+                include_path.display()
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_fails() {
+        let path = Path::new("definitions/aircraft/does not exist.yaml");
+        let report = validate_definitions(path);
+
+        assert!(!report.passed());
+        assert!(report.summary(path).starts_with("FAIL"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let path = Path::new("test.yaml");
+        let mut report = ValidationReport {
+            errors: Vec::new(),
+            avars: 2,
+            lvars: 1,
+            events: 3,
+        };
+
+        assert_eq!(
+            report.summary(path),
+            "PASS test.yaml: 2 aircraft vars, 1 local vars, 3 events\n"
+        );
+
+        report.errors.push("Missing var".to_string());
+        report.errors.push("Unknown event".to_string());
+
+        assert_eq!(
+            report.summary(path),
+            "FAIL test.yaml\n  Missing var\n  Unknown event\n"
+        );
+    }
+}
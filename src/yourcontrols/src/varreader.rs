@@ -49,17 +49,8 @@ impl VarReader {
 
         for _ in 0..item_count {
             unsafe {
-                let mut buf: Vec<u8> = vec![];
-
-                // Read all relevant bytes into buffer
-                for i in 0..3 {
-                    buf.extend_from_slice(&current_pos.offset(i).read().to_le_bytes())
-                }
-
-                let mut cursor = Cursor::new(buf);
-
                 // Read data id
-                let datum_id = cursor.read_u32::<LittleEndian>()?;
+                let datum_id = current_pos.read_unaligned();
                 // Get the matching data mapped to the datum id
                 let data = match self.data_map.get(datum_id as usize) {
                     Some(d) => d,
@@ -71,28 +62,26 @@ impl VarReader {
                     }
                 };
 
-                let result_data;
-                let data_size;
+                // Only read as many bytes as the type occupies so the last entry doesn't read past the buffer
+                let data_size = match data.data_type {
+                    InDataTypes::Bool | InDataTypes::I32 => 1,
+                    InDataTypes::I64 | InDataTypes::F64 => 2,
+                };
 
-                match data.data_type {
+                let mut buf: Vec<u8> = vec![];
+                for i in 1..=data_size {
+                    buf.extend_from_slice(&current_pos.offset(i).read_unaligned().to_le_bytes())
+                }
+
+                let mut cursor = Cursor::new(buf);
+
+                let result_data = match data.data_type {
                     InDataTypes::Bool => {
-                        result_data = VarReaderTypes::Bool(std::mem::transmute_copy(
-                            &cursor.read_i32::<LittleEndian>()?,
-                        ));
-                        data_size = 1;
-                    }
-                    InDataTypes::I32 => {
-                        result_data = VarReaderTypes::I32(cursor.read_i32::<LittleEndian>()?);
-                        data_size = 1;
-                    }
-                    InDataTypes::I64 => {
-                        result_data = VarReaderTypes::I64(cursor.read_i64::<LittleEndian>()?);
-                        data_size = 2;
-                    }
-                    InDataTypes::F64 => {
-                        result_data = VarReaderTypes::F64(cursor.read_f64::<LittleEndian>()?);
-                        data_size = 2;
+                        VarReaderTypes::Bool(cursor.read_i32::<LittleEndian>()? != 0)
                     }
+                    InDataTypes::I32 => VarReaderTypes::I32(cursor.read_i32::<LittleEndian>()?),
+                    InDataTypes::I64 => VarReaderTypes::I64(cursor.read_i64::<LittleEndian>()?),
+                    InDataTypes::F64 => VarReaderTypes::F64(cursor.read_f64::<LittleEndian>()?),
                 };
 
                 return_data.insert(data.data_name.clone(), result_data);
@@ -112,9 +101,10 @@ impl VarReader {
 
             buf.extend(datum_id.to_le_bytes().iter());
 
+            // Widths must match what read_from_bytes and the SimConnect data definition expect
             match data {
-                VarReaderTypes::Bool(n) => buf.extend((*n as i64).to_le_bytes().iter()),
-                VarReaderTypes::I32(n) => buf.extend((*n as i64).to_le_bytes().iter()),
+                VarReaderTypes::Bool(n) => buf.extend((*n as i32).to_le_bytes().iter()),
+                VarReaderTypes::I32(n) => buf.extend(n.to_le_bytes().iter()),
                 VarReaderTypes::I64(n) => buf.extend(n.to_le_bytes().iter()),
                 VarReaderTypes::F64(n) => buf.extend(n.to_le_bytes().iter()),
             };
@@ -214,4 +204,39 @@ mod tests {
         assert_eq!(values["PLANE LATITUDE"], VarReaderTypes::F64(42.0));
         assert_eq!(values["PLANE LONGITUDE"], VarReaderTypes::F64(128.0));
     }
+
+    #[test]
+    fn test_signed_and_boundary_values() {
+        let mut definitions = VarReader::new();
+
+        definitions.add_definition("VERTICAL SPEED", InDataTypes::F64);
+        definitions.add_definition("PLANE LONGITUDE", InDataTypes::F64);
+        definitions.add_definition("BIG", InDataTypes::F64);
+        definitions.add_definition("SMALL", InDataTypes::F64);
+        definitions.add_definition("MIN I32", InDataTypes::I32);
+        definitions.add_definition("MAX I32", InDataTypes::I32);
+        definitions.add_definition("MIN I64", InDataTypes::I64);
+        definitions.add_definition("SWITCH", InDataTypes::Bool);
+
+        let mut new_data = HashMap::new();
+        new_data.insert("VERTICAL SPEED".to_string(), VarReaderTypes::F64(-6000.5));
+        new_data.insert(
+            "PLANE LONGITUDE".to_string(),
+            VarReaderTypes::F64(-179.999999),
+        );
+        new_data.insert("BIG".to_string(), VarReaderTypes::F64(f64::MAX));
+        new_data.insert("SMALL".to_string(), VarReaderTypes::F64(f64::MIN));
+        new_data.insert("MIN I32".to_string(), VarReaderTypes::I32(i32::MIN));
+        new_data.insert("MAX I32".to_string(), VarReaderTypes::I32(i32::MAX));
+        new_data.insert("MIN I64".to_string(), VarReaderTypes::I64(i64::MIN));
+        new_data.insert("SWITCH".to_string(), VarReaderTypes::Bool(true));
+
+        let data = definitions.write_to_data(&new_data);
+
+        let values = definitions
+            .read_from_bytes(new_data.len() as u32, data.as_ptr() as *const u32)
+            .unwrap();
+
+        assert_eq!(values, new_data);
+    }
 }
@@ -0,0 +1,153 @@
+// Messages shown in the UI, keyed by the ids the app sends. English is used for anything missing in a language.
+var strings = {
+    en: {
+        attempt: "Attempting connection...",
+        resolving_host: "Resolving host address...",
+        contacting_rendezvous: "Contacting the Cloud Server...",
+        punching_through: "Reaching the host...",
+        handshaking: "Handshaking with the host...",
+        loading_definitions: "Connected. Loading aircraft definitions...",
+        syncing_initial_state: "Syncing the aircraft with the host...",
+        connected: "Connected to server.",
+        name_assigned: "Connected to server as {0}.",
+        server_fail: "Server failed to start. Reason: {0}",
+        client_fail: "Client disconnected. Reason: {0}",
+        server: "Server started!",
+        host: "You are now hosting!",
+        diagnostics: "Diagnostics copied to clipboard.",
+        config_exported: "Shareable config copied to clipboard. Your name and personal preferences are left out.",
+        config_import_prompt: "Paste the shared config:",
+        config_imported: "Shared config imported and saved.",
+        config_import_failed: "The shared config could not be imported: {0}",
+        sim_waiting: "Sim closed. Waiting for it to start again...",
+        sim_reconnected: "Sim reconnected.",
+        aircraft_switched: "A different aircraft ({0}) was loaded. Syncing is paused until you load the aircraft the session started with, or reconnect with matching definitions.",
+        aircraft_restored: "Back in the session's aircraft, syncing again.",
+        flight_plan_loaded: "Loaded the host's flight plan {0}.",
+        session_time: "Connected for {0}",
+        control_unassigned: "{0} left while in control. The aircraft stays frozen until you press Take Control.",
+        connection_path: "Connected via {0}",
+        connection_path_direct: "Direct",
+        connection_path_direct_upnp: "Direct (port forwarded with UPnP)",
+        connection_path_cloud_server: "Cloud Server (peer to peer)",
+        connection_path_relayed: "Cloud Server (relayed)",
+        sync_rate: "Sync rate: requested {0}Hz, achieving {1}Hz",
+        local_name: "You are {0}",
+        local_address: "Listening on {0}",
+        sim_not_running: "Could not connect to SimConnect! Is the sim running?",
+        definitions_load_failed: "Error loading definition files. Check the log for more information.",
+        summary: "You're connected: {0}.",
+        summary_you_control: "you have control",
+        summary_control: "{0} has control",
+        summary_no_control: "nobody has control",
+        summary_aircraft: "flying {0}",
+        summary_path: "via {0}",
+        summary_players: "In the session: {0}.",
+        state_pushed: "Sent your full aircraft state to everyone.",
+        config_not_saved: "Your settings could not be saved and will be lost when YourControls closes ({0}). Make sure the file and its folder aren't read-only.",
+        definitions_missing: "The aircraft definitions folder is missing or unreadable, it should be at {0}. Reinstall YourControls to restore it.",
+        select_aircraft: "Select an aircraft config first!",
+        config_suggestions: "No config selected for {0}. Closest matches:",
+        config_no_suggestions: "No config matches {0}. Pick the closest aircraft from the list, some controls may not sync.",
+        no_last_session: "There is no previous session to rejoin.",
+        no_reconnect_session: "There is no dropped session to reconnect to.",
+        session_full: "The session is full. Try again once someone leaves.",
+        session_locked: "The host has locked the session to new joiners.",
+        banned: "You have been banned from this session.",
+        invalid_password: "The session password is incorrect.",
+        invalid_session_code: "Invalid session code. Session codes are 8 letters long.",
+        reclaim_confirm: "Stop observing and take control from whoever has it?",
+        disconnect_confirm: "Leave the session?",
+        disconnect_host_confirm: "Close the session? Everyone connected will be disconnected.",
+        controller_relieved: "Took control back from {0} because of their bad connection.",
+        server_out_of_date: "The cloud server is out of date with this version of YourControls. Try again later or connect directly.",
+        session_collision: "Another host is using the same session code. Ask the host for a new code.",
+        connect_cancelled: "Cancelled.",
+        network_thread_crashed: "Networking stopped working because of an internal error. Reconnect to continue, and please report this with your log file.",
+        following: "Following {0}. Controls are not synced while the host leads.",
+        stopped_following: "Stopped following, syncing controls again.",
+        role_full: "Full",
+        role_avionics: "Avionics",
+        role_view_only: "View only",
+        own_role: "The host set your role to {0}.",
+    },
+    fr: {
+        attempt: "Tentative de connexion...",
+        resolving_host: "Résolution de l'adresse de l'hôte...",
+        contacting_rendezvous: "Contact du serveur Cloud...",
+        punching_through: "Connexion à l'hôte...",
+        handshaking: "Établissement de la liaison avec l'hôte...",
+        loading_definitions: "Connecté. Chargement des définitions de l'avion...",
+        syncing_initial_state: "Synchronisation de l'avion avec l'hôte...",
+        connected: "Connecté au serveur.",
+        name_assigned: "Connecté au serveur en tant que {0}.",
+        server_fail: "Le serveur n'a pas pu démarrer. Raison : {0}",
+        client_fail: "Client déconnecté. Raison : {0}",
+        server: "Serveur démarré !",
+        host: "Vous êtes maintenant l'hôte !",
+        diagnostics: "Diagnostics copiés dans le presse-papiers.",
+        config_exported: "Configuration partageable copiée dans le presse-papiers. Votre nom et vos préférences personnelles n'y figurent pas.",
+        config_import_prompt: "Collez la configuration partagée :",
+        config_imported: "Configuration partagée importée et enregistrée.",
+        config_import_failed: "La configuration partagée n'a pas pu être importée : {0}",
+        sim_waiting: "Simulateur fermé. En attente de son redémarrage...",
+        sim_reconnected: "Simulateur reconnecté.",
+        aircraft_switched: "Un autre avion ({0}) a été chargé. La synchronisation est suspendue jusqu'à ce que vous rechargiez l'avion de départ de la session, ou que vous vous reconnectiez avec les bonnes définitions.",
+        aircraft_restored: "De retour dans l'avion de la session, synchronisation reprise.",
+        flight_plan_loaded: "Plan de vol de l'hôte {0} chargé.",
+        session_time: "Connecté depuis {0}",
+        control_unassigned: "{0} est parti en ayant le contrôle. L'avion reste gelé jusqu'à ce que vous appuyiez sur Take Control.",
+        connection_path: "Connecté via {0}",
+        connection_path_direct: "Direct",
+        connection_path_direct_upnp: "Direct (port redirigé avec UPnP)",
+        connection_path_cloud_server: "Serveur Cloud (pair à pair)",
+        connection_path_relayed: "Serveur Cloud (relayé)",
+        sync_rate: "Fréquence de synchronisation : {0} Hz demandés, {1} Hz atteints",
+        local_name: "Vous êtes {0}",
+        local_address: "En écoute sur {0}",
+        sim_not_running: "Impossible de se connecter à SimConnect ! Le simulateur est-il lancé ?",
+        definitions_load_failed: "Erreur lors du chargement des définitions. Consultez le journal pour plus d'informations.",
+        summary: "Vous êtes connecté : {0}.",
+        summary_you_control: "vous avez le contrôle",
+        summary_control: "{0} a le contrôle",
+        summary_no_control: "personne n'a le contrôle",
+        summary_aircraft: "sur {0}",
+        summary_path: "via {0}",
+        summary_players: "Dans la session : {0}.",
+        state_pushed: "L'état complet de votre avion a été envoyé à tous.",
+        config_not_saved: "Vos paramètres n'ont pas pu être enregistrés et seront perdus à la fermeture de YourControls ({0}). Vérifiez que le fichier et son dossier ne sont pas en lecture seule.",
+        definitions_missing: "Le dossier des définitions d'avions est introuvable ou illisible, il devrait se trouver dans {0}. Réinstallez YourControls pour le restaurer.",
+        select_aircraft: "Sélectionnez d'abord une configuration d'avion !",
+        config_suggestions: "Aucune configuration sélectionnée pour {0}. Les plus proches :",
+        config_no_suggestions: "Aucune configuration ne correspond à {0}. Choisissez l'avion le plus proche dans la liste, certaines commandes pourraient ne pas être synchronisées.",
+        no_last_session: "Aucune session précédente à rejoindre.",
+        no_reconnect_session: "Aucune session interrompue à laquelle se reconnecter.",
+        session_full: "La session est pleine. Réessayez quand quelqu'un partira.",
+        session_locked: "L'hôte a verrouillé la session aux nouveaux arrivants.",
+        banned: "Vous avez été banni de cette session.",
+        invalid_password: "Le mot de passe de la session est incorrect.",
+        invalid_session_code: "Code de session invalide. Les codes de session comportent 8 lettres.",
+        reclaim_confirm: "Arrêter d'observer et reprendre le contrôle à celui qui l'a ?",
+        disconnect_confirm: "Quitter la session ?",
+        disconnect_host_confirm: "Fermer la session ? Tous les participants seront déconnectés.",
+        controller_relieved: "Contrôle repris à {0} à cause de sa mauvaise connexion.",
+        server_out_of_date: "Le serveur cloud n'est pas à jour pour cette version de YourControls. Réessayez plus tard ou connectez-vous directement.",
+        session_collision: "Un autre hôte utilise le même code de session. Demandez un nouveau code à l'hôte.",
+        connect_cancelled: "Annulé.",
+        network_thread_crashed: "Le réseau a cessé de fonctionner à cause d'une erreur interne. Reconnectez-vous pour continuer, et signalez-le avec votre fichier journal.",
+        following: "Vous suivez {0}. Les commandes ne sont pas synchronisées tant que l'hôte mène la formation.",
+        stopped_following: "Suivi arrêté, synchronisation des commandes reprise.",
+        role_full: "Complet",
+        role_avionics: "Avionique",
+        role_view_only: "Lecture seule",
+        own_role: "L'hôte a défini votre rôle sur {0}.",
+    },
+};
+
+var language = "en";
+
+// Looks up a message id, passing through text that isn't one (such as error reasons)
+function Translate(key, value) {
+    var text = (strings[language] || {})[key] || strings.en[key] || key;
+    return value === undefined ? text : text.replace("{0}", value);
+}
@@ -22,10 +22,27 @@ ConnectionList.prototype.hideStatusText = function() {
     }
 }
 
-ConnectionList.prototype.add = function(name) {
+ConnectionList.prototype.add = function(name, color) {
     var listItem = document.createElement("li")
     listItem.className = "list-group-item themed"
-    listItem.innerText = name
+
+    var nameText = document.createElement("span")
+    nameText.className = "entry-name"
+    nameText.innerText = name
+    nameText.style.color = color
+
+    var tagText = document.createElement("span")
+    tagText.className = "badge badge-secondary entry-tag"
+    tagText.hidden = true
+
+    var roleText = document.createElement("span")
+    roleText.className = "badge badge-info entry-tag"
+    roleText.hidden = true
+
+    var afkText = document.createElement("span")
+    afkText.className = "badge badge-warning entry-tag"
+    afkText.innerText = "AFK"
+    afkText.hidden = true
 
     var controlButton = document.createElement("button")
     controlButton.className = "btn btn-outline-primary btn-sm entry-button"
@@ -37,14 +54,42 @@ ConnectionList.prototype.add = function(name) {
     observeButton.type = "button"
     observeButton.innerHTML = "Observer"
 
+    var muteButton = document.createElement("button")
+    muteButton.className = "btn btn-outline-warning btn-sm entry-button"
+    muteButton.type = "button"
+    muteButton.innerHTML = "Mute"
+
+    var resendButton = document.createElement("button")
+    resendButton.className = "btn btn-outline-info btn-sm entry-button"
+    resendButton.type = "button"
+    resendButton.innerHTML = "Resend Definitions"
+
+    // Only the host hands out roles
+    var roleSelect = document.createElement("select")
+    roleSelect.className = "form-control form-control-sm entry-button themed"
+    roleSelect.style.width = "auto"
+    for (var role of ["full", "avionics", "view_only"]) {
+        var option = document.createElement("option")
+        option.value = role
+        option.innerText = Translate("role_" + role)
+        roleSelect.appendChild(option)
+    }
+
     var statusText = document.createElement("p")
     statusText.className = "entry-button"
     statusText.innerHTML = "In Control"
     statusText.hidden = true
     // Add as childs
+    listItem.appendChild(nameText)
+    listItem.appendChild(tagText)
+    listItem.appendChild(afkText)
     listItem.appendChild(controlButton)
     listItem.appendChild(observeButton)
     listItem.appendChild(statusText)
+    listItem.appendChild(muteButton)
+    listItem.appendChild(resendButton)
+    listItem.appendChild(roleText)
+    listItem.appendChild(roleSelect)
     this.object.appendChild(listItem)
     // listItem as class
     let listItemObject = new ConnectionListItem(listItem, name)
@@ -69,6 +114,21 @@ ConnectionList.prototype.setObserver = function(name, observing, forceClick) {
     }
 }
 
+ConnectionList.prototype.setTag = function(name, tag) {
+    if (!this.list[name]) {return}
+    this.list[name].setTag(tag)
+}
+
+ConnectionList.prototype.setRole = function(name, role) {
+    if (!this.list[name]) {return}
+    this.list[name].setRole(role)
+}
+
+ConnectionList.prototype.setAfk = function(name, afk) {
+    if (!this.list[name]) {return}
+    this.list[name].afkText.hidden = !afk
+}
+
 ConnectionList.prototype.remove = function(name) {
     if (!this.list[name]) {return}
     if (this.lastInControl == name) {
@@ -88,15 +148,47 @@ ConnectionList.prototype.show = function() {
 
 function ConnectionListItem(htmlObject, name) {
     this.object = htmlObject
-    this.controlButton = htmlObject.children[0]
-    this.observeButton = htmlObject.children[1]
-    this.statusText = htmlObject.children[2]
+    this.nameText = htmlObject.children[0]
+    this.tagText = htmlObject.children[1]
+    this.afkText = htmlObject.children[2]
+    this.controlButton = htmlObject.children[3]
+    this.observeButton = htmlObject.children[4]
+    this.statusText = htmlObject.children[5]
+    this.muteButton = htmlObject.children[6]
+    this.resendButton = htmlObject.children[7]
+    this.roleText = htmlObject.children[8]
+    this.roleSelect = htmlObject.children[9]
     this.name = name
 
     this.is_observer = false
+    this.is_muted = false
+    this.role = "full"
 
     this.controlButton.onclick = this.controlButtonClicked.bind(this)
     this.observeButton.onclick = this.observeButtonClicked.bind(this)
+    this.nameText.ondblclick = this.nameTextClicked.bind(this)
+    this.muteButton.onclick = this.muteButtonClicked.bind(this)
+    this.resendButton.onclick = this.resendButtonClicked.bind(this)
+    this.roleSelect.onchange = this.roleSelectChanged.bind(this)
+}
+
+ConnectionListItem.prototype.nameTextClicked = function() {
+    // Only the host can tag peers
+    if (is_client) {return}
+
+    var tag = window.prompt("Tag for " + this.name, this.tagText.innerText)
+    if (tag === null) {return}
+
+    invoke({
+        type: "setPeerTag",
+        target: this.name,
+        tag: tag.trim() == "" ? null : tag.trim()
+    })
+}
+
+ConnectionListItem.prototype.setTag = function(tag) {
+    this.tagText.innerText = tag || ""
+    this.tagText.hidden = !tag
 }
 
 
@@ -115,6 +207,42 @@ ConnectionListItem.prototype.observeButtonClicked = function() {
     })
 }
 
+ConnectionListItem.prototype.muteButtonClicked = function() {
+    this.is_muted = !this.is_muted
+    this.muteButton.classList.toggle("btn-outline-warning", !this.is_muted)
+    this.muteButton.classList.toggle("btn-warning", this.is_muted)
+    this.muteButton.innerHTML = this.is_muted ? "Unmute" : "Mute"
+
+    invoke({
+        type: "setPeerMuted",
+        is_muted: this.is_muted,
+        target: this.name
+    })
+}
+
+ConnectionListItem.prototype.roleSelectChanged = function() {
+    invoke({
+        type: "setPeerRole",
+        role: this.roleSelect.value,
+        target: this.name
+    })
+}
+
+ConnectionListItem.prototype.setRole = function(role) {
+    this.role = role
+    this.roleSelect.value = role
+    this.roleText.innerText = Translate("role_" + role)
+    this.roleText.hidden = role == "full"
+    this.setButtonsVisibility(has_control)
+}
+
+ConnectionListItem.prototype.resendButtonClicked = function() {
+    invoke({
+        type: "resendDefinitions",
+        target: this.name
+    })
+}
+
 ConnectionListItem.prototype.controlButtonClicked = function() {
     this.controlButton.hidden = true
     this.observeButton.hidden = true
@@ -140,8 +268,12 @@ ConnectionListItem.prototype.setObserver = function(observing) {
 }
 
 ConnectionListItem.prototype.setButtonsVisibility = function(hasControl) {
-    this.controlButton.hidden = this.is_observer || (!hasControl && !this.is_observer)
+    // Restricted roles can't fly
+    this.controlButton.hidden = this.is_observer || this.role != "full" || (!hasControl && !this.is_observer)
     this.observeButton.hidden = is_client || this.controlButton.hidden
+    // Only the host has definitions to send
+    this.resendButton.hidden = is_client
+    this.roleSelect.hidden = is_client
 }
 
 ConnectionListItem.prototype.hideStatus = function() {
@@ -4,6 +4,10 @@ var settings_button = document.getElementById("settings-button");
 var alert = document.getElementById("alert");
 var version_alert_text = document.getElementById("version-alert-text");
 var overloaded_alert = document.getElementById("overloaded-alert");
+var ready_alert = document.getElementById("ready-alert");
+var motd_alert = document.getElementById("motd-alert");
+var upnp_alert = document.getElementById("upnp-alert");
+var write_fail_alert = document.getElementById("write-fail-alert");
 var aircraftList = document.getElementById("aircraft-list");
 
 var nav_bar = document.getElementById("nav");
@@ -21,9 +25,43 @@ var name_input_join = document.getElementById("name-input-join");
 var theme_selector = document.getElementById("theme-select");
 var streamer_mode = document.getElementById("streamer-mode");
 var instructor_mode = document.getElementById("instructor-mode");
+var observers_by_default = document.getElementById("observers-by-default");
+var late_joiners_observe = document.getElementById("late-joiners-observe");
+var rename_duplicate_names = document.getElementById("rename-duplicate-names");
+var confirm_disconnect = document.getElementById("confirm-disconnect");
+var take_back_control_on_leave = document.getElementById("take-back-control-on-leave");
+var observe_when_not_in_control = document.getElementById("observe-when-not-in-control");
+var sync_flight_plan = document.getElementById("sync-flight-plan");
+var protocol_tracing = document.getElementById("protocol-tracing");
+var local_api = document.getElementById("local-api");
+var language_selector = document.getElementById("language-select");
+var rejoin_button = document.getElementById("rejoin-button");
+var can_rejoin = false;
+var reconnect_button = document.getElementById("reconnect-button");
+var cancel_connect_button = document.getElementById("cancel-connect-button");
+var keep_session_on_sim_exit = document.getElementById("keep-session-on-sim-exit");
+var event_driven_sync = document.getElementById("event-driven-sync");
+var disable_interpolation = document.getElementById("disable-interpolation");
+var reliable_only = document.getElementById("reliable-only");
 var sound_muted = document.getElementById("sound-muted");
 
 var timeout_input = document.getElementById("timeout-input");
+var session_timeout_input = document.getElementById("session-timeout-input");
+var afk_timeout_input = document.getElementById("afk-timeout-input");
+var packet_size_input = document.getElementById("packet-size-input");
+var heartbeat_interval_input = document.getElementById("heartbeat-interval-input");
+var controller_loss_input = document.getElementById("controller-loss-input");
+var controller_loss_seconds_input = document.getElementById("controller-loss-seconds-input");
+var simulated_latency_input = document.getElementById("simulated-latency-input");
+var simulated_loss_input = document.getElementById("simulated-loss-input");
+var follow_behind_input = document.getElementById("follow-behind-input");
+var follow_right_input = document.getElementById("follow-right-input");
+var transfer_hotkey_input = document.getElementById("transfer-hotkey-input");
+var update_rate_input = document.getElementById("update-rate-input");
+var keep_alive_input = document.getElementById("keep-alive-input");
+var disabled_tags_input = document.getElementById("disabled-tags-input");
+var motd_input = document.getElementById("motd-input");
+var simconnect_name_input = document.getElementById("simconnect-name-input");
 
 var name_div = document.getElementById("name-div");
 var port_div = document.getElementById("port-div");
@@ -47,6 +85,8 @@ var joinConnectDirect = document.getElementById("join-connect-direct");
 var joinConnectCloud = document.getElementById("join-connect-cloud");
 var joinIpInput = document.getElementById("join-ip-input");
 var joinPortInput = document.getElementById("join-port-input");
+// Set from the app as the direct connect address and port are typed
+var direct_address_valid = false;
 
 // Network
 var downloadBandwidth = document.getElementById("download-bandwidth");
@@ -55,14 +95,20 @@ var uploadBandwidth = document.getElementById("upload-bandwidth");
 var uploadRate = document.getElementById("upload-rate");
 var networkLoss = document.getElementById("network-loss");
 var ping = document.getElementById("network-ping");
+var networkJitter = document.getElementById("network-jitter");
+var networkLate = document.getElementById("network-late");
 
 var forceButton = document.getElementById("force-button");
+var reclaimButton = document.getElementById("reclaim-button");
 var observerButton = document.getElementById("observer-button");
+var followButton = document.getElementById("follow-button");
+var pushStateButton = document.getElementById("push-state-button");
 
 var is_connected = false;
 var is_client = false;
 var on_client = true;
 var has_control = false;
+var is_leading = false;
 
 var cacheIpInput = "";
 var cacheSessionInput = "";
@@ -109,12 +155,17 @@ function FormButtonsDisabled(disabled) {
     connect_button.disabled = disabled;
     server_button.disabled = disabled;
     settings_button.disabled = disabled;
+    rejoin_button.disabled = disabled;
+    reconnect_button.disabled = disabled;
 }
 
 function OnConnected() {
     connect_button.updatetext("danger", "Disconnect");
     server_button.updatetext("danger", "Stop Server");
     observerButton.hidden = false;
+    rejoin_button.hidden = true;
+    reconnect_button.hidden = true;
+    cancel_connect_button.hidden = true;
 
     FormButtonsDisabled(false);
     is_connected = true;
@@ -144,6 +195,7 @@ function OnConnected() {
 
 
 
+    SetLocalName(username.value.trim());
     SetStuffVisible(true);
 }
 
@@ -151,6 +203,7 @@ function OnDisconnect(text) {
     alert.updatetext("danger", text);
     is_connected = false;
     is_client = false;
+    cancel_connect_button.hidden = true;
     FormButtonsDisabled(false);
     port_input_host.disabled = false;
 
@@ -173,10 +226,27 @@ function OnDisconnect(text) {
     joinIpInput.value = cacheIpInput;
     sessionInput.value = cacheSessionInput;
     forceButton.hidden = true;
+    reclaimButton.hidden = true;
 
     observerButton.hidden = true;
+    followButton.hidden = true;
+    pushStateButton.hidden = true;
+    SetLeading(false);
+
+    upnp_alert.hidden = true;
+    write_fail_alert.hidden = true;
+    overloaded_alert.hidden = true;
+    ready_alert.hidden = true;
+    motd_alert.hidden = true;
+    rejoin_button.hidden = !can_rejoin;
+    reconnect_button.hidden = true;
 
     $("#session-id").hide()
+    $("#local-address").prop("hidden", true);
+    $("#session-time").prop("hidden", true);
+    $("#connection-path").prop("hidden", true);
+    $("#sync-rate").prop("hidden", true);
+    $("#local-name").prop("hidden", true);
     $("#external-ipv4").show();
     $("#external-ipv6").show();
     session_code = ""
@@ -185,6 +255,24 @@ function OnDisconnect(text) {
     SetStuffVisible(false);
 }
 
+function FormatDuration(seconds) {
+    var hours = Math.floor(seconds / 3600);
+    var minutes = Math.floor((seconds % 3600) / 60);
+    var pad = function (n) { return n < 10 ? "0" + n : n; };
+
+    return hours + ":" + pad(minutes) + ":" + pad(seconds % 60);
+}
+
+// The name others see us as, which the server may have changed from the one in settings
+function SetLocalName(name) {
+    $("#local-name").text(Translate("local_name", name)).prop("hidden", false);
+}
+
+function SetLeading(leading) {
+    is_leading = leading;
+    followButton.textContent = leading ? "Stop Leading" : "Lead Formation";
+}
+
 function SetSessionCode(code) {
     session_code = code
     if (code == "") {
@@ -215,6 +303,16 @@ function ValidateInt(e) {
     return Validate(e, e.value.match(/\d+/gi));
 }
 
+// Has to outlast the heartbeat interval, otherwise peers are dropped between heartbeats
+function ValidateSessionTimeout(e, heartbeatInput) {
+    return Validate(e, ValidateInt(e) && parseInt(e.value) * 1000 > parseInt(heartbeatInput.value));
+}
+
+// The main loop can't sync more than 100 times a second
+function ValidateUpdateRate(e) {
+    return Validate(e, ValidateInt(e) && parseInt(e.value) > 0 && parseInt(e.value) <= 100);
+}
+
 function ValidateName(e) {
     return Validate(e, e.value.trim() != "");
 }
@@ -245,78 +343,194 @@ function LoadSettings(newSettings) {
     port_input_host.value = newSettings.port;
 
     joinIpInput.value = newSettings.ip;
+    if (joinIpInput.value != "") {
+        ValidateDirectAddress();
+    }
     streamer_mode.checked = newSettings.streamer_mode;
     instructor_mode.checked = newSettings.instructor_mode;
+    observers_by_default.checked = newSettings.observers_by_default;
+    late_joiners_observe.checked = newSettings.late_joiners_observe;
+    rename_duplicate_names.checked = newSettings.rename_duplicate_names;
+    confirm_disconnect.checked = newSettings.confirm_disconnect;
+    take_back_control_on_leave.checked = newSettings.take_back_control_on_leave;
+    observe_when_not_in_control.checked = newSettings.observe_when_not_in_control;
+    sync_flight_plan.checked = newSettings.sync_flight_plan;
+    protocol_tracing.checked = newSettings.protocol_tracing;
+    local_api.checked = newSettings.local_api;
+    keep_session_on_sim_exit.checked = newSettings.keep_session_on_sim_exit;
+    event_driven_sync.checked = newSettings.event_driven_sync;
+    disable_interpolation.checked = newSettings.disable_interpolation;
+    reliable_only.checked = newSettings.reliable_only;
 
     username.value = newSettings.name;
     timeout_input.value = newSettings.conn_timeout;
+    session_timeout_input.value = newSettings.session_timeout;
+    afk_timeout_input.value = newSettings.afk_timeout;
+    packet_size_input.value = newSettings.max_packet_size;
+    heartbeat_interval_input.value = newSettings.heartbeat_interval_ms;
+    controller_loss_input.value = newSettings.max_controller_packet_loss;
+    controller_loss_seconds_input.value = newSettings.controller_loss_seconds;
+    simulated_latency_input.value = newSettings.simulated_latency_ms;
+    simulated_loss_input.value = newSettings.simulated_packet_loss;
+    follow_behind_input.value = newSettings.follow_offset_behind;
+    follow_right_input.value = newSettings.follow_offset_right;
+    transfer_hotkey_input.value = newSettings.transfer_control_hotkey;
+    update_rate_input.value = newSettings.update_rate;
+    keep_alive_input.value = newSettings.keep_alive_interval;
+    disabled_tags_input.value = newSettings.disabled_tags.join(", ");
+    motd_input.value = newSettings.motd;
+    simconnect_name_input.value = newSettings.simconnect_name;
     theme_selector.checked = newSettings.ui_dark_theme;
+    language_selector.value = newSettings.language;
+    language = newSettings.language;
+    can_rejoin = newSettings.last_session != null;
+    rejoin_button.hidden = !can_rejoin || is_connected;
 
     setTheme(newSettings.ui_dark_theme);
 
     settings = newSettings;
 }
 
+function CopyToClipboard(text) {
+    var textArea = document.createElement("textarea");
+    textArea.value = text;
+    document.body.appendChild(textArea);
+    textArea.select();
+    document.execCommand("copy");
+    document.body.removeChild(textArea);
+}
+
 function UpdateMetrics(metrics) {
     downloadBandwidth.textContent =
-        "↓ " + metrics.receiveBandwidth.toFixed(2) + "KB/s";
-    downloadRate.textContent = Math.floor(metrics.receivePackets) + " Packets/s";
+        "↓ " + metrics.receivedKbps.toFixed(2) + "KB/s";
+    downloadRate.textContent = Math.floor(metrics.receivedPackets) + " Packets/s";
     uploadBandwidth.textContent =
-        "↑ " + metrics.sentBandwidth.toFixed(2) + " KB/s";
+        "↑ " + metrics.sentKbps.toFixed(2) + " KB/s";
     uploadRate.textContent = Math.floor(metrics.sentPackets) + " Packets/s";
     networkLoss.textContent =
         (metrics.packetLoss * 100).toFixed(2) + "% Packet loss";
-    ping.textContent = metrics.ping.toFixed(0) + "ms";
+    ping.textContent = (metrics.rttMs / 2).toFixed(0) + "ms";
+    networkJitter.textContent = metrics.jitterMs.toFixed(0) + "ms Jitter";
+    networkLate.textContent = metrics.latePackets + " Late packets";
+}
+
+// One message saying who's there, who has control, what's flown and how we're connected
+function ShowConnectionSummary(summary) {
+    var parts = [];
+
+    if (summary.hasControl) {
+        parts.push(Translate("summary_you_control"));
+    } else if (summary.inControl) {
+        parts.push(Translate("summary_control", summary.inControl));
+    } else {
+        parts.push(Translate("summary_no_control"));
+    }
+
+    if (summary.aircraft) {
+        parts.push(Translate("summary_aircraft", summary.aircraft));
+    }
+
+    if (summary.path) {
+        parts.push(Translate("summary_path", Translate(summary.path)));
+    }
+
+    alert.updatetext(
+        "success",
+        Translate("summary", parts.join(", ")) + " " + Translate("summary_players", summary.players.join(", "))
+    );
 }
 
 // Handle server messages
 function MessageReceived(data) {
     switch (data["type"]) {
         case "attempt":
-            alert.updatetext("warning", "Attempting connection...");
+            alert.updatetext("warning", Translate("attempt"));
+            cancel_connect_button.hidden = false;
+            break;
+        case "connection_stage":
+            alert.updatetext("warning", Translate(data["data"]));
+            break;
+        case "synced":
+            alert.updatetext("success", Translate("connected"));
+            break;
+        case "connection_summary":
+            ShowConnectionSummary(JSON.parse(data["data"]));
             break;
         case "connected":
             is_client = true;
-            alert.updatetext("success", "Connected to server.");
+            can_rejoin = true;
+            alert.updatetext("success", Translate("connected"));
             connect_button.updatetext("danger", "Disconnect");
             $("#not_server_running").append(forceButton);
             OnConnected();
             break;
+        case "name_assigned":
+            alert.updatetext("success", Translate("name_assigned", data["data"]));
+            SetLocalName(data["data"]);
+            break;
         case "server_fail":
-            OnDisconnect("Server failed to start. Reason: " + data["data"]);
+            OnDisconnect(Translate("server_fail", Translate(data["data"])));
+            break;
+        case "motd":
+            $("#motd-alert-text").text(data["data"]);
+            motd_alert.hidden = false;
             break;
         case "client_fail":
-            OnDisconnect("Client disconnected. Reason: " + data["data"]);
+            OnDisconnect(Translate("client_fail", Translate(data["data"])));
+            break;
+        case "can_reconnect":
+            reconnect_button.hidden = false;
             break;
         case "server":
             is_client = false;
-            alert.updatetext("success", "Server started!");
+            alert.updatetext("success", Translate("server"));
             $("#not_user_client").append(forceButton);
+            $("#not_user_client").append(reclaimButton);
+            followButton.hidden = false;
             OnConnected();
             break;
+        case "upnp_fail":
+            $("#upnp-alert-reason").text("Reason: " + data["data"]);
+            upnp_alert.hidden = false;
+            break;
+        case "write_fail":
+            $("#write-fail-alert-vars").text(JSON.parse(data["data"]).join(", "));
+            write_fail_alert.hidden = false;
+            break;
         case "host":
             is_client = false;
             forceButton.hidden = false;
             $("#not_server_running").append(forceButton);
-            alert.updatetext("success", "You are now hosting!");
+            alert.updatetext("success", Translate("host"));
             break;
         case "error":
-            alert.updatetext("danger", data["data"]);
+            alert.updatetext("danger", Translate(data["data"]));
             FormButtonsDisabled(false);
             ResetForm();
             break;
+        case "config_not_saved":
+            alert.updatetext("danger", Translate("config_not_saved", data["data"]));
+            break;
+        case "definitions_missing":
+            alert.updatetext("danger", Translate("definitions_missing", data["data"]));
+            break;
         case "control":
             has_control = true;
             connectionList.update();
             connectionList.hideStatusText();
             rectangle_status.style.backgroundColor = "cyan";
             forceButton.hidden = true;
+            pushStateButton.hidden = false;
             break;
         case "lostcontrol":
             has_control = false;
             connectionList.update();
             rectangle_status.style.backgroundColor = "red";
             forceButton.hidden = false;
+            pushStateButton.hidden = true;
+            break;
+        case "state_pushed":
+            alert.updatetext("success", Translate("state_pushed"));
             break;
         case "overloaded":
             overloaded_alert.hidden = false;
@@ -324,23 +538,70 @@ function MessageReceived(data) {
         case "stable":
             overloaded_alert.hidden = true;
             break;
+        case "all_ready":
+            ready_alert.hidden = false;
+            break;
+        case "not_all_ready":
+            ready_alert.hidden = true;
+            break;
+        case "controller_relieved":
+            alert.updatetext("warning", Translate("controller_relieved", data["data"]));
+            break;
         case "newconnection":
-            connectionList.add(data["data"]);
+            var connection = JSON.parse(data["data"]);
+            connectionList.add(connection.name, connection.color);
             setTheme(settings.ui_dark_theme);
             break;
+        case "set_tag":
+            var peerTag = JSON.parse(data["data"]);
+            connectionList.setTag(peerTag.name, peerTag.tag);
+            break;
+        case "set_role":
+            var peerRole = JSON.parse(data["data"]);
+            if (connectionList.list[peerRole.name]) {
+                connectionList.setRole(peerRole.name, peerRole.role);
+            } else {
+                alert.updatetext("info", Translate("own_role", Translate("role_" + peerRole.role)));
+            }
+            break;
+        case "address_validated":
+            var validation = JSON.parse(data["data"]);
+            direct_address_valid = !validation.address && !validation.port;
+            if (validation.address) {
+                $("#ip-feedback").text(validation.address);
+            }
+            if (validation.port) {
+                $("#port-feedback").text(validation.port);
+            }
+            Validate(joinIpInput, !validation.address);
+            Validate(joinPortInput, !validation.port);
+            break;
         case "lostconnection":
             connectionList.remove(data["data"]);
             break;
+        case "control_unassigned":
+            alert.updatetext("warning", Translate("control_unassigned", data["data"]));
+            break;
         // Observing
         case "observing":
             rectangle_status.style.backgroundColor = "grey";
             forceButton.hidden = true;
             observerButton.hidden = true;
+            // Lets the host get out of a session where whoever had control froze
+            reclaimButton.hidden = is_client;
+            break;
+        // Observing because control was handed off, taking control ends it
+        case "auto_observing":
+            rectangle_status.style.backgroundColor = "grey";
+            forceButton.hidden = false;
+            observerButton.hidden = true;
+            reclaimButton.hidden = true;
             break;
         case "stop_observing":
             rectangle_status.style.backgroundColor = "red";
             forceButton.hidden = false;
             observerButton.hidden = false;
+            reclaimButton.hidden = true;
             break;
         // Other client
         case "set_observing":
@@ -349,6 +610,12 @@ function MessageReceived(data) {
         case "set_not_observing":
             connectionList.setObserver(data["data"], false);
             break;
+        case "set_afk":
+            connectionList.setAfk(data["data"], true);
+            break;
+        case "set_not_afk":
+            connectionList.setAfk(data["data"], false);
+            break;
         // Other client
         case "set_incontrol":
             connectionList.setInControl(data["data"]);
@@ -357,6 +624,12 @@ function MessageReceived(data) {
         case "add_aircraft":
             aircraftList.addAircraft(data["data"]);
             break;
+        case "select_aircraft":
+            aircraftList.value = data["data"];
+            break;
+        case "config_suggestions":
+            ShowConfigSuggestions(JSON.parse(data["data"]));
+            break;
         case "version":
             $("#updateModal").modal();
             version_alert_text.innerHTML = "New Version is available " + data["data"];
@@ -370,9 +643,65 @@ function MessageReceived(data) {
         case "metrics":
             UpdateMetrics(JSON.parse(data["data"]));
             break;
+        case "diagnostics":
+            CopyToClipboard(data["data"]);
+            alert.updatetext("info", Translate("diagnostics"));
+            break;
+        case "config_export":
+            CopyToClipboard(data["data"]);
+            alert.updatetext("info", Translate("config_exported"));
+            break;
+        case "config_imported":
+            LoadSettings(JSON.parse(data["data"]));
+            invoke({
+                type: "updateConfig",
+                new_config: settings,
+            });
+            alert.updatetext("success", Translate("config_imported"));
+            break;
+        case "config_import_failed":
+            alert.updatetext("danger", Translate("config_import_failed", data["data"]));
+            break;
         case "session":
             SetSessionCode(data["data"])
             break;
+        case "sim_waiting":
+            alert.updatetext("warning", Translate("sim_waiting"));
+            break;
+        case "aircraft_switched":
+            alert.updatetext("danger", Translate("aircraft_switched", data["data"]));
+            break;
+        case "flight_plan_loaded":
+            alert.updatetext("info", Translate("flight_plan_loaded", data["data"]));
+            break;
+        case "aircraft_restored":
+            alert.updatetext("success", Translate("aircraft_restored"));
+            break;
+        case "sim_reconnected":
+            alert.updatetext("success", Translate("sim_reconnected"));
+            break;
+        case "sync_rate":
+            var syncRate = JSON.parse(data["data"]);
+            $("#sync-rate").text(Translate("sync_rate", syncRate.requested).replace("{1}", syncRate.achieved)).prop("hidden", false);
+            break;
+        case "connection_path":
+            $("#connection-path").text(Translate("connection_path", Translate(data["data"]))).prop("hidden", false);
+            break;
+        case "session_time":
+            $("#session-time").text(Translate("session_time", FormatDuration(parseInt(data["data"])))).prop("hidden", false);
+            break;
+        case "following":
+            alert.updatetext("info", Translate("following", data["data"]));
+            break;
+        case "stopped_following":
+            alert.updatetext("info", Translate("stopped_following"));
+            break;
+        case "request_disconnect":
+            Disconnect();
+            break;
+        case "local_address":
+            $("#local-address").text(Translate("local_address", data["data"])).prop("hidden", false);
+            break;
     }
 }
 
@@ -465,6 +794,17 @@ joinPortInput.addEventListener("change", function () {
     port_input_host.value = joinPortInput.value;
 });
 
+function ValidateDirectAddress() {
+    invoke({
+        type: "validateAddress",
+        address: joinIpInput.value,
+        port: joinPortInput.value,
+    });
+}
+
+joinIpInput.addEventListener("input", ValidateDirectAddress);
+joinPortInput.addEventListener("input", ValidateDirectAddress);
+
 port_input_host.addEventListener("change", function () {
     joinPortInput.value = port_input_host.value;
 });
@@ -476,6 +816,22 @@ forceButton.addEventListener("click", function () {
     forceButton.hidden = true;
 });
 
+reclaimButton.addEventListener("click", function () {
+    if (!confirm(Translate("reclaim_confirm"))) {
+        return;
+    }
+    invoke({
+        type: "reclaimControl",
+    });
+    reclaimButton.hidden = true;
+});
+
+pushStateButton.addEventListener("click", function () {
+    invoke({
+        type: "pushFullState",
+    });
+});
+
 observerButton.addEventListener("click", function () {
     invoke({
         type: "goObserver",
@@ -483,6 +839,14 @@ observerButton.addEventListener("click", function () {
     observerButton.hidden = true;
 });
 
+followButton.addEventListener("click", function () {
+    SetLeading(!is_leading);
+    invoke({
+        type: "setLeading",
+        is_leading: is_leading,
+    });
+});
+
 $("input[type=radio][name=connectionRadios]").change(function () {
     $("#host-ip-radios").attr("hidden", $("#direct-radio").prop("checked"))
 })
@@ -496,9 +860,66 @@ $("#settings-form").submit(function (e) {
     newSettings.conn_timeout = ValidateInt(timeout_input)
         ? parseInt(timeout_input.value)
         : null;
+    newSettings.session_timeout = ValidateSessionTimeout(session_timeout_input, heartbeat_interval_input)
+        ? parseInt(session_timeout_input.value)
+        : null;
+    newSettings.afk_timeout = ValidateInt(afk_timeout_input)
+        ? parseInt(afk_timeout_input.value)
+        : null;
+    newSettings.max_packet_size = ValidateInt(packet_size_input)
+        ? parseInt(packet_size_input.value)
+        : null;
+    newSettings.heartbeat_interval_ms = ValidateInt(heartbeat_interval_input)
+        ? parseInt(heartbeat_interval_input.value)
+        : null;
+    newSettings.max_controller_packet_loss = ValidateInt(controller_loss_input)
+        ? parseInt(controller_loss_input.value)
+        : null;
+    newSettings.controller_loss_seconds = ValidateInt(controller_loss_seconds_input)
+        ? parseInt(controller_loss_seconds_input.value)
+        : null;
+    newSettings.simulated_latency_ms = ValidateInt(simulated_latency_input)
+        ? parseInt(simulated_latency_input.value)
+        : null;
+    newSettings.simulated_packet_loss = ValidateInt(simulated_loss_input)
+        ? parseInt(simulated_loss_input.value)
+        : null;
+    newSettings.follow_offset_behind = ValidateInt(follow_behind_input)
+        ? parseFloat(follow_behind_input.value)
+        : null;
+    newSettings.follow_offset_right = ValidateInt(follow_right_input)
+        ? parseFloat(follow_right_input.value)
+        : null;
+    newSettings.transfer_control_hotkey = transfer_hotkey_input.value.trim();
+    newSettings.update_rate = ValidateUpdateRate(update_rate_input)
+        ? parseInt(update_rate_input.value)
+        : null;
+    newSettings.keep_alive_interval = ValidateInt(keep_alive_input)
+        ? parseInt(keep_alive_input.value)
+        : null;
+    newSettings.motd = motd_input.value.trim();
+    newSettings.simconnect_name = simconnect_name_input.value.trim();
+    newSettings.disabled_tags = disabled_tags_input.value
+        .split(",")
+        .map(function (tag) { return tag.trim(); })
+        .filter(function (tag) { return tag != ""; });
     newSettings.ui_dark_theme = theme_selector.checked;
+    newSettings.language = language_selector.value;
     newSettings.streamer_mode = streamer_mode.checked;
     newSettings.instructor_mode = instructor_mode.checked;
+    newSettings.observers_by_default = observers_by_default.checked;
+    newSettings.late_joiners_observe = late_joiners_observe.checked;
+    newSettings.rename_duplicate_names = rename_duplicate_names.checked;
+    newSettings.confirm_disconnect = confirm_disconnect.checked;
+    newSettings.take_back_control_on_leave = take_back_control_on_leave.checked;
+    newSettings.observe_when_not_in_control = observe_when_not_in_control.checked;
+    newSettings.sync_flight_plan = sync_flight_plan.checked;
+    newSettings.protocol_tracing = protocol_tracing.checked;
+    newSettings.local_api = local_api.checked;
+    newSettings.keep_session_on_sim_exit = keep_session_on_sim_exit.checked;
+    newSettings.event_driven_sync = event_driven_sync.checked;
+    newSettings.disable_interpolation = disable_interpolation.checked;
+    newSettings.reliable_only = reliable_only.checked;
     newSettings.sound_muted = sound_muted.checked;
 
     for (key in newSettings) {
@@ -515,12 +936,43 @@ $("#settings-form").submit(function (e) {
     });
 });
 
+// Only sent once the user agrees, closing a hosted session drops everyone
+function Disconnect() {
+    if (settings.confirm_disconnect && !confirm(Translate(is_client ? "disconnect_confirm" : "disconnect_host_confirm"))) {
+        return;
+    }
+    invoke({
+        type: "disconnect",
+    });
+}
+
+$("#diagnostics-button").click(function () {
+    invoke({
+        type: "copyDiagnostics",
+    });
+});
+
+$("#export-config-button").click(function () {
+    invoke({
+        type: "exportConfig",
+    });
+});
+
+$("#import-config-button").click(function () {
+    var text = prompt(Translate("config_import_prompt"));
+    if (!text) {
+        return;
+    }
+    invoke({
+        type: "importConfig",
+        text: text.trim(),
+    });
+});
+
 $("#server-button").click(function (e) {
 
     if (is_connected) {
-        invoke({
-            type: "disconnect",
-        });
+        Disconnect();
         return;
     }
 
@@ -552,12 +1004,31 @@ $("#server-button").click(function (e) {
     });
 });
 
+rejoin_button.addEventListener("click", function () {
+    FormButtonsDisabled(true);
+    invoke({
+        type: "rejoinLast",
+    });
+});
+
+reconnect_button.addEventListener("click", function () {
+    FormButtonsDisabled(true);
+    invoke({
+        type: "reconnect",
+    });
+});
+
+$("#cancel-connect-button").click(function () {
+    cancel_connect_button.hidden = true;
+    invoke({
+        type: "cancelConnect",
+    });
+});
+
 $("#connect-button").click(function (e) {
 
     if (is_connected) {
-        invoke({
-            type: "disconnect",
-        });
+        Disconnect();
         return;
     }
 
@@ -587,18 +1058,19 @@ $("#connect-button").click(function (e) {
 
 
     if (joinConnectDirect.checked) {
+        if (!direct_address_valid) {
+            ValidateDirectAddress();
+            return;
+        }
+
         if (ValidateIp(joinIpInput)) {
             data["ip"] = cacheIpInput;
-        } else if (ValidateHostname(sessionInput)) {
+        } else if (ValidateHostname(joinIpInput)) {
             data["hostname"] = cacheIpInput;
         } else {
             return;
         }
 
-        if (!ValidateInt(joinPortInput)) {
-            return;
-        }
-
         data["session_id"] = null; // Joining directly should never include a session_id
         data["port"] = parseInt(joinPortInput.value);
     }
@@ -626,6 +1098,34 @@ function updateFailed() {
 
 version_alert_button.onclick = update;
 
+// Buttons for the configs closest to the aircraft loaded in the sim
+function ShowConfigSuggestions(data) {
+    const list = $("#config-suggestions-list").empty();
+
+    if (data.suggestions.length == 0) {
+        $("#config-suggestions-text").text(Translate("config_no_suggestions", data.aircraft));
+    } else {
+        $("#config-suggestions-text").text(Translate("config_suggestions", data.aircraft));
+    }
+
+    data.suggestions.forEach(function (config) {
+        $("<button type='button' class='btn btn-outline-primary btn-sm mr-1 mt-1'></button>")
+            .text(config.replace(".yaml", ""))
+            .click(function () {
+                aircraftList.value = config;
+                UpdateAircraft(config);
+                $("#config-suggestions").prop("hidden", true);
+            })
+            .appendTo(list);
+    });
+
+    $("#config-suggestions").prop("hidden", false);
+}
+
+aircraftList.addEventListener("change", function () {
+    $("#config-suggestions").prop("hidden", true);
+});
+
 aircraftList.addAircraft = function (aircraftName) {
     const newButton = document.createElement("option");
     newButton.className =